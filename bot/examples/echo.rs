@@ -0,0 +1,27 @@
+//! Repeats whatever follows `!echo` and waves at greetings.
+//!
+//!     cargo run -p chat-bot --example echo -- 127.0.0.1:9999
+
+use std::error::Error;
+
+use chat_bot::{Bot, Regex};
+use chat_client::Config;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let addr = std::env::args()
+        .nth(1)
+        .unwrap_or("127.0.0.1:9999".to_string());
+
+    Bot::new("echo")
+        .command(
+            "echo",
+            |ctx| async move { ctx.reply(&ctx.args).await.map(drop) },
+        )
+        .on(Regex::new(r"(?i)\b(hello|hi|hey)\b")?, |ctx| async move {
+            ctx.react("👋").await
+        })
+        .run(Config::new(addr))
+        .await?;
+    Ok(())
+}
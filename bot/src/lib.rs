@@ -0,0 +1,273 @@
+//! Headless bots on top of `chat_client`.
+//!
+//! A `Bot` logs in as a bot account, runs handlers for messages starting
+//! with a command (`!deploy main`) or matching a pattern, and runs
+//! scheduled tasks:
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), chat_client::ClientError> {
+//! use chat_bot::Bot;
+//! use chat_client::Config;
+//!
+//! Bot::new("echo")
+//!     .command("echo", |ctx| async move { ctx.reply(&ctx.args).await.map(drop) })
+//!     .run(Config::new("127.0.0.1:9999"))
+//!     .await
+//! # }
+//! ```
+
+use std::{collections::HashMap, future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use chat_client::{Client, ClientError, Config, Event};
+use futures::StreamExt;
+use protocol::{ErrorCode, Message, User};
+use tokio::task::JoinHandle;
+
+pub use regex::Regex;
+
+/// Commands are messages starting with this, unless `Bot::prefix` says
+/// otherwise.
+const DEFAULT_PREFIX: &str = "!";
+
+type BoxFuture = Pin<Box<dyn Future<Output = Result<(), ClientError>> + Send>>;
+type Handler = Arc<dyn Fn(Context) -> BoxFuture + Send + Sync>;
+type Scheduled = Arc<dyn Fn(Client) -> BoxFuture + Send + Sync>;
+
+/// The message a handler runs for, and the connection to answer on.
+#[derive(Clone)]
+pub struct Context {
+    pub client: Client,
+    pub message: Message,
+    /// For commands, the text after the command name.
+    pub args: String,
+    /// For patterns, the capture groups, the whole match first. Groups that
+    /// did not participate are empty.
+    pub captures: Vec<String>,
+}
+
+impl Context {
+    /// Posts `body` to the channel the message came from.
+    pub async fn reply(&self, body: &str) -> Result<Message, ClientError> {
+        self.client.send(&self.message.channel, body).await
+    }
+
+    /// Reacts to the message with `emoji`.
+    pub async fn react(&self, emoji: &str) -> Result<(), ClientError> {
+        self.client
+            .react(&self.message.channel, self.message.seq, emoji)
+            .await
+    }
+}
+
+pub struct Bot {
+    user: User,
+    prefix: String,
+    commands: HashMap<String, Handler>,
+    patterns: Vec<(Regex, Handler)>,
+    schedules: Vec<(Duration, Scheduled)>,
+    ignore_bots: bool,
+}
+
+impl Bot {
+    /// A bot logging in as `name`, flagged as a bot so clients can show it
+    /// differently.
+    pub fn new(name: &str) -> Self {
+        Bot {
+            user: User {
+                username: name.to_string(),
+                color: None,
+                avatar: None,
+                bot: true,
+            },
+            prefix: DEFAULT_PREFIX.to_string(),
+            commands: HashMap::new(),
+            patterns: vec![],
+            schedules: vec![],
+            ignore_bots: true,
+        }
+    }
+
+    pub fn with_avatar(mut self, avatar: &str) -> Self {
+        self.user.avatar = Some(avatar.to_string());
+        self
+    }
+
+    pub fn with_color(mut self, color: &str) -> Self {
+        self.user.color = Some(color.to_string());
+        self
+    }
+
+    /// What command messages start with, `!` by default.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    /// Also handle messages of other bots. Off by default so two bots cannot
+    /// keep answering each other.
+    pub fn hear_bots(mut self) -> Self {
+        self.ignore_bots = false;
+        self
+    }
+
+    /// Runs `handler` for messages of the form `<prefix><name> <args>`.
+    pub fn command<F, Fut>(mut self, name: &str, handler: F) -> Self
+    where
+        F: Fn(Context) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), ClientError>> + Send + 'static,
+    {
+        self.commands.insert(name.to_string(), boxed(handler));
+        self
+    }
+
+    /// Runs `handler` for every message matching `pattern`.
+    pub fn on<F, Fut>(mut self, pattern: Regex, handler: F) -> Self
+    where
+        F: Fn(Context) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), ClientError>> + Send + 'static,
+    {
+        self.patterns.push((pattern, boxed(handler)));
+        self
+    }
+
+    /// Runs `task` every `period`, the first time one period after login.
+    pub fn every<F, Fut>(mut self, period: Duration, task: F) -> Self
+    where
+        F: Fn(Client) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), ClientError>> + Send + 'static,
+    {
+        self.schedules
+            .push((period, Arc::new(move |client| Box::pin(task(client)))));
+        self
+    }
+
+    /// Connects and serves until the connection is lost for good, which
+    /// only happens if `config.reconnect` is off or the server turns the bot
+    /// away, e.g. because an administrator kicked it.
+    pub async fn run(self, config: Config) -> Result<(), ClientError> {
+        self.spawn(config)
+            .await?
+            .await
+            .map_err(|_| ClientError::Disconnected)?
+    }
+
+    /// Connects and logs in, then serves in the background.
+    pub async fn spawn(
+        self,
+        config: Config,
+    ) -> Result<JoinHandle<Result<(), ClientError>>, ClientError> {
+        let reconnect = config.reconnect;
+        let client = Client::connect(config).await?;
+        // Subscribe first so nothing sent right after login is missed.
+        let events = client.events();
+        client.login(self.user.clone()).await?;
+        tracing::info!("bot {} logged in", self.user.username);
+
+        Ok(tokio::spawn(async move {
+            let schedules: Vec<_> = self
+                .schedules
+                .iter()
+                .map(|(period, task)| schedule(*period, Arc::clone(task), client.clone()))
+                .collect();
+
+            let mut events = Box::pin(events);
+            // Set by an error turning the bot away, final if the connection
+            // is lost right after.
+            let mut refused = None;
+            let result = loop {
+                let Some(event) = events.next().await else {
+                    break Ok(());
+                };
+                match event {
+                    Event::Message(message) => self.dispatch(&client, message),
+                    Event::Disconnected => {
+                        if let Some(refusal) = refused.take() {
+                            break Err(refusal);
+                        }
+                        if !reconnect {
+                            break Err(ClientError::Disconnected);
+                        }
+                    }
+                    Event::Error { code, reason }
+                        if matches!(code, ErrorCode::Forbidden | ErrorCode::Unauthorized) =>
+                    {
+                        refused = Some(ClientError::Server { code, reason });
+                        continue;
+                    }
+                    _ => {}
+                }
+                refused = None;
+            };
+            for schedule in schedules {
+                schedule.abort();
+            }
+            result
+        }))
+    }
+
+    fn dispatch(&self, client: &Client, message: Message) {
+        let from = &message.from;
+        if from.username == self.user.username || (from.bot && self.ignore_bots) {
+            return;
+        }
+
+        let context = |args: String, captures: Vec<String>| Context {
+            client: client.clone(),
+            message: message.clone(),
+            args,
+            captures,
+        };
+
+        if let Some(command) = message.body.strip_prefix(&self.prefix) {
+            let (name, args) = command
+                .split_once(char::is_whitespace)
+                .unwrap_or((command, ""));
+            if let Some(handler) = self.commands.get(name) {
+                run(name, handler, context(args.trim().to_string(), vec![]));
+            }
+        }
+
+        for (pattern, handler) in &self.patterns {
+            if let Some(found) = pattern.captures(&message.body) {
+                let captures = found
+                    .iter()
+                    .map(|group| group.map_or(String::new(), |group| group.as_str().to_string()))
+                    .collect();
+                run(pattern.as_str(), handler, context(String::new(), captures));
+            }
+        }
+    }
+}
+
+fn boxed<F, Fut>(handler: F) -> Handler
+where
+    F: Fn(Context) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), ClientError>> + Send + 'static,
+{
+    Arc::new(move |context| Box::pin(handler(context)))
+}
+
+/// Handlers run concurrently, a slow one does not hold up the others.
+fn run(name: &str, handler: &Handler, context: Context) {
+    let name = name.to_string();
+    let handler = Arc::clone(handler);
+    tokio::spawn(async move {
+        if let Err(e) = handler(context).await {
+            tracing::warn!("handler {} failed; error = {}", name, e);
+        }
+    });
+}
+
+fn schedule(period: Duration, task: Scheduled, client: Client) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        // The first tick completes right away.
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(e) = task(client.clone()).await {
+                tracing::warn!("scheduled task failed; error = {}", e);
+            }
+        }
+    })
+}
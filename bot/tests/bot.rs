@@ -0,0 +1,208 @@
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use chat_bot::{Bot, Regex};
+use chat_client::{Client, ClientError, Config, Event};
+use futures::{Stream, StreamExt};
+use protocol::{ErrorCode, Message, User};
+use server::{
+    connection::{framed, PeerAddr},
+    Server,
+};
+use tokio::net::TcpListener;
+
+fn user(name: &str) -> User {
+    User {
+        username: name.to_string(),
+        color: None,
+        avatar: None,
+        bot: false,
+    }
+}
+
+async fn start() -> SocketAddr {
+    let server = Server::bind("127.0.0.1:0").await.unwrap();
+    let addr = server.addr;
+    tokio::spawn(server.run());
+    addr
+}
+
+/// Serves `server` on a listener of its own, so the test keeps a handle to
+/// administer it.
+async fn serve(server: Arc<Server>) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (stream, peer) = listener.accept().await.unwrap();
+            let server = Arc::clone(&server);
+            tokio::spawn(async move { server.handle(framed(stream), PeerAddr::Net(peer)).await });
+        }
+    });
+    addr
+}
+
+async fn login(addr: SocketAddr, user: User) -> Client {
+    let client = Client::connect(Config::new(addr.to_string()))
+        .await
+        .unwrap();
+    client.login(user).await.unwrap();
+    client
+}
+
+fn echo() -> Bot {
+    Bot::new("echo").command(
+        "echo",
+        |ctx| async move { ctx.reply(&ctx.args).await.map(drop) },
+    )
+}
+
+/// Waits for the first event `pick` accepts, skipping the others.
+async fn wait_for<T>(
+    events: &mut (impl Stream<Item = Event> + Unpin),
+    mut pick: impl FnMut(Event) -> Option<T>,
+) -> T {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(found) = pick(events.next().await.unwrap()) {
+                return found;
+            }
+        }
+    })
+    .await
+    .expect("event did not arrive")
+}
+
+fn from_bot(event: Event) -> Option<Message> {
+    match event {
+        Event::Message(message) if message.from.bot => Some(message),
+        _ => None,
+    }
+}
+
+#[tokio::test]
+async fn commands_are_answered_by_a_bot_account() {
+    let addr = start().await;
+    echo().spawn(Config::new(addr.to_string())).await.unwrap();
+
+    let alice = login(addr, user("alice")).await;
+    let mut events = Box::pin(alice.events());
+    alice.send("default", "!echo  hello there ").await.unwrap();
+    // Not a command, only a mention of one.
+    alice.send("default", "try !echo").await.unwrap();
+
+    let reply = wait_for(&mut events, from_bot).await;
+    assert_eq!(reply.from.username, "echo");
+    assert_eq!(reply.channel, "default");
+    assert_eq!(reply.body, "hello there");
+}
+
+#[tokio::test]
+async fn patterns_trigger_reactions() {
+    let addr = start().await;
+    Bot::new("greeter")
+        .on(Regex::new(r"(?i)\bhello\b").unwrap(), |ctx| async move {
+            ctx.react("👋").await
+        })
+        .spawn(Config::new(addr.to_string()))
+        .await
+        .unwrap();
+
+    let alice = login(addr, user("alice")).await;
+    let mut events = Box::pin(alice.events());
+    alice.send("default", "nothing to see").await.unwrap();
+    let sent = alice.send("default", "Hello everyone").await.unwrap();
+
+    let (from, seq, emoji) = wait_for(&mut events, |event| match event {
+        Event::Reaction {
+            from, seq, emoji, ..
+        } => Some((from, seq, emoji)),
+        _ => None,
+    })
+    .await;
+    assert_eq!(from.username, "greeter");
+    assert!(from.bot);
+    assert_eq!(seq, sent.seq);
+    assert_eq!(emoji, "👋");
+}
+
+#[tokio::test]
+async fn other_bots_are_ignored() {
+    let addr = start().await;
+    echo().spawn(Config::new(addr.to_string())).await.unwrap();
+
+    let other = login(
+        addr,
+        User {
+            bot: true,
+            ..user("other")
+        },
+    )
+    .await;
+    let alice = login(addr, user("alice")).await;
+    let mut events = Box::pin(alice.events());
+    other.send("default", "!echo from a bot").await.unwrap();
+    alice.send("default", "!echo from a person").await.unwrap();
+
+    let reply = wait_for(&mut events, |event| {
+        from_bot(event).filter(|message| message.from.username == "echo")
+    })
+    .await;
+    assert_eq!(reply.body, "from a person");
+}
+
+#[tokio::test]
+async fn scheduled_tasks_repeat() {
+    let addr = start().await;
+    let runs = Arc::new(AtomicUsize::new(0));
+    let counted = Arc::clone(&runs);
+    Bot::new("ticker")
+        .every(Duration::from_millis(50), move |client| {
+            let run = counted.fetch_add(1, Ordering::SeqCst);
+            async move {
+                client
+                    .send("default", &format!("tick {run}"))
+                    .await
+                    .map(drop)
+            }
+        })
+        .spawn(Config::new(addr.to_string()))
+        .await
+        .unwrap();
+
+    let alice = login(addr, user("alice")).await;
+    let mut events = Box::pin(alice.events());
+    let mut ticks = vec![];
+    while ticks.len() < 3 {
+        ticks.push(wait_for(&mut events, from_bot).await.body);
+    }
+    assert!(runs.load(Ordering::SeqCst) >= 3);
+    assert!(ticks.iter().all(|tick| tick.starts_with("tick ")));
+}
+
+#[tokio::test]
+async fn kicked_bots_stop() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let addr = serve(Arc::clone(&server)).await;
+    let running = echo().spawn(Config::new(addr.to_string())).await.unwrap();
+
+    assert!(server.sessions.kick(server.sessions.list()[0].id));
+    let stopped = tokio::time::timeout(Duration::from_secs(5), running)
+        .await
+        .expect("the bot kept running")
+        .unwrap();
+    assert!(matches!(
+        stopped,
+        Err(ClientError::Server {
+            code: ErrorCode::Forbidden,
+            ..
+        })
+    ));
+    assert!(server.sessions.list().is_empty());
+}
@@ -0,0 +1,580 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use futures::{SinkExt, Stream, StreamExt};
+use protocol::{Channel, ChatCodec, ConnectionError, ErrorCode, Frame, Message, RequestId, User};
+use tokio::{
+    sync::{broadcast, mpsc, oneshot},
+    time::Instant,
+};
+use tokio_util::codec::Framed;
+
+use crate::{
+    transport::{self, Transport},
+    ClientError, Config,
+};
+
+/// Number of heartbeat intervals without any frame after which the server is
+/// considered dead.
+const MISSED_HEARTBEATS: u32 = 3;
+/// Events kept for subscribers that fall behind.
+const EVENT_BUFFER: usize = 1024;
+/// How long the server may take to accept the login after reconnecting.
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+type Chat = Framed<Box<dyn Transport>, ChatCodec>;
+type Reply<T> = oneshot::Sender<Result<T, ClientError>>;
+
+/// Narrows down `Client::search`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchFilter {
+    /// Only this channel instead of every channel the user is in.
+    pub channel: Option<String>,
+    /// Only messages sent by this username.
+    pub from: Option<String>,
+    pub before: Option<DateTime<Utc>>,
+    pub after: Option<DateTime<Utc>>,
+}
+
+/// One page of `Client::search` results.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchPage {
+    /// Newest first.
+    pub messages: Vec<Message>,
+    /// The `cursor` of the next page, `None` on the last one.
+    pub next: Option<u64>,
+}
+
+/// Something the server pushed, or a change of the connection.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Message(Message),
+    /// The channel list, sent on login and whenever a channel is created.
+    Channels(Vec<Channel>),
+    /// A user disconnected.
+    Left(User),
+    Reaction {
+        from: User,
+        channel: String,
+        seq: u64,
+        emoji: String,
+    },
+    /// The server deleted these messages of `channel`, e.g. because they
+    /// outlived its retention.
+    Deleted {
+        channel: String,
+        seqs: Vec<u64>,
+    },
+    ServerShutdown {
+        reason: String,
+        reconnect_after: Option<Duration>,
+    },
+    /// The connection was lost, requests in flight failed with
+    /// `ClientError::Disconnected`. Unless `Config::reconnect` is off,
+    /// `Reconnected` follows once the server is back. Right after an `Error`
+    /// with `ErrorCode::Forbidden` or `ErrorCode::Unauthorized` it is final:
+    /// the server turned the user away, e.g. kicked or banned them, and
+    /// logging in again would not help.
+    Disconnected,
+    /// The connection is back. If the client had logged in, it logged in
+    /// again and these are the channels the server sent.
+    Reconnected(Vec<Channel>),
+    Error {
+        code: ErrorCode,
+        reason: String,
+    },
+}
+
+enum Command {
+    Login {
+        user: User,
+        reply: Reply<Vec<Channel>>,
+    },
+    Request {
+        frame: Frame,
+        reply: Reply<Option<Frame>>,
+    },
+}
+
+impl Command {
+    fn fail(self, error: ClientError) {
+        match self {
+            Command::Login { reply, .. } => {
+                let _ = reply.send(Err(error));
+            }
+            Command::Request { reply, .. } => {
+                let _ = reply.send(Err(error));
+            }
+        }
+    }
+}
+
+/// Handle to a connection, cheap to clone. The connection is closed once
+/// every clone was dropped.
+#[derive(Clone)]
+pub struct Client {
+    commands: mpsc::UnboundedSender<Command>,
+    events: broadcast::Sender<Event>,
+    user: Arc<Mutex<Option<User>>>,
+}
+
+impl Client {
+    /// Connects to the server, failing if it cannot be reached. Later
+    /// connection losses are handled in the background, see `Event`.
+    pub async fn connect(config: Config) -> Result<Client, ClientError> {
+        let stream = transport::connect(&config).await?;
+        let chat = Framed::new(stream, config.codec());
+
+        let (commands, rx) = mpsc::unbounded_channel();
+        let (events, _) = broadcast::channel(EVENT_BUFFER);
+        let task = Task {
+            config,
+            commands: rx,
+            events: events.clone(),
+            user: None,
+            next_id: 1,
+        };
+        tokio::spawn(task.run(chat));
+
+        Ok(Client {
+            commands,
+            events,
+            user: Arc::default(),
+        })
+    }
+
+    /// Logs in as `user`, returning the channels the server sent.
+    pub async fn login(&self, user: User) -> Result<Vec<Channel>, ClientError> {
+        let (reply, answer) = oneshot::channel();
+        self.command(Command::Login {
+            user: user.clone(),
+            reply,
+        })?;
+        let channels = answer.await.map_err(|_| ClientError::Disconnected)??;
+        *self.user.lock().unwrap() = Some(user);
+        Ok(channels)
+    }
+
+    /// The user logged in as.
+    pub fn user(&self) -> Option<User> {
+        self.user.lock().unwrap().clone()
+    }
+
+    /// Posts `body` to `channel`, returning the message as the server
+    /// stored it.
+    pub async fn send(&self, channel: &str, body: &str) -> Result<Message, ClientError> {
+        let user = self.user().ok_or(ClientError::NotLoggedIn)?;
+        self.post(Message::new(user, channel.to_string(), body.to_string()))
+            .await
+    }
+
+    /// Posts `body` to `channel` to be deleted for everyone `ttl` after the
+    /// server accepted it, see `Message::expires`.
+    pub async fn send_ephemeral(
+        &self,
+        channel: &str,
+        body: &str,
+        ttl: Duration,
+    ) -> Result<Message, ClientError> {
+        let user = self.user().ok_or(ClientError::NotLoggedIn)?;
+        let mut message = Message::new(user, channel.to_string(), body.to_string());
+        message.ttl = Some(ttl);
+        self.post(message).await
+    }
+
+    async fn post(&self, message: Message) -> Result<Message, ClientError> {
+        match self.request(Frame::Message(message)).await? {
+            Some(Frame::Message(message)) => Ok(message),
+            _ => Err(ClientError::Unexpected),
+        }
+    }
+
+    /// Creates a channel, returning the new channel list.
+    pub async fn create_channel(
+        &self,
+        name: &str,
+        cover: Option<String>,
+    ) -> Result<Vec<Channel>, ClientError> {
+        let channel = Channel {
+            name: name.to_string(),
+            messages: vec![],
+            cover,
+        };
+        match self.request(Frame::Channel(channel)).await? {
+            Some(Frame::Bulk(_, channels)) => Ok(channels),
+            _ => Err(ClientError::Unexpected),
+        }
+    }
+
+    pub async fn join(&self, channel: &str) -> Result<Channel, ClientError> {
+        match self.request(Frame::Join(channel.to_string())).await? {
+            Some(Frame::Joined(channel)) => Ok(channel),
+            _ => Err(ClientError::Unexpected),
+        }
+    }
+
+    pub async fn leave(&self, channel: &str) -> Result<(), ClientError> {
+        self.request(Frame::Leave(channel.to_string())).await?;
+        Ok(())
+    }
+
+    /// Reacts with `emoji` to the message numbered `seq` in `channel`.
+    pub async fn react(&self, channel: &str, seq: u64, emoji: &str) -> Result<(), ClientError> {
+        let from = self.user().ok_or(ClientError::NotLoggedIn)?;
+        let frame = Frame::React {
+            from,
+            channel: channel.to_string(),
+            seq,
+            emoji: emoji.to_string(),
+        };
+        self.request(frame).await?;
+        Ok(())
+    }
+
+    /// Up to `limit` messages of `channel` older than the one numbered
+    /// `before`, or the latest ones, oldest first.
+    pub async fn history(
+        &self,
+        channel: &str,
+        before: Option<u64>,
+        limit: u32,
+    ) -> Result<Vec<Message>, ClientError> {
+        let frame = Frame::History {
+            channel: channel.to_string(),
+            before,
+            limit,
+        };
+        match self.request(frame).await? {
+            Some(Frame::HistoryPage { messages, .. }) => Ok(messages),
+            _ => Err(ClientError::Unexpected),
+        }
+    }
+
+    /// Up to `limit` messages containing every word of `query`, newest first,
+    /// continuing below `cursor` when given.
+    pub async fn search(
+        &self,
+        query: &str,
+        filter: SearchFilter,
+        cursor: Option<u64>,
+        limit: u32,
+    ) -> Result<SearchPage, ClientError> {
+        let frame = Frame::Search {
+            query: query.to_string(),
+            channel: filter.channel,
+            from: filter.from,
+            before: filter.before,
+            after: filter.after,
+            cursor,
+            limit,
+        };
+        match self.request(frame).await? {
+            Some(Frame::SearchResults { messages, next, .. }) => Ok(SearchPage { messages, next }),
+            _ => Err(ClientError::Unexpected),
+        }
+    }
+
+    /// Everything that happens from now on. A subscriber that falls more
+    /// than `EVENT_BUFFER` events behind misses the oldest ones.
+    pub fn events(&self) -> impl Stream<Item = Event> {
+        futures::stream::unfold(self.events.subscribe(), |mut events| async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => return Some((event, events)),
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        tracing::warn!("event subscriber missed {} events", missed);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+
+    async fn request(&self, frame: Frame) -> Result<Option<Frame>, ClientError> {
+        let (reply, answer) = oneshot::channel();
+        self.command(Command::Request { frame, reply })?;
+        answer.await.map_err(|_| ClientError::Disconnected)?
+    }
+
+    fn command(&self, command: Command) -> Result<(), ClientError> {
+        self.commands
+            .send(command)
+            .map_err(|_| ClientError::Disconnected)
+    }
+}
+
+/// Why a session ended.
+enum Ended {
+    /// Every `Client` was dropped.
+    Closed,
+    /// The connection is gone; the server may have said when to come back.
+    Lost { retry_after: Option<Duration> },
+    /// The server turned the user away and closed the connection.
+    Refused,
+}
+
+/// Owns the connection on behalf of every `Client` clone.
+struct Task {
+    config: Config,
+    commands: mpsc::UnboundedReceiver<Command>,
+    events: broadcast::Sender<Event>,
+    /// Logged in as, logged in again after reconnecting.
+    user: Option<User>,
+    next_id: RequestId,
+}
+
+impl Task {
+    async fn run(mut self, mut chat: Chat) {
+        loop {
+            let retry_after = match self.session(chat).await {
+                Ended::Closed => return,
+                Ended::Refused => {
+                    self.emit(Event::Disconnected);
+                    return;
+                }
+                Ended::Lost { retry_after } => retry_after,
+            };
+            self.emit(Event::Disconnected);
+            if !self.config.reconnect {
+                return;
+            }
+            chat = match self.reconnect(retry_after).await {
+                Some(chat) => chat,
+                None => return,
+            };
+        }
+    }
+
+    fn emit(&self, event: Event) {
+        // Nobody listening is fine.
+        let _ = self.events.send(event);
+    }
+
+    async fn session(&mut self, mut chat: Chat) -> Ended {
+        let mut login: Option<(User, Reply<Vec<Channel>>)> = None;
+        let mut pending: HashMap<RequestId, (Frame, Reply<Option<Frame>>)> = HashMap::new();
+        // Frames answering requests, handed out on the following `Ack`.
+        let mut answers: Vec<Frame> = vec![];
+        let mut server_timeout = self.config.server_timeout;
+        let mut deadline = Instant::now() + server_timeout;
+        // Whether the last frame turned the user away. If the connection
+        // ends right after, there is no point in coming back.
+        let mut refused = false;
+
+        let ended = loop {
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => {
+                    tracing::info!("server is not responding");
+                    break Ended::Lost { retry_after: None };
+                }
+                command = self.commands.recv() => {
+                    let sent = match command {
+                        None => break Ended::Closed,
+                        Some(Command::Login { user, reply }) => {
+                            let sent = chat.send(Frame::Authorize(user.clone())).await;
+                            login = Some((user, reply));
+                            sent
+                        }
+                        Some(command @ Command::Request { .. }) if self.user.is_none() => {
+                            command.fail(ClientError::NotLoggedIn);
+                            continue;
+                        }
+                        Some(Command::Request { frame, reply }) => {
+                            let id = self.next_id;
+                            self.next_id += 1;
+                            let sent = chat.send(Frame::request(id, frame.clone())).await;
+                            pending.insert(id, (frame, reply));
+                            sent
+                        }
+                    };
+                    if let Err(e) = sent {
+                        tracing::info!("sending failed; error = {:?}", e);
+                        break Ended::Lost { retry_after: None };
+                    }
+                }
+                frame = chat.next() => {
+                    let frame = match frame {
+                        Some(Ok(frame)) => frame,
+                        Some(Err(e)) => {
+                            tracing::info!("connection error; error = {:?}", e);
+                            break Ended::Lost { retry_after: None };
+                        }
+                        None => {
+                            tracing::info!("server closed the connection");
+                            break Ended::Lost { retry_after: None };
+                        }
+                    };
+                    deadline = Instant::now() + server_timeout;
+                    // A refused login can be retried with another user.
+                    refused = login.is_none()
+                        && matches!(&frame, Frame::Error { code, .. } if turned_away(*code));
+
+                    match frame {
+                        Frame::Ping { id, interval } => {
+                            server_timeout = interval * MISSED_HEARTBEATS;
+                            deadline = Instant::now() + server_timeout;
+                            if chat.send(Frame::Pong(id)).await.is_err() {
+                                break Ended::Lost { retry_after: None };
+                            }
+                        }
+                        Frame::Bulk(_, channels) => {
+                            if let Some((user, reply)) = login.take() {
+                                self.user = Some(user);
+                                let _ = reply.send(Ok(channels.clone()));
+                            } else if !pending.is_empty() {
+                                answers.push(Frame::Bulk(vec![], channels.clone()));
+                            }
+                            self.emit(Event::Channels(channels));
+                        }
+                        Frame::Message(message) => {
+                            let own = self
+                                .user
+                                .as_ref()
+                                .is_some_and(|user| user.username == message.from.username);
+                            if own && !pending.is_empty() {
+                                answers.push(Frame::Message(message.clone()));
+                            }
+                            self.emit(Event::Message(message));
+                        }
+                        frame @ (Frame::Joined(_)
+                        | Frame::HistoryPage { .. }
+                        | Frame::SearchResults { .. }) => {
+                            answers.push(frame);
+                        }
+                        Frame::Ack(id) => {
+                            if let Some((request, reply)) = pending.remove(&id) {
+                                let _ = reply.send(Ok(answer(&request, &mut answers)));
+                            }
+                            answers.clear();
+                        }
+                        Frame::Rejected { id, code, reason } => {
+                            if let Some((_, reply)) = pending.remove(&id) {
+                                let _ = reply.send(Err(ClientError::Rejected { code, reason }));
+                            }
+                            answers.clear();
+                        }
+                        Frame::Disconnect(user) => self.emit(Event::Left(user)),
+                        Frame::React { from, channel, seq, emoji } => {
+                            self.emit(Event::Reaction { from, channel, seq, emoji });
+                        }
+                        Frame::Deleted { channel, seqs } => self.emit(Event::Deleted { channel, seqs }),
+                        Frame::ServerShutdown { reason, reconnect_after } => {
+                            self.emit(Event::ServerShutdown { reason, reconnect_after });
+                            break Ended::Lost { retry_after: reconnect_after };
+                        }
+                        Frame::Error { code, reason } => match login.take() {
+                            Some((_, reply)) => {
+                                let _ = reply.send(Err(ClientError::Server { code, reason }));
+                            }
+                            None => self.emit(Event::Error { code, reason }),
+                        },
+                        frame => tracing::debug!("ignoring {:?}", frame),
+                    }
+                }
+            }
+        };
+
+        let ended = match ended {
+            Ended::Lost { .. } if refused => Ended::Refused,
+            ended => ended,
+        };
+
+        // Whatever was in flight is lost with the connection.
+        if let Some((_, reply)) = login {
+            let _ = reply.send(Err(ClientError::Disconnected));
+        }
+        for (_, (_, reply)) in pending {
+            let _ = reply.send(Err(ClientError::Disconnected));
+        }
+        ended
+    }
+
+    /// Connects again after `retry_after`, or with exponential backoff,
+    /// failing commands sent in the meantime. Returns `None` once every
+    /// `Client` was dropped or the server refused to log the user in again.
+    async fn reconnect(&mut self, retry_after: Option<Duration>) -> Option<Chat> {
+        let (min, max) = (self.config.min_backoff, self.config.max_backoff);
+        let mut backoff = min;
+        let mut delay = retry_after.map_or(min, |after| after.clamp(min, max));
+
+        loop {
+            let wake = Instant::now() + delay;
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep_until(wake) => break,
+                    command = self.commands.recv() => match command {
+                        Some(command) => command.fail(ClientError::Disconnected),
+                        None => return None,
+                    },
+                }
+            }
+
+            match self.login_again().await {
+                Ok((chat, channels)) => {
+                    tracing::info!("reconnected to {}", self.config.addr);
+                    self.emit(Event::Reconnected(channels));
+                    return Some(chat);
+                }
+                Err(ClientError::Server { code, reason }) if turned_away(code) => {
+                    tracing::info!("logging in again was refused; reason = {}", reason);
+                    self.emit(Event::Error { code, reason });
+                    self.emit(Event::Disconnected);
+                    return None;
+                }
+                Err(e) => tracing::info!("reconnecting failed; error = {}", e),
+            }
+            backoff = (backoff * 2).min(max);
+            delay = backoff;
+        }
+    }
+
+    /// Opens a new connection and logs in as before, if the client had.
+    async fn login_again(&self) -> Result<(Chat, Vec<Channel>), ClientError> {
+        let stream = transport::connect(&self.config).await?;
+        let mut chat = Framed::new(stream, self.config.codec());
+        let Some(user) = &self.user else {
+            return Ok((chat, vec![]));
+        };
+
+        chat.send(Frame::Authorize(user.clone()))
+            .await
+            .map_err(ConnectionError::from)?;
+        loop {
+            match tokio::time::timeout(LOGIN_TIMEOUT, chat.next()).await {
+                Ok(Some(Ok(Frame::Bulk(_, channels)))) => return Ok((chat, channels)),
+                Ok(Some(Ok(Frame::Error { code, reason }))) => {
+                    return Err(ClientError::Server { code, reason })
+                }
+                Ok(Some(Ok(_))) => {}
+                Ok(Some(Err(e))) => return Err(ConnectionError::from(e).into()),
+                Ok(None) | Err(_) => return Err(ClientError::Disconnected),
+            }
+        }
+    }
+}
+
+/// Whether an error with `code` means the server will not have the user,
+/// as opposed to refusing a single frame.
+fn turned_away(code: ErrorCode) -> bool {
+    matches!(code, ErrorCode::Forbidden | ErrorCode::Unauthorized)
+}
+
+/// Picks the answer to `request` among the frames received since the
+/// previous `Ack`; the server sends it right before acknowledging.
+fn answer(request: &Frame, answers: &mut Vec<Frame>) -> Option<Frame> {
+    let position = answers.iter().rposition(|answer| {
+        matches!(
+            (request, answer),
+            (Frame::Message(_), Frame::Message(_))
+                | (Frame::Channel(_), Frame::Bulk(..))
+                | (Frame::Join(_), Frame::Joined(_))
+                | (Frame::History { .. }, Frame::HistoryPage { .. })
+                | (Frame::Search { .. }, Frame::SearchResults { .. })
+        )
+    })?;
+    Some(answers.swap_remove(position))
+}
@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use protocol::{tls::Trust, ChatCodec, Compression, Encoding};
+
+/// Used until the server announces its heartbeat interval.
+const SERVER_TIMEOUT: Duration = Duration::from_secs(60);
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Where and how to connect, see `Client::connect`.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub addr: String,
+    /// Connect over TLS, trusting the server certificate this way.
+    pub trust: Option<Trust>,
+    /// Name the server certificate is checked against.
+    pub server_name: String,
+    /// Connect over QUIC instead of TCP, requires `trust`.
+    pub quic: bool,
+    /// Encoding to negotiate, the server default (bincode) if `None`.
+    pub encoding: Option<Encoding>,
+    pub compression: Option<Compression>,
+    /// Reconnect when the connection is lost, logging in again as the same
+    /// user.
+    pub reconnect: bool,
+    /// First delay before reconnecting, doubled after every failed attempt
+    /// up to `max_backoff`. A delay the server asks for on shutdown is kept
+    /// within these bounds too.
+    pub min_backoff: Duration,
+    pub max_backoff: Duration,
+    /// How long the server may stay silent before the connection is
+    /// considered lost, until it announces its heartbeat interval.
+    pub server_timeout: Duration,
+}
+
+impl Config {
+    pub fn new(addr: impl Into<String>) -> Self {
+        Config {
+            addr: addr.into(),
+            trust: None,
+            server_name: "localhost".to_string(),
+            quic: false,
+            encoding: None,
+            compression: None,
+            reconnect: true,
+            min_backoff: MIN_BACKOFF,
+            max_backoff: MAX_BACKOFF,
+            server_timeout: SERVER_TIMEOUT,
+        }
+    }
+
+    pub(crate) fn codec(&self) -> ChatCodec {
+        let codec = match self.encoding {
+            Some(encoding) => ChatCodec::with_encoding(encoding),
+            None => ChatCodec::new(),
+        };
+        match self.compression {
+            Some(compression) => codec.with_compression(compression),
+            None => codec,
+        }
+    }
+}
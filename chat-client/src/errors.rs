@@ -0,0 +1,21 @@
+use protocol::{ConnectionError, ErrorCode};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error("unable to connect")]
+    Connection(#[from] ConnectionError),
+    /// The server refused the login with a `Frame::Error`.
+    #[error("server error: {code}: {reason}")]
+    Server { code: ErrorCode, reason: String },
+    /// The server answered the request with `Frame::Rejected`.
+    #[error("rejected: {code}: {reason}")]
+    Rejected { code: ErrorCode, reason: String },
+    #[error("not logged in")]
+    NotLoggedIn,
+    /// The connection was lost before the server answered.
+    #[error("disconnected from the server")]
+    Disconnected,
+    #[error("unexpected answer from the server")]
+    Unexpected,
+}
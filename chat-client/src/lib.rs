@@ -0,0 +1,15 @@
+//! Async client for the chat server.
+//!
+//! `Client::connect` opens the connection and keeps it alive in a background
+//! task: heartbeats are answered, requests are matched with the server's
+//! answers and a lost connection is reestablished with backoff. Everything
+//! the server pushes is available from `Client::events`.
+
+pub mod client;
+pub mod config;
+pub mod errors;
+pub mod transport;
+
+pub use client::{Client, Event, SearchFilter, SearchPage};
+pub use config::Config;
+pub use errors::ClientError;
@@ -0,0 +1,37 @@
+use protocol::{quic, tls, ConnectionError};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+
+use crate::Config;
+
+/// Byte stream the chat runs over, whichever transport was picked.
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Transport for T {}
+
+/// Opens a TCP stream to `config.addr`, wrapped in TLS if the server
+/// certificate is to be verified, or a QUIC stream if asked to.
+pub async fn connect(config: &Config) -> Result<Box<dyn Transport>, ConnectionError> {
+    match &config.trust {
+        Some(trust) if config.quic => {
+            let addr = tokio::net::lookup_host(&config.addr)
+                .await?
+                .next()
+                .ok_or(ConnectionError::Quic("address did not resolve".to_string()))?;
+            Ok(Box::new(
+                quic::connect(addr, &config.server_name, trust).await?,
+            ))
+        }
+        Some(trust) => {
+            let tls = tls::client_config(trust)?;
+            Ok(Box::new(
+                tls::connect(&config.addr, &config.server_name, tls).await?,
+            ))
+        }
+        None if config.quic => Err(ConnectionError::Quic(
+            "quic requires a trusted certificate".to_string(),
+        )),
+        None => Ok(Box::new(TcpStream::connect(&config.addr).await?)),
+    }
+}
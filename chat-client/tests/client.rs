@@ -0,0 +1,399 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use chat_client::{Client, ClientError, Config, Event, SearchFilter};
+use futures::{SinkExt, Stream, StreamExt};
+use protocol::{ChatCodec, ErrorCode, Frame, User};
+use server::{
+    connection::{framed, PeerAddr},
+    Server,
+};
+use tokio::net::TcpListener;
+use tokio_util::{codec::Framed, sync::CancellationToken};
+
+fn user(name: &str) -> User {
+    User {
+        username: name.to_string(),
+        color: None,
+        avatar: None,
+        bot: false,
+    }
+}
+
+async fn start(addr: &str) -> (SocketAddr, CancellationToken) {
+    let server = Server::bind(addr).await.unwrap();
+    let addr = server.addr;
+    let shutdown = server.shutdown.clone();
+    tokio::spawn(server.run());
+    (addr, shutdown)
+}
+
+/// Serves `server` on a listener of its own, so the test keeps a handle to
+/// administer it.
+async fn serve(server: Arc<Server>) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (stream, peer) = listener.accept().await.unwrap();
+            let server = Arc::clone(&server);
+            tokio::spawn(async move { server.handle(framed(stream), PeerAddr::Net(peer)).await });
+        }
+    });
+    addr
+}
+
+fn config(addr: SocketAddr) -> Config {
+    let mut config = Config::new(addr.to_string());
+    config.min_backoff = Duration::from_millis(50);
+    config.max_backoff = Duration::from_millis(200);
+    config
+}
+
+async fn login(addr: SocketAddr, name: &str) -> Client {
+    let client = Client::connect(config(addr)).await.unwrap();
+    let channels = client.login(user(name)).await.unwrap();
+    assert!(channels.iter().any(|channel| channel.name == "default"));
+    client
+}
+
+async fn next_event(events: &mut (impl Stream<Item = Event> + Unpin)) -> Event {
+    tokio::time::timeout(Duration::from_secs(5), events.next())
+        .await
+        .expect("event did not arrive")
+        .unwrap()
+}
+
+/// Waits for the first event `pick` accepts, skipping the others.
+async fn wait_for<T>(
+    events: &mut (impl Stream<Item = Event> + Unpin),
+    mut pick: impl FnMut(Event) -> Option<T>,
+) -> T {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(found) = pick(events.next().await.unwrap()) {
+                return found;
+            }
+        }
+    })
+    .await
+    .expect("event did not arrive")
+}
+
+#[tokio::test]
+async fn messages_reach_other_clients() {
+    let (addr, _) = start("127.0.0.1:0").await;
+    let alice = login(addr, "alice").await;
+    let bob = login(addr, "bob").await;
+    let mut events = Box::pin(bob.events());
+
+    let sent = alice.send("default", "hi bob").await.unwrap();
+    assert!(sent.seq > 0);
+    assert_eq!(sent.body, "hi bob");
+
+    let received = wait_for(&mut events, |event| match event {
+        Event::Message(message) => Some(message),
+        _ => None,
+    })
+    .await;
+    assert_eq!(received, sent);
+}
+
+#[tokio::test]
+async fn refusals_carry_the_error_code() {
+    let (addr, _) = start("127.0.0.1:0").await;
+    let client = Client::connect(config(addr)).await.unwrap();
+    assert!(matches!(
+        client.send("default", "hi").await,
+        Err(ClientError::NotLoggedIn)
+    ));
+
+    client.login(user("alice")).await.unwrap();
+    match client.send("nowhere", "hi").await {
+        Err(ClientError::Rejected { code, .. }) => assert_eq!(code, ErrorCode::NotFound),
+        other => panic!("expected a rejection, got {other:?}"),
+    }
+    // The connection stays usable.
+    client.send("default", "hi").await.unwrap();
+}
+
+#[tokio::test]
+async fn channels_can_be_created_left_and_joined() {
+    let (addr, _) = start("127.0.0.1:0").await;
+    let client = login(addr, "alice").await;
+
+    let channels = client.create_channel("rust", None).await.unwrap();
+    assert!(channels.iter().any(|channel| channel.name == "rust"));
+    let sent = client.send("rust", "hello").await.unwrap();
+
+    client.leave("rust").await.unwrap();
+    assert!(matches!(
+        client.send("rust", "hello?").await,
+        Err(ClientError::Rejected {
+            code: ErrorCode::Forbidden,
+            ..
+        })
+    ));
+
+    let channel = client.join("rust").await.unwrap();
+    assert_eq!(channel.messages, vec![sent]);
+}
+
+#[tokio::test]
+async fn history_is_paged() {
+    let (addr, _) = start("127.0.0.1:0").await;
+    let client = login(addr, "alice").await;
+
+    let mut sent = vec![];
+    for i in 0..5 {
+        sent.push(client.send("default", &i.to_string()).await.unwrap());
+    }
+
+    let latest = client.history("default", None, 3).await.unwrap();
+    assert_eq!(latest, sent[2..]);
+    let older = client
+        .history("default", Some(latest[0].seq), 3)
+        .await
+        .unwrap();
+    assert_eq!(older, sent[..2]);
+}
+
+#[tokio::test]
+async fn messages_can_be_searched() {
+    let (addr, _) = start("127.0.0.1:0").await;
+    let client = login(addr, "alice").await;
+    let green = client.send("default", "build is green").await.unwrap();
+    client.send("default", "coffee?").await.unwrap();
+    let red = client.send("another", "build is red").await.unwrap();
+
+    let page = client
+        .search("build", SearchFilter::default(), None, 1)
+        .await
+        .unwrap();
+    assert_eq!(page.messages, vec![red.clone()]);
+    let page = client
+        .search("build", SearchFilter::default(), page.next, 1)
+        .await
+        .unwrap();
+    assert_eq!(page.messages, vec![green]);
+    assert_eq!(page.next, None);
+
+    let filter = SearchFilter {
+        channel: Some("another".to_string()),
+        ..SearchFilter::default()
+    };
+    let page = client.search("build", filter, None, 10).await.unwrap();
+    assert_eq!(page.messages, vec![red]);
+}
+
+#[tokio::test]
+async fn ephemeral_messages_are_deleted_for_everyone() {
+    let (addr, _) = start("127.0.0.1:0").await;
+    let alice = login(addr, "alice").await;
+    let bob = login(addr, "bob").await;
+    let mut events = Box::pin(bob.events());
+
+    let kept = alice.send("default", "kept").await.unwrap();
+    let gone = alice
+        .send_ephemeral("default", "gone soon", Duration::from_millis(200))
+        .await
+        .unwrap();
+    let expires = gone.expires.expect("the server sets the expiry");
+    assert!(expires > gone.created);
+    assert_eq!(kept.expires, None);
+
+    let deleted = wait_for(&mut events, |event| match event {
+        Event::Deleted { channel, seqs } => Some((channel, seqs)),
+        _ => None,
+    })
+    .await;
+    assert_eq!(deleted, ("default".to_string(), vec![gone.seq]));
+    let history = bob.history("default", None, 10).await.unwrap();
+    assert_eq!(history, vec![kept]);
+
+    let forever = alice
+        .send_ephemeral(
+            "default",
+            "too long",
+            Duration::from_secs(30 * 24 * 60 * 60),
+        )
+        .await;
+    match forever {
+        Err(ClientError::Rejected { code, .. }) => assert_eq!(code, ErrorCode::InvalidFrame),
+        other => panic!("expected a rejection, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn clients_reconnect_after_a_restart() {
+    let (addr, shutdown) = start("127.0.0.1:0").await;
+    let client = login(addr, "alice").await;
+    let mut events = Box::pin(client.events());
+
+    shutdown.cancel();
+    wait_for(&mut events, |event| {
+        matches!(event, Event::ServerShutdown { .. }).then_some(())
+    })
+    .await;
+    wait_for(&mut events, |event| {
+        (event == Event::Disconnected).then_some(())
+    })
+    .await;
+
+    // Requests fail while the server is away.
+    assert!(matches!(
+        client.send("default", "anyone?").await,
+        Err(ClientError::Disconnected)
+    ));
+
+    start(&addr.to_string()).await;
+    let channels = wait_for(&mut events, |event| match event {
+        Event::Reconnected(channels) => Some(channels),
+        _ => None,
+    })
+    .await;
+    assert!(channels.iter().any(|channel| channel.name == "default"));
+
+    // Logged in again as the same user.
+    let sent = client.send("default", "back").await.unwrap();
+    assert_eq!(sent.from, user("alice"));
+}
+
+#[tokio::test]
+async fn clients_can_opt_out_of_reconnecting() {
+    let (addr, shutdown) = start("127.0.0.1:0").await;
+    let mut config = config(addr);
+    config.reconnect = false;
+    let client = Client::connect(config).await.unwrap();
+    client.login(user("alice")).await.unwrap();
+    let mut events = Box::pin(client.events());
+
+    shutdown.cancel();
+    wait_for(&mut events, |event| {
+        (event == Event::Disconnected).then_some(())
+    })
+    .await;
+    start(&addr.to_string()).await;
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    assert!(matches!(
+        client.send("default", "anyone?").await,
+        Err(ClientError::Disconnected)
+    ));
+}
+
+#[tokio::test]
+async fn silent_servers_are_given_up_on() {
+    // Logs the client in, announces a short heartbeat and then stops
+    // answering without closing the connection.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut chat = Framed::new(stream, ChatCodec::new());
+        assert!(matches!(
+            chat.next().await.unwrap().unwrap(),
+            Frame::Authorize(_)
+        ));
+        chat.send(Frame::Bulk(vec![], vec![])).await.unwrap();
+        let interval = Duration::from_millis(50);
+        chat.send(Frame::Ping { id: 1, interval }).await.unwrap();
+        assert_eq!(chat.next().await.unwrap().unwrap(), Frame::Pong(1));
+        // The client hangs up once three intervals pass in silence.
+        assert!(chat.next().await.is_none());
+    });
+
+    let mut config = config(addr);
+    config.reconnect = false;
+    let client = Client::connect(config).await.unwrap();
+    let mut events = Box::pin(client.events());
+    client.login(user("alice")).await.unwrap();
+    wait_for(&mut events, |event| {
+        (event == Event::Disconnected).then_some(())
+    })
+    .await;
+    tokio::time::timeout(Duration::from_secs(5), server)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(matches!(
+        client.send("default", "anyone?").await,
+        Err(ClientError::Disconnected)
+    ));
+}
+
+#[tokio::test]
+async fn kicked_clients_stay_out() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let addr = serve(Arc::clone(&server)).await;
+    let client = login(addr, "alice").await;
+    let mut events = Box::pin(client.events());
+
+    assert!(server.sessions.kick(server.sessions.list()[0].id));
+    let code = wait_for(&mut events, |event| match event {
+        Event::Error { code, .. } => Some(code),
+        _ => None,
+    })
+    .await;
+    assert_eq!(code, ErrorCode::Forbidden);
+    assert_eq!(next_event(&mut events).await, Event::Disconnected);
+
+    // Several backoffs later, she is still gone.
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(server.sessions.list().is_empty());
+    assert!(matches!(
+        client.send("default", "let me back in").await,
+        Err(ClientError::Disconnected)
+    ));
+}
+
+#[tokio::test]
+async fn refused_logins_are_not_retried() {
+    // Logs the client in and hangs up, then refuses every login.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let mut logins = 0;
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut chat = Framed::new(stream, ChatCodec::new());
+            assert!(matches!(
+                chat.next().await.unwrap().unwrap(),
+                Frame::Authorize(_)
+            ));
+            logins += 1;
+            let answer = match logins {
+                1 => Frame::Bulk(vec![], vec![]),
+                _ => Frame::error(ErrorCode::Forbidden, "banned by an administrator"),
+            };
+            chat.send(answer).await.unwrap();
+            if logins == 1 {
+                chat.close().await.unwrap();
+            } else {
+                // Several backoffs pass without another attempt.
+                let again = Duration::from_millis(300);
+                return tokio::time::timeout(again, listener.accept())
+                    .await
+                    .is_err();
+            }
+        }
+    });
+
+    let client = Client::connect(config(addr)).await.unwrap();
+    let mut events = Box::pin(client.events());
+    client.login(user("alice")).await.unwrap();
+    assert_eq!(next_event(&mut events).await, Event::Channels(vec![]));
+    assert_eq!(next_event(&mut events).await, Event::Disconnected);
+    assert!(matches!(
+        next_event(&mut events).await,
+        Event::Error {
+            code: ErrorCode::Forbidden,
+            ..
+        }
+    ));
+    assert_eq!(next_event(&mut events).await, Event::Disconnected);
+    assert!(server.await.unwrap());
+    assert!(matches!(
+        client.send("default", "anyone?").await,
+        Err(ClientError::Disconnected)
+    ));
+}
@@ -1,4 +1,4 @@
-use crate::{CHANNELS, CURRENT_CHANNEL};
+use crate::{CHANNELS, CURRENT_CHANNEL, PENDING};
 
 use super::message::Message;
 use dioxus::prelude::*;
@@ -28,11 +28,22 @@ pub fn Chat(cx: Scope<ChatProps>) -> Element {
                 message: message.clone()
             })
         });
+    // Messages the server has not acknowledged yet go last.
+    let pending = use_atom_state(cx, PENDING);
+    let pending = pending.get().iter().filter_map(|request| {
+        let message = request.message().filter(|m| &m.channel == channel)?;
+        Some(rsx!(Message {
+            left: false,
+            message: message.clone(),
+            pending: request.clone()
+        }))
+    });
     cx.render(rsx! {
         div {
             id: "messages",
             class:"flex flex-col space-y-4 p-3 overflow-y-auto scrollbar-thumb-blue scrollbar-thumb-rounded scrollbar-track-blue-lighter scrollbar-w-2 scrolling-touch",
             messages
+            pending
         }
     })
 }
@@ -1,7 +1,7 @@
 use dioxus::prelude::*;
 use fermi::use_atom_state;
 
-use crate::{CHANNELS, CURRENT_CHANNEL};
+use crate::{CHANNELS, CURRENT_CHANNEL, SEARCH_OPEN};
 
 #[allow(non_snake_case)]
 #[inline_props]
@@ -9,6 +9,7 @@ pub fn Header(cx: Scope, name: String, cover: String) -> Element {
     println!("{name} {cover}");
     let current_channel = use_atom_state(cx, CURRENT_CHANNEL);
     let channels = use_atom_state(cx, CHANNELS);
+    let searching = use_atom_state(cx, SEARCH_OPEN);
     let channel = current_channel.clone();
     let current_channel = current_channel.as_ref().unwrap();
     // let cover = channels
@@ -46,6 +47,7 @@ pub fn Header(cx: Scope, name: String, cover: String) -> Element {
                     class: "flex items-center space-x-2",
                     button {
                         class: "inline-flex items-center justify-center rounded-lg border h-10 w-10 transition duration-500 ease-in-out text-gray-500 hover:bg-gray-300 focus:outline-none",
+                        onclick: move |_| searching.modify(|open| !open),
                         svg {
                             xmlns: "http://www.w3.org/2000/svg",
                             fill:"none",
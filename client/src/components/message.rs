@@ -1,11 +1,19 @@
-use crate::CURRENT_USER;
+use crate::{
+    pending::{self, Pending, PendingState},
+    CURRENT_USER, PENDING,
+};
+use chrono::{DateTime, Utc};
 use dioxus::prelude::*;
 use fermi::prelude::*;
+use protocol::Frame;
+use std::time::Duration;
 
 #[derive(PartialEq, Props)]
 pub struct MessageProps {
     pub left: bool,
     pub message: protocol::Message,
+    /// Set while the server has not acknowledged the message.
+    pub pending: Option<Pending>,
 }
 
 #[allow(non_snake_case)]
@@ -16,6 +24,41 @@ pub fn Message(cx: Scope<MessageProps>) -> Element {
     let avatar = cx.props.message.clone().from.avatar.unwrap_or(
         "https://images.unsplash.com/photo-1549078642-b2ba4bda0cdb?ixlib=rb-1.2.1&amp;ixid=eyJhcHBfaWQiOjEyMDd9&amp;auto=format&amp;fit=facearea&amp;facepad=3&amp;w=144&amp;h=144".to_owned()
     );
+    let requests = use_atom_state(cx, PENDING);
+    let server_tx = use_coroutine_handle::<Frame>(cx);
+    let status = cx.props.pending.as_ref().map(|request| {
+        let id = request.id;
+        match &request.state {
+            PendingState::Sending => rsx!(p {
+                class: "text-gray-400 italic",
+                "sending…"
+            }),
+            PendingState::Failed(reason) => rsx!(button {
+                class: "text-red-500 hover:underline",
+                title: "{reason}",
+                onclick: move |_| {
+                    let mut frame = None;
+                    requests.with_mut(|requests| frame = pending::retry(requests, id));
+                    if let (Some(frame), Some(server_tx)) = (frame, server_tx) {
+                        server_tx.send(frame);
+                    }
+                },
+                "failed, retry"
+            }),
+        }
+    });
+    let countdown = cx
+        .props
+        .message
+        .expires
+        .map(|expires| rsx!(Countdown { expires: expires }));
+    // Bots are marked so they are not mistaken for people.
+    let badge = cx.props.message.from.bot.then(|| {
+        rsx!(span {
+            class: "ml-1 px-1 rounded bg-purple-500 text-white font-normal",
+            "bot"
+        })
+    });
     cx.render(rsx! {
         div {
             class: "chat-message transition-all ease-in-out delay-150",
@@ -35,11 +78,14 @@ pub fn Message(cx: Scope<MessageProps>) -> Element {
                             p {
                                 class: "font-extrabold",
                                 "{cx.props.message.from.username}"
+                                badge
                             }
                             p {
                                 "{cx.props.message.body}"
                             }
+                            countdown
                         }
+                        status
                     }
                 }
                 img {
@@ -49,3 +95,43 @@ pub fn Message(cx: Scope<MessageProps>) -> Element {
         }
     })
 }
+
+#[derive(PartialEq, Props)]
+pub struct CountdownProps {
+    pub expires: DateTime<Utc>,
+}
+
+/// Time left until the server deletes a self-destructing message, ticking
+/// every second. The message disappears once the server says it is gone.
+#[allow(non_snake_case)]
+pub fn Countdown(cx: Scope<CountdownProps>) -> Element {
+    let now = use_state(cx, Utc::now);
+    use_future(cx, (), |_| {
+        let now = now.clone();
+        async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                now.set(Utc::now());
+            }
+        }
+    });
+    let left = remaining((cx.props.expires - *now.get()).num_seconds());
+    cx.render(rsx! {
+        p {
+            class: "text-xs opacity-75",
+            title: "deleted for everyone when the timer runs out",
+            "⏱ {left}"
+        }
+    })
+}
+
+/// `secs` as the two largest units, e.g. `1h 05m` or `42s`.
+fn remaining(secs: i64) -> String {
+    let secs = secs.max(0);
+    match (secs / 86_400, secs / 3_600 % 24, secs / 60 % 60, secs % 60) {
+        (0, 0, 0, s) => format!("{s}s"),
+        (0, 0, m, s) => format!("{m}m {s:02}s"),
+        (0, h, m, _) => format!("{h}h {m:02}m"),
+        (d, h, _, _) => format!("{d}d {h}h"),
+    }
+}
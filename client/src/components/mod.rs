@@ -4,10 +4,12 @@ mod contact;
 mod header;
 mod login;
 mod message;
+mod search;
 mod sidebar;
 pub use chat::{Chat, ChatProps};
 pub use contact::Contact;
 pub use header::Header;
 pub use login::{Login, LoginProps};
 pub use message::{Message, MessageProps};
+pub use search::{Found, SearchPanel};
 pub use sidebar::Sidebar;
@@ -0,0 +1,157 @@
+use crate::{CURRENT_CHANNEL, SEARCH, SEARCH_OPEN};
+
+use super::message::Message;
+use chat_client::SearchPage;
+use dioxus::prelude::*;
+use fermi::use_atom_state;
+use protocol::{Frame, Message};
+
+/// Results asked for at once.
+const SEARCH_PAGE: u32 = 20;
+
+/// What the search panel shows, newest first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Found {
+    pub query: String,
+    /// Set when only one channel was searched.
+    pub channel: Option<String>,
+    pub messages: Vec<Message>,
+    /// Cursor of the next page, if there is one.
+    pub next: Option<u64>,
+}
+
+impl Found {
+    /// Shows `page`, below the results so far when it continues them.
+    pub fn add(
+        found: &mut Option<Found>,
+        query: String,
+        channel: Option<String>,
+        cursor: Option<u64>,
+        page: SearchPage,
+    ) {
+        match found {
+            Some(found) if cursor.is_some() && found.query == query && found.channel == channel => {
+                found.messages.extend(page.messages);
+                found.next = page.next;
+            }
+            _ => {
+                *found = Some(Found {
+                    query,
+                    channel,
+                    messages: page.messages,
+                    next: page.next,
+                })
+            }
+        }
+    }
+}
+
+fn search(query: &str, channel: Option<String>, cursor: Option<u64>) -> Frame {
+    Frame::Search {
+        query: query.to_string(),
+        channel,
+        from: None,
+        before: None,
+        after: None,
+        cursor,
+        limit: SEARCH_PAGE,
+    }
+}
+
+/// Searches the channels the user is in, opened with the magnifier in the
+/// `Header`.
+#[allow(non_snake_case)]
+pub fn SearchPanel(cx: Scope) -> Element {
+    let open = use_atom_state(cx, SEARCH_OPEN);
+    let found = use_atom_state(cx, SEARCH);
+    let current_channel = use_atom_state(cx, CURRENT_CHANNEL);
+    let query = use_state(cx, String::new);
+    let only_here = use_state(cx, || false);
+    let server_tx = use_coroutine_handle::<Frame>(cx);
+
+    if !*open.get() {
+        return None;
+    }
+
+    let results = found
+        .get()
+        .iter()
+        .flat_map(|found| found.messages.iter())
+        .map(|message| {
+            rsx!(div {
+                key: "{message.seq}",
+                p {
+                    class: "text-xs text-gray-400",
+                    "#{message.channel}"
+                }
+                Message {
+                    left: true,
+                    message: message.clone()
+                }
+            })
+        });
+    let nothing = found
+        .get()
+        .as_ref()
+        .filter(|found| found.messages.is_empty())
+        .map(|found| {
+            rsx!(p {
+                class: "text-gray-500 italic",
+                "nothing found for \"{found.query}\""
+            })
+        });
+    let more = found.get().as_ref().and_then(|found| {
+        let frame = search(&found.query, found.channel.clone(), Some(found.next?));
+        Some(rsx!(button {
+            class: "text-blue-500 hover:underline",
+            onclick: move |_| {
+                if let Some(server_tx) = server_tx {
+                    server_tx.send(frame.clone());
+                }
+            },
+            "older results"
+        }))
+    });
+
+    cx.render(rsx! {
+        div {
+            class: "border-b-2 border-gray-200 px-4 py-3 flex flex-col space-y-2 max-h-96 overflow-y-auto",
+            div {
+                class: "flex items-center space-x-2",
+                input {
+                    placeholder: "Search messages",
+                    class: "w-full focus:outline-none focus:placeholder-gray-400 text-gray-600 placeholder-gray-600 px-4 bg-gray-200 rounded-md py-2",
+                    "type": "text",
+                    value: "{query}",
+                    oninput: move |evt| query.set(evt.value.clone())
+                }
+                label {
+                    class: "flex items-center space-x-1 text-sm text-gray-600 whitespace-nowrap",
+                    input {
+                        "type": "checkbox",
+                        checked: "{only_here}",
+                        oninput: move |evt| only_here.set(evt.value == "true")
+                    }
+                    span { "this channel only" }
+                }
+                button {
+                    class: "inline-flex items-center justify-center rounded-lg px-4 py-2 transition duration-500 ease-in-out text-white bg-blue-500 hover:bg-blue-400 focus:outline-none",
+                    onclick: move |_| {
+                        let channel = if *only_here.get() {
+                            current_channel.get().clone()
+                        } else {
+                            None
+                        };
+                        if let Some(server_tx) = server_tx {
+                            server_tx.send(search(query.get(), channel, None));
+                        }
+                    },
+                    "Search"
+                }
+            }
+            results
+            nothing
+            more
+        }
+    })
+}
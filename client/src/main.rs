@@ -2,28 +2,71 @@ use std::collections::HashMap;
 
 use dioxus::prelude::*;
 
-use components::{Chat, Login, Sidebar};
-use futures::{executor::block_on, SinkExt, StreamExt};
-use tokio::{net::TcpStream, select};
-use tokio_util::codec::{BytesCodec, Framed};
+use chat_client::{Client, ClientError, Config, Event, SearchFilter};
+use components::{Chat, Found, Header, Login, SearchPanel, Sidebar};
+use futures::{stream::FuturesUnordered, StreamExt};
+use pending::Pending;
+use tokio::select;
 
-use bytes::Bytes;
 use fermi::prelude::*;
-use protocol::{Channel, Frame, Message, User};
+use protocol::{tls::Trust, Channel, Compression, Frame, Message, User};
 
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Notify;
+use tokio::time::Instant;
 
 mod components;
+mod pending;
 
 fn main() {
     dioxus_desktop::launch(app);
 }
 
+/// Connects to `CHAT_ADDR` (default `127.0.0.1:9999`). TLS is used when either
+/// `CHAT_TLS_CA` points to a CA certificate or `CHAT_TLS_PIN` holds the
+/// SHA-256 fingerprint of a self-signed server certificate. Setting
+/// `CHAT_QUIC` connects over QUIC instead, which keeps the session alive when
+/// the network changes and requires one of the above.
+fn config() -> Config {
+    let mut config =
+        Config::new(std::env::var("CHAT_ADDR").unwrap_or("127.0.0.1:9999".to_string()));
+    config.trust = match (std::env::var("CHAT_TLS_CA"), std::env::var("CHAT_TLS_PIN")) {
+        (Ok(ca), _) => Some(Trust::Ca(ca.into())),
+        (_, Ok(pin)) => Some(Trust::Pin(pin)),
+        _ => None,
+    };
+    if let Ok(server_name) = std::env::var("CHAT_SERVER_NAME") {
+        config.server_name = server_name;
+    }
+    config.quic = std::env::var_os("CHAT_QUIC").is_some();
+    // The history sent on connect compresses well, ask for zstd unless
+    // `CHAT_COMPRESSION` names another algorithm, or `none`.
+    config.compression = match std::env::var("CHAT_COMPRESSION") {
+        Ok(name) => name.parse().ok(),
+        Err(_) => Some(Compression::Zstd),
+    };
+    config
+}
+
 pub static CURRENT_USER: Atom<Option<User>> = |_| None;
 pub static CURRENT_CHANNEL: Atom<Option<String>> = |_| None;
 pub static MESSAGES: Atom<Vec<Message>> = |_| Vec::new();
 pub static CHANNELS: Atom<HashMap<String, Channel>> = |_| HashMap::new();
+/// Requests sent to the server that were not acknowledged yet.
+pub static PENDING: Atom<Vec<Pending>> = |_| Vec::new();
+/// Whether the search panel is open, toggled in the `Header`.
+pub static SEARCH_OPEN: Atom<bool> = |_| false;
+pub static SEARCH: Atom<Option<Found>> = |_| None;
+
+/// Timers offered for self-destructing messages, in seconds, zero for none.
+const TIMERS: [(&str, u64); 5] = [
+    ("no timer", 0),
+    ("10 seconds", 10),
+    ("1 minute", 60),
+    ("1 hour", 60 * 60),
+    ("1 day", 24 * 60 * 60),
+];
 
 fn app(cx: Scope) -> Element {
     use_init_atom_root(cx);
@@ -39,66 +82,119 @@ fn app(cx: Scope) -> Element {
     let chnls = channels.clone();
     let chnls1 = channels.clone();
     let message = use_state(cx, String::new);
+    let ttl = use_state(cx, || None::<Duration>);
+    let pending = use_atom_state(cx, PENDING);
+    let pending_state = pending.clone();
+    let search_state = use_atom_state(cx, SEARCH).clone();
 
     let server_tx = use_coroutine(&cx, |mut rx: UnboundedReceiver<Frame>| async move {
         block.notified().await;
-        let stream = TcpStream::connect("127.0.0.1:9999").await.unwrap();
-        let chat = Framed::new(stream, BytesCodec::new());
-        let (mut sink, mut stream) = chat.split();
-
-        let login_frame = block_on(rx.next()).unwrap();
-
-        if let Frame::Authorize(_) = login_frame {
-            let bytes: Bytes = login_frame.try_into().unwrap();
-            let _ = sink.send(bytes).await;
-        } else {
-            println!("wrong");
-        }
+        let client = match Client::connect(config()).await {
+            Ok(client) => client,
+            Err(e) => {
+                println!("unable to connect: {e}");
+                return;
+            }
+        };
+        let mut events = Box::pin(client.events());
+        // Commands from the UI run concurrently, each resolving to the
+        // request it answers, if any.
+        let mut requests = FuturesUnordered::new();
+        let mut request_timeouts = tokio::time::interval(Duration::from_secs(1));
 
         loop {
             select! {
-                Some(msg) = rx.next() => {
-                    let bytes: Bytes = msg.try_into().unwrap();
-                    sink.send(bytes).await.unwrap();
+                _ = request_timeouts.tick() => {
+                    pending_state.with_mut(|pending| pending::expire(pending, Instant::now()));
                 }
-                result = stream.next() => match result {
-                    Some(Ok(msg)) => {
-                        let message: Frame = msg.freeze().try_into().unwrap();
-                        match message {
-                            Frame::Message(message) => {
-                                let channels = channels_state_clone.clone();
-                                // chnls1.with_mut(|chnls| {
-                                //     chnls.get_mut(&message.channel).unwrap().messages.push(message);
-                                // });
-                                channels.with_mut(|chnls| {
-                                    chnls.get_mut(&message.channel).unwrap().messages.push(message);
-                                });
-                                chnls1.set(HashMap::from_iter(channels.current().clone().iter().map(|ch| (ch.0.to_owned(), ch.1.clone()))));
-                            },
-                            Frame::Bulk(_, chnls) => {
-                                let channels = channels_state_clone.clone();
-                                chnls1.with_mut(|chnl| {
-                                    chnl.extend(chnls.iter().map(|ch| {
-                                        (ch.name.to_owned(), ch.clone())
-                                    }));
-                                });
-                                channels.with_mut(|chnl| {
-                                    chnl.extend(chnls.iter().map(|ch| {
-                                        (ch.name.to_owned(), ch.clone())
-                                    }));
-                                });
-                            },
-                            Frame::Error(_) => {
-                                break;
+                Some(frame) = rx.next() => {
+                    let client = client.clone();
+                    let found = search_state.clone();
+                    requests.push(async move {
+                        match frame {
+                            Frame::Authorize(user) => (None, client.login(user).await.map(drop)),
+                            Frame::Request { id, frame } => {
+                                let result = match *frame {
+                                    Frame::Message(message) => match message.ttl {
+                                        Some(ttl) => client.send_ephemeral(&message.channel, &message.body, ttl).await.map(drop),
+                                        None => client.send(&message.channel, &message.body).await.map(drop),
+                                    },
+                                    Frame::Channel(channel) => client
+                                        .create_channel(&channel.name, channel.cover)
+                                        .await
+                                        .map(drop),
+                                    _ => Err(ClientError::Unexpected),
+                                };
+                                (Some(id), result)
                             }
-                            _ => {
+                            Frame::Search { query, channel, from, before, after, cursor, limit } => {
+                                let filter = SearchFilter { channel: channel.clone(), from, before, after };
+                                let result = client
+                                    .search(&query, filter, cursor, limit)
+                                    .await
+                                    .map(|page| found.with_mut(|found| Found::add(found, query, channel, cursor, page)));
+                                (None, result)
                             }
+                            _ => (None, Err(ClientError::Unexpected)),
                         }
-                    },
-                    Some(Err(_)) => {
+                    });
+                }
+                Some((id, result)) = requests.next() => match (id, result) {
+                    (Some(id), Ok(())) => {
+                        pending_state.with_mut(|pending| pending::acknowledge(pending, id));
                     }
-                    None => {
+                    (Some(id), Err(e)) => {
+                        pending_state.with_mut(|pending| pending::reject(pending, id, e.to_string()));
                     }
+                    (None, Err(e)) => println!("request failed: {e}"),
+                    (None, Ok(())) => {}
+                },
+                Some(event) = events.next() => match event {
+                    Event::Message(message) => {
+                        let channels = channels_state_clone.clone();
+                        channels.with_mut(|chnls| {
+                            chnls.get_mut(&message.channel).unwrap().messages.push(message);
+                        });
+                        chnls1.set(HashMap::from_iter(channels.current().clone().iter().map(|ch| (ch.0.to_owned(), ch.1.clone()))));
+                    }
+                    Event::Channels(chnls) | Event::Reconnected(chnls) => {
+                        let channels = channels_state_clone.clone();
+                        chnls1.with_mut(|chnl| {
+                            chnl.extend(chnls.iter().map(|ch| {
+                                (ch.name.to_owned(), ch.clone())
+                            }));
+                        });
+                        channels.with_mut(|chnl| {
+                            chnl.extend(chnls.iter().map(|ch| {
+                                (ch.name.to_owned(), ch.clone())
+                            }));
+                        });
+                    }
+                    Event::Deleted { channel, seqs } => {
+                        let forget = |chnls: &mut HashMap<String, Channel>| {
+                            if let Some(chnl) = chnls.get_mut(&channel) {
+                                chnl.messages.retain(|message| !seqs.contains(&message.seq));
+                            }
+                        };
+                        channels_state_clone.with_mut(forget);
+                        chnls1.with_mut(forget);
+                        search_state.with_mut(|found| {
+                            if let Some(found) = found {
+                                found.messages.retain(|message| message.channel != channel || !seqs.contains(&message.seq));
+                            }
+                        });
+                    }
+                    Event::Disconnected => {
+                        println!("connection lost, reconnecting");
+                        pending_state.with_mut(|pending| pending::fail_all(pending, "connection lost"));
+                    }
+                    Event::ServerShutdown { reason, .. } => {
+                        println!("server shutdown: {reason}");
+                    }
+                    Event::Error { code, reason } => {
+                        println!("server error: {code}: {reason}");
+                    }
+                    Event::Left(_) | Event::Reaction { .. } => {}
                 },
             }
         }
@@ -107,13 +203,15 @@ fn app(cx: Scope) -> Element {
     let tx1 = server_tx.clone();
     let login_tx = server_tx.clone();
     let sidebar_tx = server_tx.clone();
+    let sidebar_pending = pending.clone();
 
     let chat = if channel.current().is_some() {
         cx.render(rsx!{
-            // Header {
-            //     name: channel.as_ref().unwrap().to_string(),
-            //     cover: chnls.clone().current().get(channel.as_ref().unwrap()).unwrap().cover.clone().unwrap().to_string()
-            // }
+            Header {
+                name: channel.as_ref().unwrap().to_string(),
+                cover: chnls.current().get(channel.as_ref().unwrap()).and_then(|channel| channel.cover.clone()).unwrap_or_default()
+            }
+            SearchPanel {}
             Chat {
                 messages: chnls.clone().current().get(channel.as_ref().unwrap()).unwrap().messages.clone()
             }
@@ -122,6 +220,12 @@ fn app(cx: Scope) -> Element {
             class: "border-t-2 border-gray-200 px-4 pt-4 mb-2 sm:mb-0",
             div {
                 class: "relative flex",
+                select {
+                    class: "mr-2 rounded-md bg-gray-200 text-gray-600 px-2 focus:outline-none",
+                    title: "Delete the message for everyone after",
+                    oninput: move |evt| ttl.set(evt.value.parse().ok().filter(|secs| *secs > 0).map(Duration::from_secs)),
+                    TIMERS.iter().map(|(label, secs)| rsx!(option { value: "{secs}", "{label}" }))
+                }
                 input {
                     placeholder: "Write your message!", 
                     class: "w-full focus:outline-none focus:placeholder-gray-400 text-gray-600 placeholder-gray-600 px-6 bg-gray-200 rounded-md py-3",
@@ -135,12 +239,17 @@ fn app(cx: Scope) -> Element {
                         class: "z-40 inline-flex items-center justify-center rounded-lg px-4 py-3 transition duration-500 ease-in-out text-white bg-blue-500 hover:bg-blue-400 focus:outline-none",
                         onclick: move |_| {
 
-                            let message = Frame::Message(Message::new(
+                            let mut message = Message::new(
                                 user.as_ref().unwrap().clone(),
                                 channel.as_ref().unwrap().clone(),
                                 message.clone().to_string()
-                            ));
-                            tx1.send(message)
+                            );
+                            message.ttl = *ttl.get();
+                            let message = Frame::Message(message);
+                            // Shown as "sending…" until the server answers.
+                            let request = Pending::new(message);
+                            tx1.send(request.request());
+                            pending.with_mut(|pending| pending.push(request));
                         },
                         span {
                             class: "font-bold",
@@ -176,6 +285,7 @@ fn app(cx: Scope) -> Element {
                         } else {
                             Some("https://w7.pngwing.com/pngs/754/2/png-transparent-samsung-galaxy-a8-a8-user-login-telephone-avatar-pawn-blue-angle-sphere-thumbnail.png".to_string())
                         },
+                        bot: false,
                     };
                     user.modify(|_| Some(logged_in.clone()));
                     login_tx.send(Frame::Authorize(logged_in));
@@ -196,7 +306,9 @@ fn app(cx: Scope) -> Element {
                         },
                         messages: vec![]
                     });
-                    sidebar_tx.send(channel);
+                    let request = Pending::new(channel);
+                    sidebar_tx.send(request.request());
+                    sidebar_pending.with_mut(|pending| pending.push(request));
                 }
             }
             div {
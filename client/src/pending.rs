@@ -0,0 +1,87 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use protocol::{Frame, Message, RequestId};
+use tokio::time::Instant;
+
+/// How long a request may go unanswered before it is shown as failed.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PendingState {
+    Sending,
+    Failed(String),
+}
+
+/// A command sent to the server that it has not acknowledged yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pending {
+    pub id: RequestId,
+    pub frame: Frame,
+    pub state: PendingState,
+    pub sent: Instant,
+}
+
+impl Pending {
+    pub fn new(frame: Frame) -> Self {
+        Pending {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            frame,
+            state: PendingState::Sending,
+            sent: Instant::now(),
+        }
+    }
+
+    /// The frame to send to the server for this request.
+    pub fn request(&self) -> Frame {
+        Frame::request(self.id, self.frame.clone())
+    }
+
+    pub fn message(&self) -> Option<&Message> {
+        match &self.frame {
+            Frame::Message(message) => Some(message),
+            _ => None,
+        }
+    }
+}
+
+/// The server carried out request `id`, it no longer needs tracking.
+pub fn acknowledge(pending: &mut Vec<Pending>, id: RequestId) {
+    pending.retain(|request| request.id != id);
+}
+
+pub fn reject(pending: &mut [Pending], id: RequestId, reason: String) {
+    if let Some(request) = pending.iter_mut().find(|request| request.id == id) {
+        request.state = PendingState::Failed(reason);
+    }
+}
+
+/// Marks requests that went unanswered for `REQUEST_TIMEOUT` as failed.
+pub fn expire(pending: &mut [Pending], now: Instant) {
+    for request in pending.iter_mut() {
+        if request.state == PendingState::Sending && now - request.sent > REQUEST_TIMEOUT {
+            request.state = PendingState::Failed("no answer from the server".to_string());
+        }
+    }
+}
+
+/// Every request still in flight is lost with the connection.
+pub fn fail_all(pending: &mut [Pending], reason: &str) {
+    for request in pending.iter_mut() {
+        if request.state == PendingState::Sending {
+            request.state = PendingState::Failed(reason.to_string());
+        }
+    }
+}
+
+/// Puts a failed request back in flight, returning the frame to send again.
+pub fn retry(pending: &mut [Pending], id: RequestId) -> Option<Frame> {
+    let request = pending.iter_mut().find(|request| request.id == id)?;
+    request.state = PendingState::Sending;
+    request.sent = Instant::now();
+    Some(request.request())
+}
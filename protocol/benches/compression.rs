@@ -0,0 +1,96 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use protocol::{Channel, ChatCodec, Compression, Frame, Message, User};
+use tokio_util::codec::{Decoder, Encoder};
+
+/// A `Frame::Bulk` like the one sent on connect: every channel with its full
+/// history.
+fn history(channels: usize, messages: usize) -> Frame {
+    let words = [
+        "hello", "anyone", "around", "deploy", "finished", "lunch", "meeting", "tomorrow",
+        "thanks", "review", "please", "looks", "good", "to", "me", "the", "build", "is", "green",
+        "sure", "I'll", "take", "a", "look", "after", "standup",
+    ];
+    let channels = (0..channels)
+        .map(|c| Channel {
+            name: format!("channel{c}"),
+            cover: Some("https://cdn-icons-png.flaticon.com/512/134/134932.png".to_string()),
+            messages: (0..messages)
+                .map(|i| {
+                    let body = (0..4 + (i * 31 + c) % 23)
+                        .map(|j| words[(i * 17 + j * 5 + c) % words.len()])
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    Message::new(
+                        User {
+                            username: format!("user{}", (i + c) % 25),
+                            color: Some("#3b82f6".to_string()),
+                            avatar: Some(format!(
+                                "https://images.example.com/avatars/{}.png",
+                                (i + c) % 25
+                            )),
+                            bot: false,
+                        },
+                        format!("channel{c}"),
+                        body,
+                    )
+                })
+                .collect(),
+        })
+        .collect();
+    Frame::Bulk(vec![], channels)
+}
+
+fn codec(compression: Option<Compression>) -> (ChatCodec, ChatCodec) {
+    let mut client = ChatCodec::new();
+    let mut server = ChatCodec::new();
+    if let Some(compression) = compression {
+        client = client.with_compression(compression);
+        // Complete the handshake so both sides compress.
+        let mut wire = bytes::BytesMut::new();
+        client.encode(Frame::Ok, &mut wire).unwrap();
+        server.decode(&mut wire).unwrap();
+        server.encode(Frame::Ok, &mut wire).unwrap();
+        client.decode(&mut wire).unwrap();
+    }
+    (client, server)
+}
+
+fn bulk(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bulk");
+    for (channels, messages) in [(2, 100), (5, 1000)] {
+        let frame = history(channels, messages);
+        let plain = bincode::serialize(&frame).unwrap().len();
+        group.throughput(Throughput::Bytes(plain as u64));
+
+        for compression in [None, Some(Compression::Zstd), Some(Compression::Deflate)] {
+            let name = compression.map_or("none", |c| c.name());
+            let (mut client, mut server) = codec(compression);
+
+            let mut wire = bytes::BytesMut::new();
+            server.encode(frame.clone(), &mut wire).unwrap();
+            println!(
+                "bulk/{name}/{channels}x{messages}: {plain} bytes encoded, {} on the wire",
+                wire.len()
+            );
+            wire.clear();
+
+            let id = format!("{channels}x{messages}");
+            group.bench_function(BenchmarkId::new(format!("encode/{name}"), &id), |b| {
+                b.iter(|| {
+                    server.encode(frame.clone(), &mut wire).unwrap();
+                    wire.clear();
+                })
+            });
+            group.bench_function(BenchmarkId::new(format!("round_trip/{name}"), &id), |b| {
+                b.iter(|| {
+                    server.encode(frame.clone(), &mut wire).unwrap();
+                    client.decode(&mut wire).unwrap().unwrap()
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bulk);
+criterion_main!(benches);
@@ -0,0 +1,86 @@
+use std::{
+    fmt::Display,
+    io::{self, Read, Write},
+    str::FromStr,
+};
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder};
+
+use crate::{ProtocolError, Result};
+
+/// Encoded frames larger than this many bytes are compressed on connections
+/// that negotiated a `Compression`; smaller ones would barely shrink.
+pub const COMPRESSION_THRESHOLD: usize = 1024;
+
+const ZSTD_LEVEL: i32 = 3;
+
+/// Algorithms a connection can compress large frames with, see
+/// `ChatCodec::with_compression`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Compression {
+    Zstd,
+    Deflate,
+}
+
+impl Compression {
+    pub const ALL: [Compression; 2] = [Compression::Zstd, Compression::Deflate];
+
+    /// Name used when negotiating the compression.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::Zstd => "zstd",
+            Compression::Deflate => "deflate",
+        }
+    }
+
+    pub fn compress(&self, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::Zstd => zstd::bulk::compress(bytes, ZSTD_LEVEL),
+            Compression::Deflate => {
+                let mut encoder = DeflateEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(bytes)?;
+                encoder.finish()
+            }
+        }
+    }
+
+    /// Decompresses `bytes`, failing rather than inflating beyond `limit`
+    /// bytes so a tiny frame cannot expand into gigabytes.
+    pub fn decompress(&self, bytes: &[u8], limit: usize) -> Result<Vec<u8>> {
+        let decompressed = match self {
+            Compression::Zstd => {
+                zstd::bulk::decompress(bytes, limit).map_err(|_| ProtocolError::Decode)?
+            }
+            Compression::Deflate => {
+                let mut decompressed = vec![];
+                DeflateDecoder::new(bytes)
+                    .take(limit as u64 + 1)
+                    .read_to_end(&mut decompressed)
+                    .map_err(|_| ProtocolError::Decode)?;
+                decompressed
+            }
+        };
+
+        if decompressed.len() > limit {
+            return Err(ProtocolError::Decode);
+        }
+        Ok(decompressed)
+    }
+}
+
+impl Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Compression {
+    type Err = ProtocolError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Compression::ALL
+            .into_iter()
+            .find(|compression| compression.name() == s)
+            .ok_or_else(|| ProtocolError::UnsupportedEncoding(s.to_string()))
+    }
+}
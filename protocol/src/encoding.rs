@@ -0,0 +1,68 @@
+use std::{fmt::Display, str::FromStr};
+
+use bytes::Bytes;
+
+use crate::{Frame, ProtocolError, Result};
+
+/// Wire formats a `Frame` can be serialized with.
+///
+/// Bincode is the default and what every connection speaks unless the client
+/// asks for another encoding when it connects, see `ChatCodec::with_encoding`.
+/// JSON and MessagePack describe frames by field and variant names, so they
+/// can be produced by non-Rust clients and read by debugging tools.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Encoding {
+    #[default]
+    Bincode,
+    Json,
+    MessagePack,
+}
+
+impl Encoding {
+    pub const ALL: [Encoding; 3] = [Encoding::Bincode, Encoding::Json, Encoding::MessagePack];
+
+    /// Name used when negotiating the encoding.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Bincode => "bincode",
+            Encoding::Json => "json",
+            Encoding::MessagePack => "msgpack",
+        }
+    }
+
+    pub fn encode(&self, frame: &Frame) -> Result<Bytes> {
+        let bytes = match self {
+            Encoding::Bincode => bincode::serialize(frame)?,
+            Encoding::Json => serde_json::to_vec(frame)?,
+            Encoding::MessagePack => rmp_serde::to_vec_named(frame)?,
+        };
+        Ok(Bytes::from(bytes))
+    }
+
+    pub fn decode(&self, bytes: &[u8]) -> Result<Frame> {
+        match self {
+            Encoding::Bincode => bincode::deserialize(bytes).map_err(|_| ProtocolError::Decode),
+            Encoding::Json => serde_json::from_slice(bytes).map_err(|_| ProtocolError::Decode),
+            Encoding::MessagePack => {
+                rmp_serde::from_slice(bytes).map_err(|_| ProtocolError::Decode)
+            }
+        }
+    }
+}
+
+impl Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = ProtocolError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Encoding::ALL
+            .into_iter()
+            .find(|encoding| encoding.name() == s)
+            .ok_or_else(|| ProtocolError::UnsupportedEncoding(s.to_string()))
+    }
+}
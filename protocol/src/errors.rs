@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tracing_subscriber::filter::ParseError;
 
@@ -9,6 +10,54 @@ pub enum ProtocolError {
     Decode,
     #[error("unable to serialize value")]
     Serialize(#[from] bincode::Error),
+    #[error("unable to serialize value as json")]
+    Json(#[from] serde_json::Error),
+    #[error("unable to serialize value as messagepack")]
+    MessagePack(#[from] rmp_serde::encode::Error),
+    #[error("unsupported encoding {0:?}")]
+    UnsupportedEncoding(String),
+    #[error("frame exceeds the maximum length")]
+    TooLarge,
+}
+
+impl ProtocolError {
+    /// How the failure is reported to the peer.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ProtocolError::TooLarge => ErrorCode::TooLarge,
+            _ => ErrorCode::InvalidFrame,
+        }
+    }
+}
+
+/// Machine readable reason of a `Frame::Error` or `Frame::Rejected`.
+#[derive(Error, Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+pub enum ErrorCode {
+    /// The peer has not logged in, or is not allowed to connect at all.
+    #[error("unauthorized")]
+    Unauthorized,
+    /// The frame refers to a channel that does not exist.
+    #[error("not found")]
+    NotFound,
+    /// The channel to be created exists already.
+    #[error("already exists")]
+    AlreadyExists,
+    /// The peer sends more than the server is willing to process.
+    #[error("rate limited")]
+    RateLimited,
+    /// The frame could not be decoded or is not valid at this point.
+    #[error("invalid frame")]
+    InvalidFrame,
+    /// The frame or one of its fields exceeds a size limit.
+    #[error("too large")]
+    TooLarge,
+    /// The peer is logged in but not allowed to do this, e.g. post as
+    /// someone else.
+    #[error("forbidden")]
+    Forbidden,
+    /// The server cannot take the connection right now, retry later.
+    #[error("server busy")]
+    ServerBusy,
 }
 
 pub type Result<T> = std::result::Result<T, ProtocolError>;
@@ -25,4 +74,10 @@ pub enum ConnectionError {
     MessageParse,
     #[error("failed to establish tracing")]
     TracingError(#[from] ParseError),
+    #[error("tls error")]
+    Tls(#[from] rustls::Error),
+    #[error("invalid certificate or key: {0}")]
+    Certificate(String),
+    #[error("quic error: {0}")]
+    Quic(String),
 }
@@ -1,11 +1,15 @@
 use colored::Colorize;
 use std::fmt::Display;
+use std::time::Duration;
 use termion::{cursor, terminal_size};
 
-use crate::{ConnectionError, ProtocolError, Result};
-use bytes::{BufMut, Bytes};
+use crate::{
+    compression::COMPRESSION_THRESHOLD, Compression, ConnectionError, Encoding, ErrorCode,
+    ProtocolError, Result,
+};
+use bytes::Bytes;
 use serde::{Deserialize, Serialize};
-use tokio_util::codec::{Decoder, Encoder};
+use tokio_util::codec::{Decoder, Encoder, LengthDelimitedCodec, LengthDelimitedCodecError};
 
 use chrono::prelude::*;
 
@@ -14,6 +18,13 @@ pub struct User {
     pub username: String,
     pub color: Option<String>,
     pub avatar: Option<String>,
+    /// Set by automated accounts so clients can tell them from people.
+    ///
+    /// Advisory only: the server takes it from the client's `Authorize`
+    /// unchecked, so anyone can set or clear it. Good enough for a badge,
+    /// not for deciding whom to trust.
+    #[serde(default)]
+    pub bot: bool,
 }
 
 impl Display for User {
@@ -29,6 +40,17 @@ pub struct Message {
     pub channel: String,
     pub body: String,
     pub created: DateTime<Utc>,
+    /// Assigned by the server when it accepts the message, increasing across
+    /// all channels. Zero until then.
+    pub seq: u64,
+    /// Asks the server to delete the message for everyone this long after
+    /// accepting it.
+    #[serde(default)]
+    pub ttl: Option<Duration>,
+    /// When the server deletes the message, set from `ttl` when it accepts
+    /// it.
+    #[serde(default)]
+    pub expires: Option<DateTime<Utc>>,
 }
 
 impl Message {
@@ -38,6 +60,9 @@ impl Message {
             channel,
             body,
             created: Utc::now(),
+            seq: 0,
+            ttl: None,
+            expires: None,
         }
     }
 }
@@ -67,6 +92,10 @@ pub struct Channel {
     pub cover: Option<String>,
 }
 
+/// Identifies a `Frame::Request` and the server's answer to it, unique per
+/// connection.
+pub type RequestId = u64;
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub enum Frame {
     Authorize(User),
@@ -75,8 +104,117 @@ pub enum Frame {
     Bulk(Vec<Message>, Vec<Channel>),
     Channel(Channel),
     Ok,
-    Error(String),
+    /// Something the client did failed. `reason` is meant for humans, clients
+    /// should act on `code`.
+    Error {
+        code: ErrorCode,
+        reason: String,
+    },
     Disconnect(User),
+    ServerShutdown {
+        reason: String,
+        reconnect_after: Option<Duration>,
+    },
+    /// Keepalive sent by the server every `interval`; answered with `Pong(id)`.
+    Ping {
+        id: u64,
+        interval: Duration,
+    },
+    Pong(u64),
+    /// Adds the peer to a channel it left, answered with `Joined`.
+    Join(String),
+    Joined(Channel),
+    /// Stops receiving messages of a channel.
+    Leave(String),
+    /// Asks for up to `limit` messages of `channel` older than the message
+    /// with sequence number `before`, or the latest ones. Answered with
+    /// `HistoryPage`, oldest message first.
+    History {
+        channel: String,
+        before: Option<u64>,
+        limit: u32,
+    },
+    HistoryPage {
+        channel: String,
+        messages: Vec<Message>,
+    },
+    /// Asks for up to `limit` messages containing the words of `query`, in
+    /// `channel` or in every channel the peer is in, optionally only those
+    /// sent by the user named `from` and between `after` and `before`. Newest
+    /// first, starting below the sequence number `cursor` when given.
+    /// Answered with `SearchResults`.
+    Search {
+        query: String,
+        channel: Option<String>,
+        from: Option<String>,
+        before: Option<DateTime<Utc>>,
+        after: Option<DateTime<Utc>>,
+        cursor: Option<u64>,
+        limit: u32,
+    },
+    /// `next` is the `cursor` of the following page, `None` on the last one.
+    SearchResults {
+        query: String,
+        messages: Vec<Message>,
+        next: Option<u64>,
+    },
+    /// `from` reacts with `emoji` to the message numbered `seq` in `channel`.
+    /// Relayed to everyone in the channel, the sender included.
+    React {
+        from: User,
+        channel: String,
+        seq: u64,
+        emoji: String,
+    },
+    /// The server deleted the messages numbered `seqs` from `channel`, e.g.
+    /// because they outlived their `ttl` or the channel's retention. Clients
+    /// should forget them too.
+    Deleted {
+        channel: String,
+        seqs: Vec<u64>,
+    },
+    /// `user` joined `channel`. Sent to the others in the channel, the
+    /// joiner gets `Joined`.
+    PeerJoined {
+        channel: String,
+        user: User,
+    },
+    /// `user` left `channel`, sent to those still in it. Closing the
+    /// connection is announced with `Disconnect` instead.
+    PeerLeft {
+        channel: String,
+        user: User,
+    },
+    /// A command (`Message`, `Channel`, `Join`, `Leave`, `History`, `Search`
+    /// or `React`) the client wants to hear back about. The server answers with
+    /// `Ack(id)` or `Rejected` carrying the same `id`, chosen by the client.
+    Request {
+        id: RequestId,
+        frame: Box<Frame>,
+    },
+    Ack(RequestId),
+    Rejected {
+        id: RequestId,
+        code: ErrorCode,
+        reason: String,
+    },
+}
+
+impl Frame {
+    pub fn error(code: ErrorCode, reason: impl Into<String>) -> Self {
+        Frame::Error {
+            code,
+            reason: reason.into(),
+        }
+    }
+
+    /// Wraps a command in a `Frame::Request` so it gets acknowledged.
+    pub fn request(id: RequestId, frame: Frame) -> Self {
+        Frame::Request {
+            id,
+            frame: Box::new(frame),
+        }
+    }
 }
 
 impl Display for Frame {
@@ -85,20 +223,143 @@ impl Display for Frame {
     }
 }
 
-pub struct ChatCodec {}
+/// Prefix of the payload a client sends ahead of its first frame to pick an
+/// `Encoding`, followed by the encoding name and optionally
+/// `;compression=<name>`. The server echoes it back ahead of its first frame
+/// once it switched. It cannot be mistaken for a bincode frame, whose first
+/// four bytes are a small variant index.
+const HELLO: &[u8] = b"chat-encoding:";
+const HELLO_COMPRESSION: &str = "compression=";
+
+/// Leading byte of every frame on connections that negotiated compression.
+const UNCOMPRESSED: u8 = 0;
+const COMPRESSED: u8 = 1;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Handshake {
+    /// Server side, the first payload may pick the encoding.
+    #[default]
+    Accept,
+    /// The client picked an encoding, the choice is echoed before the next
+    /// frame.
+    Acknowledge,
+    /// Client side, the encoding is announced before the first frame.
+    Offer,
+    /// Waiting for the server to echo the announced encoding.
+    Offered,
+    Done,
+}
+
+/// Length delimited encoding of `Frame`s, for any byte stream.
+///
+/// Frames are bincode encoded and uncompressed unless a client created its
+/// codec with `ChatCodec::with_encoding` or `ChatCodec::with_compression`, in
+/// which case both sides switch before the first frame.
+#[derive(Debug, Default)]
+pub struct ChatCodec {
+    inner: LengthDelimitedCodec,
+    encoding: Encoding,
+    compression: Option<Compression>,
+    handshake: Handshake,
+}
 
 impl ChatCodec {
     pub fn new() -> Self {
-        ChatCodec {}
+        ChatCodec {
+            inner: LengthDelimitedCodec::new(),
+            encoding: Encoding::Bincode,
+            compression: None,
+            handshake: Handshake::Accept,
+        }
+    }
+
+    /// Codec for the client side of a connection that negotiates `encoding`
+    /// with the server.
+    pub fn with_encoding(encoding: Encoding) -> Self {
+        ChatCodec {
+            encoding,
+            handshake: Handshake::Offer,
+            ..ChatCodec::new()
+        }
+    }
+
+    /// Also negotiates compressing frames larger than
+    /// `COMPRESSION_THRESHOLD` with `compression`, in both directions.
+    pub fn with_compression(self, compression: Compression) -> Self {
+        ChatCodec {
+            compression: Some(compression),
+            handshake: Handshake::Offer,
+            ..self
+        }
+    }
+
+    /// The encoding frames are currently written and read with.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// The compression applied to large frames, if any was negotiated.
+    pub fn compression(&self) -> Option<Compression> {
+        self.compression
+    }
+
+    fn hello(&self) -> Bytes {
+        let mut hello = self.encoding.name().to_string();
+        if let Some(compression) = self.compression {
+            hello.push_str(&format!(";{HELLO_COMPRESSION}{compression}"));
+        }
+        [HELLO, hello.as_bytes()].concat().into()
+    }
+
+    /// Adopts the encoding and compression a client asked for.
+    fn accept_hello(&mut self, hello: &[u8]) -> Result<()> {
+        let hello = String::from_utf8_lossy(hello);
+        let mut options = hello.split(';');
+        self.encoding = options.next().unwrap_or_default().parse()?;
+        for option in options {
+            match option.strip_prefix(HELLO_COMPRESSION) {
+                Some(name) => self.compression = Some(name.parse()?),
+                None => return Err(ProtocolError::UnsupportedEncoding(option.to_string())),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The length prefix announced more than the codec accepts.
+fn too_large(e: std::io::Error) -> ProtocolError {
+    match e.get_ref() {
+        Some(inner) if inner.is::<LengthDelimitedCodecError>() => ProtocolError::TooLarge,
+        _ => ProtocolError::Encode(e),
     }
 }
 
 impl Encoder<Frame> for ChatCodec {
     type Error = ProtocolError;
     fn encode(&mut self, item: Frame, dst: &mut bytes::BytesMut) -> Result<()> {
-        let frame = bincode::serialize(&item)?;
-        dst.reserve(frame.len());
-        dst.put(&frame[..]);
+        match self.handshake {
+            Handshake::Offer => {
+                self.inner.encode(self.hello(), dst)?;
+                self.handshake = Handshake::Offered;
+            }
+            Handshake::Acknowledge => {
+                self.inner.encode(self.hello(), dst)?;
+                self.handshake = Handshake::Done;
+            }
+            _ => {}
+        }
+
+        let frame = self.encoding.encode(&item)?;
+        let frame = match self.compression {
+            Some(compression) if frame.len() > COMPRESSION_THRESHOLD => {
+                let mut compressed = vec![COMPRESSED];
+                compressed.extend(compression.compress(&frame)?);
+                Bytes::from(compressed)
+            }
+            Some(_) => [&[UNCOMPRESSED], &frame[..]].concat().into(),
+            None => frame,
+        };
+        self.inner.encode(frame, dst)?;
 
         Ok(())
     }
@@ -108,8 +369,45 @@ impl Decoder for ChatCodec {
     type Item = Frame;
     type Error = ProtocolError;
     fn decode(&mut self, src: &mut bytes::BytesMut) -> Result<Option<Self::Item>> {
-        let frame = bincode::deserialize(src)?;
-        Ok(Some(frame))
+        loop {
+            let Some(bytes) = self.inner.decode(src).map_err(too_large)? else {
+                return Ok(None);
+            };
+
+            match self.handshake {
+                Handshake::Accept => {
+                    if let Some(hello) = bytes.strip_prefix(HELLO) {
+                        self.accept_hello(hello)?;
+                        self.handshake = Handshake::Acknowledge;
+                        continue;
+                    }
+                    self.handshake = Handshake::Done;
+                }
+                Handshake::Offer | Handshake::Offered => {
+                    self.handshake = Handshake::Done;
+                    if bytes == self.hello() {
+                        continue;
+                    }
+                    // The server never read the offer, e.g. it turned the
+                    // connection away right away, so it still speaks bincode.
+                    self.encoding = Encoding::Bincode;
+                    self.compression = None;
+                }
+                Handshake::Acknowledge | Handshake::Done => {}
+            }
+
+            let Some(compression) = self.compression else {
+                return self.encoding.decode(&bytes).map(Some);
+            };
+            return match bytes.split_first() {
+                Some((&UNCOMPRESSED, frame)) => self.encoding.decode(frame).map(Some),
+                Some((&COMPRESSED, frame)) => {
+                    let frame = compression.decompress(frame, self.inner.max_frame_length())?;
+                    self.encoding.decode(&frame).map(Some)
+                }
+                _ => Err(ProtocolError::Decode),
+            };
+        }
     }
 }
 
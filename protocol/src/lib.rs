@@ -1,5 +1,11 @@
+pub mod compression;
+pub mod encoding;
 pub mod errors;
-pub use errors::{ConnectionError, ProtocolError, Result};
+pub use compression::Compression;
+pub use encoding::Encoding;
+pub use errors::{ConnectionError, ErrorCode, ProtocolError, Result};
 pub use frame::*;
 
 pub mod frame;
+pub mod quic;
+pub mod tls;
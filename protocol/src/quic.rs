@@ -0,0 +1,164 @@
+use std::{
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use quinn::{
+    crypto::rustls::{QuicClientConfig, QuicServerConfig},
+    ClientConfig, Connection, Endpoint, RecvStream, SendStream, ServerConfig,
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::{
+    tls::{self, Trust},
+    ConnectionError,
+};
+
+/// ALPN protocol id of the chat over QUIC.
+pub const ALPN: &[u8] = b"chat";
+
+fn quic_error(e: impl std::fmt::Display) -> ConnectionError {
+    ConnectionError::Quic(e.to_string())
+}
+
+/// Binds a QUIC endpoint on `addr` using the same certificate as the TLS
+/// listener. Clients may migrate to a new address, e.g. when roaming between
+/// networks, without losing their session.
+pub fn server_endpoint(
+    addr: SocketAddr,
+    tls: &rustls::ServerConfig,
+) -> Result<Endpoint, ConnectionError> {
+    let mut tls = tls.clone();
+    tls.alpn_protocols = vec![ALPN.to_vec()];
+
+    let crypto = QuicServerConfig::try_from(tls).map_err(quic_error)?;
+    let mut config = ServerConfig::with_crypto(Arc::new(crypto));
+    config.migration(true);
+
+    Ok(Endpoint::server(config, addr)?)
+}
+
+/// Connects to a QUIC chat server and opens the bidirectional stream the
+/// session runs on.
+pub async fn connect(
+    addr: SocketAddr,
+    server_name: &str,
+    trust: &Trust,
+) -> Result<QuicStream, ConnectionError> {
+    let mut tls = (*tls::client_config(trust)?).clone();
+    tls.alpn_protocols = vec![ALPN.to_vec()];
+    let crypto = QuicClientConfig::try_from(tls).map_err(quic_error)?;
+
+    let bind: SocketAddr = if addr.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        ([0u16; 8], 0).into()
+    };
+    let endpoint = Endpoint::client(bind)?;
+    let connection = endpoint
+        .connect_with(ClientConfig::new(Arc::new(crypto)), addr, server_name)
+        .map_err(quic_error)?
+        .await
+        .map_err(quic_error)?;
+    let (send, recv) = connection.open_bi().await.map_err(quic_error)?;
+
+    Ok(QuicStream {
+        endpoint,
+        connection,
+        stream: BiStream::new(send, recv),
+    })
+}
+
+/// Client side of a chat session over QUIC, usable wherever a byte stream is.
+pub struct QuicStream {
+    endpoint: Endpoint,
+    connection: Connection,
+    stream: BiStream,
+}
+
+impl QuicStream {
+    /// Moves the connection to a new local socket, as happens when a device
+    /// switches networks. The session continues on the new path.
+    pub fn rebind(&self, socket: std::net::UdpSocket) -> io::Result<()> {
+        self.endpoint.rebind(socket)
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.endpoint.local_addr()
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+}
+
+impl AsyncRead for QuicStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for QuicStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+/// Both halves of a bidirectional QUIC stream as one byte stream.
+pub struct BiStream {
+    send: SendStream,
+    recv: RecvStream,
+}
+
+impl BiStream {
+    pub fn new(send: SendStream, recv: RecvStream) -> Self {
+        Self { send, recv }
+    }
+}
+
+impl AsyncRead for BiStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.recv).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for BiStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        AsyncWrite::poll_write(Pin::new(&mut self.send), cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_flush(Pin::new(&mut self.send), cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_shutdown(Pin::new(&mut self.send), cx)
+    }
+}
@@ -0,0 +1,162 @@
+use std::{path::Path, sync::Arc};
+
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName, UnixTime},
+    ClientConfig, DigitallySignedStruct, RootCertStore, ServerConfig, SignatureScheme,
+};
+use sha2::{Digest, Sha256};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio_rustls::{client::TlsStream, TlsConnector};
+
+use crate::ConnectionError;
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+/// Builds the server side TLS configuration from PEM encoded certificate
+/// chain and private key files.
+pub fn server_config(
+    cert: impl AsRef<Path>,
+    key: impl AsRef<Path>,
+) -> Result<Arc<ServerConfig>, ConnectionError> {
+    let certs = CertificateDer::pem_file_iter(cert.as_ref())
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| ConnectionError::Certificate(format!("{}: {e}", cert.as_ref().display())))?;
+    let key = PrivateKeyDer::from_pem_file(key.as_ref())
+        .map_err(|e| ConnectionError::Certificate(format!("{}: {e}", key.as_ref().display())))?;
+
+    let config = ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    Ok(Arc::new(config))
+}
+
+/// How a client decides to trust the server certificate.
+#[derive(Debug, Clone)]
+pub enum Trust {
+    /// Verify the chain against the CA certificates in this PEM file.
+    Ca(std::path::PathBuf),
+    /// Accept only the certificate with this SHA-256 fingerprint, used for
+    /// self-signed server certificates.
+    Pin(String),
+}
+
+/// Builds the client side TLS configuration.
+pub fn client_config(trust: &Trust) -> Result<Arc<ClientConfig>, ConnectionError> {
+    let builder =
+        ClientConfig::builder_with_provider(provider()).with_safe_default_protocol_versions()?;
+
+    let config = match trust {
+        Trust::Ca(path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in CertificateDer::pem_file_iter(path)
+                .map_err(|e| ConnectionError::Certificate(format!("{}: {e}", path.display())))?
+            {
+                let cert = cert.map_err(|e| {
+                    ConnectionError::Certificate(format!("{}: {e}", path.display()))
+                })?;
+                roots.add(cert)?;
+            }
+            builder.with_root_certificates(roots).with_no_client_auth()
+        }
+        Trust::Pin(fingerprint) => builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(PinnedCertVerifier::new(fingerprint)))
+            .with_no_client_auth(),
+    };
+    Ok(Arc::new(config))
+}
+
+/// Opens a TCP connection to `addr` and performs the TLS handshake.
+pub async fn connect(
+    addr: impl ToSocketAddrs,
+    server_name: &str,
+    config: Arc<ClientConfig>,
+) -> Result<TlsStream<TcpStream>, ConnectionError> {
+    let server_name = ServerName::try_from(server_name.to_owned())
+        .map_err(|e| ConnectionError::Certificate(e.to_string()))?;
+    let stream = TcpStream::connect(addr).await?;
+    Ok(TlsConnector::from(config)
+        .connect(server_name, stream)
+        .await?)
+}
+
+/// Hex encoded SHA-256 digest of a DER certificate, the format expected by
+/// `Trust::Pin`.
+pub fn fingerprint(cert: &CertificateDer<'_>) -> String {
+    hex::encode(Sha256::digest(cert.as_ref()))
+}
+
+/// Accepts exactly one end-entity certificate, identified by its fingerprint.
+/// Handshake signatures are still verified so the peer has to own the key.
+#[derive(Debug)]
+struct PinnedCertVerifier {
+    fingerprint: String,
+    provider: Arc<CryptoProvider>,
+}
+
+impl PinnedCertVerifier {
+    fn new(fingerprint: &str) -> Self {
+        Self {
+            fingerprint: fingerprint.replace(':', "").to_lowercase(),
+            provider: provider(),
+        }
+    }
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if fingerprint(end_entity) == self.fingerprint {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificate(
+                rustls::CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
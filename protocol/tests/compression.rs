@@ -0,0 +1,124 @@
+use bytes::{BufMut, BytesMut};
+use protocol::{Channel, ChatCodec, Compression, Encoding, Frame, Message, User};
+use tokio_util::codec::{Decoder, Encoder};
+
+/// A history dump the size servers send on connect.
+fn history(messages: usize) -> Frame {
+    let words = [
+        "hello", "anyone", "around", "deploy", "finished", "lunch", "meeting", "tomorrow",
+        "thanks", "review", "please", "looks", "good", "to", "me", "the", "build", "is", "green",
+    ];
+    let messages: Vec<Message> = (0..messages)
+        .map(|i| {
+            let body = (0..8 + i % 13)
+                .map(|j| words[(i * 7 + j * 3) % words.len()])
+                .collect::<Vec<_>>()
+                .join(" ");
+            Message::new(
+                User {
+                    username: format!("user{}", i % 10),
+                    color: Some("#ff8800".to_string()),
+                    avatar: Some(format!("https://example.com/avatars/{}.png", i % 10)),
+                    bot: false,
+                },
+                "default".to_string(),
+                body,
+            )
+        })
+        .collect();
+    let channel = Channel {
+        name: "default".to_string(),
+        messages: messages.clone(),
+        cover: None,
+    };
+    Frame::Bulk(messages, vec![channel])
+}
+
+/// Sends `frame` from a client negotiating `compression` and back, returning
+/// the bytes it took on the wire each way.
+fn round_trip(encoding: Encoding, compression: Compression, frame: &Frame) -> (usize, usize) {
+    let mut client = ChatCodec::with_encoding(encoding).with_compression(compression);
+    let mut server = ChatCodec::new();
+
+    let mut wire = BytesMut::new();
+    client.encode(frame.clone(), &mut wire).unwrap();
+    let sent = wire.len();
+    assert_eq!(server.decode(&mut wire).unwrap().as_ref(), Some(frame));
+    assert_eq!(server.compression(), Some(compression));
+    assert_eq!(server.encoding(), encoding);
+
+    server.encode(frame.clone(), &mut wire).unwrap();
+    let received = wire.len();
+    assert_eq!(client.decode(&mut wire).unwrap().as_ref(), Some(frame));
+    assert_eq!(client.compression(), Some(compression));
+
+    (sent, received)
+}
+
+#[test]
+fn large_frames_shrink_with_every_compression() {
+    let frame = history(500);
+    for encoding in Encoding::ALL {
+        let plain = encoding.encode(&frame).unwrap().len();
+        for compression in Compression::ALL {
+            let (sent, received) = round_trip(encoding, compression, &frame);
+            assert!(
+                received * 4 < plain,
+                "{encoding}+{compression}: {received} of {plain} bytes"
+            );
+            assert!(
+                sent * 4 < plain,
+                "{encoding}+{compression}: {sent} of {plain} bytes"
+            );
+        }
+    }
+}
+
+#[test]
+fn small_frames_are_sent_as_is() {
+    let frame = Frame::Pong(1);
+    let mut client = ChatCodec::new().with_compression(Compression::Zstd);
+    let mut server = ChatCodec::new();
+
+    let mut wire = BytesMut::new();
+    client.encode(Frame::Pong(0), &mut wire).unwrap();
+    server.decode(&mut wire).unwrap();
+
+    server.encode(frame.clone(), &mut wire).unwrap();
+    let hello = b"chat-encoding:bincode;compression=zstd";
+    let plain = Encoding::Bincode.encode(&frame).unwrap();
+    // Length prefixed hello, then the length prefixed, flagged frame.
+    assert_eq!(&wire[4..4 + hello.len()], hello);
+    assert_eq!(wire[8 + hello.len()], 0);
+    assert_eq!(&wire[9 + hello.len()..], &plain[..]);
+
+    assert_eq!(client.decode(&mut wire).unwrap(), Some(frame));
+}
+
+#[test]
+fn oversized_decompression_is_rejected() {
+    let mut server = ChatCodec::new();
+    let mut wire = BytesMut::new();
+    let hello = b"chat-encoding:bincode;compression=zstd";
+    wire.put_u32(hello.len() as u32);
+    wire.put_slice(hello);
+
+    // 64 MiB of zeroes compress to a few kilobytes.
+    let bomb = Compression::Zstd.compress(&vec![0; 64 << 20]).unwrap();
+    wire.put_u32(bomb.len() as u32 + 1);
+    wire.put_u8(1);
+    wire.put_slice(&bomb);
+
+    assert!(server.decode(&mut wire).is_err());
+}
+
+#[test]
+fn unknown_compressions_are_rejected() {
+    let mut server = ChatCodec::new();
+    let mut wire = BytesMut::new();
+    let hello = b"chat-encoding:json;compression=lz4";
+    wire.put_u32(hello.len() as u32);
+    wire.put_slice(hello);
+
+    assert!(server.decode(&mut wire).is_err());
+}
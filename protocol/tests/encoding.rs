@@ -0,0 +1,250 @@
+use std::time::Duration;
+
+use bytes::{BufMut, BytesMut};
+use chrono::{TimeZone, Utc};
+use protocol::{Channel, ChatCodec, Encoding, ErrorCode, Frame, Message, ProtocolError, User};
+use tokio_util::codec::{Decoder, Encoder};
+
+fn user(name: &str) -> User {
+    User {
+        username: name.to_string(),
+        color: Some("blue".to_string()),
+        avatar: None,
+        bot: false,
+    }
+}
+
+fn message(body: &str) -> Message {
+    Message::new(user("alice"), "default".to_string(), body.to_string())
+}
+
+fn channel() -> Channel {
+    Channel {
+        name: "default".to_string(),
+        messages: vec![message("first"), message("second")],
+        cover: Some("https://example.com/cover.png".to_string()),
+    }
+}
+
+/// One frame of every variant. The match makes adding a variant without a
+/// sample here a compile error.
+fn every_frame() -> Vec<Frame> {
+    let frames = vec![
+        Frame::Authorize(user("alice")),
+        Frame::Connect(vec![channel()]),
+        Frame::Message(message("hello")),
+        Frame::Message(Message {
+            ttl: Some(Duration::from_secs(30)),
+            expires: Some(Utc.with_ymd_and_hms(2023, 2, 1, 0, 0, 30).unwrap()),
+            ..message("gone soon")
+        }),
+        Frame::Bulk(vec![message("hello")], vec![channel()]),
+        Frame::Channel(channel()),
+        Frame::Ok,
+        Frame::error(ErrorCode::ServerBusy, "max connections reached"),
+        Frame::Disconnect(user("bob")),
+        Frame::ServerShutdown {
+            reason: "server is shutting down".to_string(),
+            reconnect_after: Some(Duration::from_secs(10)),
+        },
+        Frame::Ping {
+            id: 42,
+            interval: Duration::from_millis(15_500),
+        },
+        Frame::Pong(42),
+        Frame::Join("default".to_string()),
+        Frame::Joined(channel()),
+        Frame::Leave("default".to_string()),
+        Frame::History {
+            channel: "default".to_string(),
+            before: Some(100),
+            limit: 50,
+        },
+        Frame::HistoryPage {
+            channel: "default".to_string(),
+            messages: vec![message("old")],
+        },
+        Frame::Search {
+            query: "release notes".to_string(),
+            channel: Some("default".to_string()),
+            from: Some("alice".to_string()),
+            before: Some(Utc.with_ymd_and_hms(2023, 2, 1, 0, 0, 0).unwrap()),
+            after: None,
+            cursor: Some(100),
+            limit: 20,
+        },
+        Frame::SearchResults {
+            query: "release notes".to_string(),
+            messages: vec![message("the release notes are out")],
+            next: Some(12),
+        },
+        Frame::React {
+            from: User {
+                bot: true,
+                ..user("echo")
+            },
+            channel: "default".to_string(),
+            seq: 3,
+            emoji: "👍".to_string(),
+        },
+        Frame::Deleted {
+            channel: "default".to_string(),
+            seqs: vec![1, 2, 5],
+        },
+        Frame::PeerJoined {
+            channel: "default".to_string(),
+            user: user("bob"),
+        },
+        Frame::PeerLeft {
+            channel: "default".to_string(),
+            user: user("bob"),
+        },
+        Frame::request(7, Frame::Message(message("acked"))),
+        Frame::Ack(7),
+        Frame::Rejected {
+            id: 8,
+            code: ErrorCode::NotFound,
+            reason: "no channel named nowhere".to_string(),
+        },
+    ];
+    for frame in &frames {
+        match frame {
+            Frame::Authorize(_)
+            | Frame::Connect(_)
+            | Frame::Message(_)
+            | Frame::Bulk(..)
+            | Frame::Channel(_)
+            | Frame::Ok
+            | Frame::Error { .. }
+            | Frame::Disconnect(_)
+            | Frame::ServerShutdown { .. }
+            | Frame::Ping { .. }
+            | Frame::Pong(_)
+            | Frame::Join(_)
+            | Frame::Joined(_)
+            | Frame::Leave(_)
+            | Frame::History { .. }
+            | Frame::HistoryPage { .. }
+            | Frame::Search { .. }
+            | Frame::SearchResults { .. }
+            | Frame::React { .. }
+            | Frame::Deleted { .. }
+            | Frame::PeerJoined { .. }
+            | Frame::PeerLeft { .. }
+            | Frame::Request { .. }
+            | Frame::Ack(_)
+            | Frame::Rejected { .. } => {}
+        }
+    }
+    frames
+}
+
+#[test]
+fn every_frame_round_trips_in_every_encoding() {
+    for encoding in Encoding::ALL {
+        for frame in every_frame() {
+            let bytes = encoding.encode(&frame).unwrap();
+            let decoded = encoding
+                .decode(&bytes)
+                .unwrap_or_else(|e| panic!("{encoding}: {frame:?} did not decode: {e}"));
+            assert_eq!(decoded, frame, "{encoding}");
+        }
+    }
+}
+
+#[test]
+fn every_frame_round_trips_through_negotiated_codecs() {
+    let frames = every_frame();
+    for encoding in Encoding::ALL {
+        let mut client = ChatCodec::with_encoding(encoding);
+        let mut server = ChatCodec::new();
+
+        let mut wire = BytesMut::new();
+        for frame in frames.iter().cloned() {
+            client.encode(frame, &mut wire).unwrap();
+        }
+        for frame in frames.iter().cloned() {
+            assert_eq!(server.decode(&mut wire).unwrap(), Some(frame), "{encoding}");
+        }
+        assert_eq!(server.decode(&mut wire).unwrap(), None);
+        assert_eq!(server.encoding(), encoding);
+
+        for frame in frames.iter().cloned() {
+            server.encode(frame, &mut wire).unwrap();
+        }
+        for frame in frames.iter().cloned() {
+            assert_eq!(client.decode(&mut wire).unwrap(), Some(frame), "{encoding}");
+        }
+        assert_eq!(client.encoding(), encoding);
+    }
+}
+
+#[test]
+fn clients_that_do_not_negotiate_speak_bincode() {
+    let mut client = ChatCodec::new();
+    let mut server = ChatCodec::new();
+
+    let mut wire = BytesMut::new();
+    client.encode(Frame::Pong(7), &mut wire).unwrap();
+    // Length prefix followed by the plain bincode frame.
+    assert_eq!(
+        &wire[4..],
+        &Encoding::Bincode.encode(&Frame::Pong(7)).unwrap()[..]
+    );
+
+    assert_eq!(server.decode(&mut wire).unwrap(), Some(Frame::Pong(7)));
+    assert_eq!(server.encoding(), Encoding::Bincode);
+}
+
+#[test]
+fn client_falls_back_when_server_answers_without_negotiating() {
+    let mut client = ChatCodec::with_encoding(Encoding::Json);
+    let mut server = ChatCodec::new();
+
+    // The server turns the connection away before reading anything.
+    let mut wire = BytesMut::new();
+    let error = Frame::error(ErrorCode::ServerBusy, "max connections reached");
+    server.encode(error.clone(), &mut wire).unwrap();
+
+    assert_eq!(client.decode(&mut wire).unwrap(), Some(error));
+    assert_eq!(client.encoding(), Encoding::Bincode);
+}
+
+#[test]
+fn unknown_encodings_are_rejected() {
+    let mut server = ChatCodec::new();
+    let mut wire = BytesMut::new();
+    let hello = b"chat-encoding:xml";
+    wire.put_u32(hello.len() as u32);
+    wire.put_slice(hello);
+
+    assert!(matches!(
+        server.decode(&mut wire),
+        Err(ProtocolError::UnsupportedEncoding(name)) if name == "xml"
+    ));
+}
+
+#[test]
+fn json_frames_are_readable() {
+    let bytes = Encoding::Json.encode(&Frame::Pong(7)).unwrap();
+    assert_eq!(&bytes[..], br#"{"Pong":7}"#);
+
+    let frame = Encoding::Json
+        .decode(br#"{"Error":{"code":"Unauthorized","reason":"who are you?"}}"#)
+        .unwrap();
+    assert_eq!(frame, Frame::error(ErrorCode::Unauthorized, "who are you?"));
+
+    // Clients that predate bot accounts leave the flag out.
+    let frame = Encoding::Json
+        .decode(br#"{"Authorize":{"username":"alice","color":"blue","avatar":null}}"#)
+        .unwrap();
+    assert_eq!(frame, Frame::Authorize(user("alice")));
+}
+
+#[test]
+fn encodings_parse_from_their_names() {
+    for encoding in Encoding::ALL {
+        assert_eq!(encoding.name().parse::<Encoding>().unwrap(), encoding);
+    }
+    assert!("yaml".parse::<Encoding>().is_err());
+}
@@ -0,0 +1,204 @@
+use std::io::{self, BufRead, Write};
+
+use chrono::{DateTime, Utc};
+use protocol::Message;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{retention::Retention, server::Reaction};
+
+/// Version written into every archive, archives of newer versions are
+/// refused.
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// One channel with its history, as exported by `Server::export` and
+/// imported by `Server::import`.
+///
+/// Stored as JSON Lines: a `channel` record first, then every message,
+/// oldest first, then every reaction, e.g.
+///
+/// ```text
+/// {"type":"channel","version":1,"name":"ops","cover":null,"retention":{...},"exported":"..."}
+/// {"type":"message","from":{...},"channel":"ops","body":"deploying","created":"...","seq":7,...}
+/// {"type":"reaction","seq":7,"from":{...},"emoji":"🚀"}
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Archive {
+    pub channel: ChannelMeta,
+    pub messages: Vec<Message>,
+    pub reactions: Vec<Reaction>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ChannelMeta {
+    pub version: u32,
+    pub name: String,
+    pub cover: Option<String>,
+    #[serde(default)]
+    pub retention: Retention,
+    pub exported: DateTime<Utc>,
+}
+
+/// One line of an archive.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Record {
+    Channel(ChannelMeta),
+    Message(Message),
+    Reaction(Reaction),
+}
+
+#[derive(Debug, Error)]
+pub enum ArchiveError {
+    #[error("failed to access the archive: {0}")]
+    Io(#[from] io::Error),
+    #[error("line {line} is not a valid record: {source}")]
+    Json {
+        line: usize,
+        source: serde_json::Error,
+    },
+    #[error("the archive does not start with a channel record")]
+    MissingChannel,
+    #[error("line {0} is a second channel record")]
+    SecondChannel(usize),
+    #[error("archive version {0} is newer than this server understands")]
+    UnsupportedVersion(u32),
+}
+
+impl Archive {
+    pub fn write_jsonl(&self, mut out: impl Write) -> io::Result<()> {
+        let channel = Record::Channel(self.channel.clone());
+        let messages = self.messages.iter().cloned().map(Record::Message);
+        let reactions = self.reactions.iter().cloned().map(Record::Reaction);
+        for record in std::iter::once(channel).chain(messages).chain(reactions) {
+            serde_json::to_writer(&mut out, &record)?;
+            out.write_all(b"\n")?;
+        }
+        out.flush()
+    }
+
+    /// Reads an archive written by `write_jsonl`, blank lines are skipped.
+    pub fn read_jsonl(input: impl BufRead) -> Result<Archive, ArchiveError> {
+        let mut channel = None;
+        let mut messages = vec![];
+        let mut reactions = vec![];
+        for (index, line) in input.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record = serde_json::from_str(&line).map_err(|source| ArchiveError::Json {
+                line: index + 1,
+                source,
+            })?;
+            match record {
+                Record::Channel(_) if channel.is_some() => {
+                    return Err(ArchiveError::SecondChannel(index + 1));
+                }
+                Record::Channel(meta) if meta.version > ARCHIVE_VERSION => {
+                    return Err(ArchiveError::UnsupportedVersion(meta.version));
+                }
+                Record::Channel(meta) => channel = Some(meta),
+                _ if channel.is_none() => return Err(ArchiveError::MissingChannel),
+                Record::Message(message) => messages.push(message),
+                Record::Reaction(reaction) => reactions.push(reaction),
+            }
+        }
+        Ok(Archive {
+            channel: channel.ok_or(ArchiveError::MissingChannel)?,
+            messages,
+            reactions,
+        })
+    }
+
+    /// Writes a standalone, human readable transcript of the channel.
+    pub fn write_html(&self, mut out: impl Write) -> io::Result<()> {
+        let name = escape(&self.channel.name);
+        writeln!(out, "<!DOCTYPE html>")?;
+        writeln!(out, "<html lang=\"en\">")?;
+        writeln!(out, "<head>")?;
+        writeln!(out, "<meta charset=\"utf-8\">")?;
+        writeln!(out, "<title>#{name}</title>")?;
+        writeln!(out, "<style>{STYLE}</style>")?;
+        writeln!(out, "</head>")?;
+        writeln!(out, "<body>")?;
+        writeln!(out, "<header>")?;
+        if let Some(cover) = &self.channel.cover {
+            writeln!(
+                out,
+                "<img class=\"cover\" src=\"{}\" alt=\"\">",
+                escape(cover)
+            )?;
+        }
+        writeln!(out, "<h1>#{name}</h1>")?;
+        writeln!(
+            out,
+            "<p>{} messages, exported {}, kept {}</p>",
+            self.messages.len(),
+            self.channel.exported.format("%Y-%m-%d %H:%M UTC"),
+            self.channel.retention
+        )?;
+        writeln!(out, "</header>")?;
+        writeln!(out, "<main>")?;
+        for message in &self.messages {
+            let bot = if message.from.bot {
+                " <span class=\"bot\">bot</span>"
+            } else {
+                ""
+            };
+            writeln!(out, "<article id=\"m{}\">", message.seq)?;
+            writeln!(
+                out,
+                "<p class=\"meta\"><strong>{}</strong>{bot} <time datetime=\"{}\">{}</time></p>",
+                escape(&message.from.username),
+                message.created.to_rfc3339(),
+                message.created.format("%Y-%m-%d %H:%M")
+            )?;
+            writeln!(out, "<p class=\"body\">{}</p>", escape(&message.body))?;
+            let reactions: Vec<String> = self
+                .reactions
+                .iter()
+                .filter(|reaction| reaction.seq == message.seq)
+                .map(|reaction| {
+                    format!(
+                        "<li title=\"{}\">{}</li>",
+                        escape(&reaction.from.username),
+                        escape(&reaction.emoji)
+                    )
+                })
+                .collect();
+            if !reactions.is_empty() {
+                writeln!(out, "<ul class=\"reactions\">{}</ul>", reactions.join(""))?;
+            }
+            writeln!(out, "</article>")?;
+        }
+        writeln!(out, "</main>")?;
+        writeln!(out, "</body>")?;
+        writeln!(out, "</html>")?;
+        out.flush()
+    }
+}
+
+const STYLE: &str = "body{font-family:sans-serif;max-width:48rem;margin:2rem auto;color:#374151}\
+    .cover{width:4rem;height:4rem;border-radius:9999px;float:left;margin-right:1rem}\
+    article{border-top:1px solid #e5e7eb;padding:.5rem 0}\
+    .meta{margin:0;color:#6b7280}.meta strong{color:#111827}\
+    .body{margin:.25rem 0;white-space:pre-wrap}\
+    .bot{background:#8b5cf6;color:#fff;border-radius:.25rem;padding:0 .25rem;font-size:.75rem}\
+    .reactions{list-style:none;display:flex;gap:.5rem;padding:0;margin:0}";
+
+/// `text` with the characters that mean something in HTML escaped.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
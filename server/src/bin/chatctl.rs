@@ -0,0 +1,106 @@
+use clap::Parser;
+use server::cli::{CtlCli, CtlCommand};
+use server::control::{ControlClient, Response};
+use std::{error::Error, process::ExitCode};
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run(CtlCli::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("chatctl: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(args: CtlCli) -> Result<(), Box<dyn Error>> {
+    // The server resolves paths against its own working directory.
+    let command = match args.command {
+        CtlCommand::Snapshot { path } => CtlCommand::Snapshot {
+            path: std::path::absolute(path)?,
+        },
+        CtlCommand::Export { name, path, html } => CtlCommand::Export {
+            name,
+            path: std::path::absolute(path)?,
+            html,
+        },
+        CtlCommand::Import { path, name } => CtlCommand::Import {
+            path: std::path::absolute(path)?,
+            name,
+        },
+        command => command,
+    };
+
+    let mut control = ControlClient::connect(&args.socket).await?;
+    match control.request(&command.into()).await? {
+        Response::Done => println!("done"),
+        Response::Sessions(sessions) => {
+            for session in sessions {
+                println!(
+                    "{:>4}  {:<20} {:<24} {}  {}",
+                    session.id,
+                    session.user.username,
+                    session.addr,
+                    session.connected.format("%Y-%m-%d %H:%M:%S"),
+                    session.channels.join(","),
+                );
+            }
+        }
+        Response::Channels(channels) => {
+            for channel in channels {
+                println!(
+                    "{:<20} {:>4} members {:>7} messages  kept {}",
+                    channel.name, channel.members, channel.messages, channel.retention
+                );
+            }
+        }
+        Response::Channel(state) => {
+            println!("name:     {}", state.info.name);
+            if let Some(cover) = &state.info.cover {
+                println!("cover:    {cover}");
+            }
+            println!("messages: {}", state.info.messages);
+            println!("kept:     {}", state.info.retention);
+            if let Some(seq) = state.info.last_seq {
+                println!("last seq: {seq}");
+            }
+            println!("members:  {}", state.members.join(", "));
+            for message in state.recent {
+                println!(
+                    "{:>6} {} {}: {}",
+                    message.seq,
+                    message.created.format("%Y-%m-%d %H:%M"),
+                    message.from.username,
+                    message.body
+                );
+            }
+        }
+        Response::Kicked(sessions) if sessions.is_empty() => println!("no session kicked"),
+        Response::Kicked(sessions) => {
+            let ids: Vec<String> = sessions.iter().map(u64::to_string).collect();
+            println!("kicked {}", ids.join(", "));
+        }
+        Response::Bans(bans) => {
+            for username in bans {
+                println!("{username}");
+            }
+        }
+        Response::Snapshot {
+            path,
+            channels,
+            messages,
+        } => println!(
+            "wrote {channels} channels with {messages} messages to {}",
+            path.display()
+        ),
+        Response::Exported { path, messages } => {
+            println!("wrote {messages} messages to {}", path.display())
+        }
+        Response::Imported { name, messages } => {
+            println!("imported {name} with {messages} messages")
+        }
+        Response::Error { code, reason } => return Err(format!("{code}: {reason}").into()),
+    }
+    Ok(())
+}
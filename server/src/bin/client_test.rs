@@ -1,69 +1,93 @@
-use bytes::Bytes;
-use futures::{SinkExt, StreamExt};
-use tokio::net::TcpStream;
-use tokio_util::codec::{BytesCodec, Framed};
+use chat_client::{Client, ClientError, Config, Event};
+use futures::StreamExt;
 
 use clap::Parser;
-use protocol::{Frame, Message, User};
+use protocol::{tls::Trust, User};
 use server::cli::Cli;
 use std::error::Error;
 
 #[tokio::main]
 pub async fn main() -> Result<(), Box<dyn Error>> {
-    // Open a TCP stream to the socket address.
-    //
-    // Note that this is the Tokio TcpStream, which is fully async.
-    let stream = TcpStream::connect("127.0.0.1:9999").await?;
-    let chat = Framed::new(stream, BytesCodec::new());
-    let (mut sink, mut stream) = chat.split();
-
     let args = Cli::parse();
 
+    // Connect over TLS if the server certificate is to be verified, over
+    // QUIC if asked to, and plain TCP otherwise.
+    let mut config = Config::new(args.addr);
+    config.trust = match (&args.tls_ca, &args.tls_pin) {
+        (Some(ca), _) => Some(Trust::Ca(ca.to_owned())),
+        (_, Some(pin)) => Some(Trust::Pin(pin.to_owned())),
+        _ => None,
+    };
+    config.server_name = args.server_name;
+    config.quic = args.quic;
+    config.encoding = args.encoding;
+    config.compression = args.compression;
+    let client = Client::connect(config).await?;
+
     let user = User {
         username: args.user,
         color: args.color,
-        avatar: Some("https://images.unsplash.com/photo-1675456110416-53a9df455bae?ixlib=rb-4.0.3&ixid=MnwxMjA3fDB8MHxwaG90by1wYWdlfHx8fGVufDB8fHx8&auto=format&fit=crop&w=687&q=80".to_string())
+        avatar: Some("https://images.unsplash.com/photo-1675456110416-53a9df455bae?ixlib=rb-4.0.3&ixid=MnwxMjA3fDB8MHxwaG90by1wYWdlfHx8fGVufDB8fHx8&auto=format&fit=crop&w=687&q=80".to_string()),
+        bot: false,
     };
 
+    let mut events = Box::pin(client.events());
     tokio::spawn(async move {
-        loop {
-            let resp = stream.next().await.unwrap().unwrap();
-            let message: Frame = resp.freeze().try_into().unwrap();
-            match message {
-                Frame::Message(message) => {
+        while let Some(event) = events.next().await {
+            match event {
+                Event::Message(message) => {
                     println!("{}\x07", &message);
                 }
-                Frame::Bulk(messages, _) => {
-                    for message in messages.iter() {
-                        println!("{}\x07", &message);
-                    }
+                Event::Channels(_) => {}
+                Event::Left(user) => {
+                    println!("{} left", user.username);
                 }
-                Frame::Error(err) => {
-                    println!("err: {err}");
-                    break;
+                Event::Reaction { from, emoji, .. } => {
+                    println!("{} reacted {emoji}", from.username);
                 }
-                _ => {
-                    println!("idk: {message:?}");
+                Event::Deleted { seqs, .. } => {
+                    println!("{} message(s) deleted", seqs.len());
                 }
+                Event::Error { code, reason } => {
+                    println!("err: {code}: {reason}");
+                }
+                Event::ServerShutdown {
+                    reason,
+                    reconnect_after,
+                } => match reconnect_after {
+                    Some(after) => println!(
+                        "server shutdown: {reason}, reconnect after {}s",
+                        after.as_secs()
+                    ),
+                    None => println!("server shutdown: {reason}"),
+                },
+                Event::Disconnected => println!("disconnected, reconnecting"),
+                Event::Reconnected(_) => println!("reconnected"),
             }
         }
     });
 
-    let connect_message = Frame::Authorize(user.clone());
-
     let room = args.room.unwrap();
 
-    let bytes: Bytes = connect_message.try_into().unwrap();
-
-    let _ = sink.send(bytes).await;
+    // Catch up on what was said in the room so far.
+    for channel in client.login(user).await? {
+        if channel.name == room {
+            for message in channel.messages.iter() {
+                println!("{}\x07", &message);
+            }
+        }
+    }
 
     loop {
         let mut inp = String::new();
         std::io::stdin().read_line(&mut inp).unwrap();
         let inp = inp.trim().to_owned();
-        let message = Frame::Message(Message::new(user.clone(), room.to_owned(), inp));
 
-        let bytes: Bytes = message.try_into().unwrap();
-        let _ = sink.send(bytes).await;
+        match client.send(&room, &inp).await {
+            // Our own message is printed along with everyone else's.
+            Ok(_) => {}
+            Err(ClientError::Rejected { reason, .. }) => println!("message failed: {reason}"),
+            Err(e) => println!("message failed: {e}"),
+        }
     }
 }
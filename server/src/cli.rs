@@ -1,4 +1,9 @@
-use clap::Parser;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use protocol::{Compression, Encoding};
+
+use crate::{control::Request, retention::Retention, webhooks::Webhook};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -9,4 +14,211 @@ pub struct Cli {
     pub color: Option<String>,
     #[arg(short, long)]
     pub room: Option<String>,
+    #[arg(short, long, default_value = "127.0.0.1:9999")]
+    pub addr: String,
+    /// Connect over TLS and verify the server against this CA certificate.
+    #[arg(long)]
+    pub tls_ca: Option<PathBuf>,
+    /// Connect over TLS and accept only the server certificate with this
+    /// SHA-256 fingerprint.
+    #[arg(long, conflicts_with = "tls_ca")]
+    pub tls_pin: Option<String>,
+    #[arg(long, default_value = "localhost")]
+    pub server_name: String,
+    /// Connect over QUIC instead of TCP, requires `--tls-ca` or `--tls-pin`.
+    #[arg(long)]
+    pub quic: bool,
+    /// Ask the server to exchange frames as `bincode`, `json` or `msgpack`.
+    #[arg(long)]
+    pub encoding: Option<Encoding>,
+    /// Ask the server to compress large frames with `zstd` or `deflate`.
+    #[arg(long)]
+    pub compression: Option<Compression>,
+}
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+pub struct ServerCli {
+    #[arg(short, long, default_value = "127.0.0.1:9999")]
+    pub addr: String,
+    /// PEM encoded certificate chain, enables TLS together with `--tls-key`.
+    #[arg(long, requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+    /// PEM encoded private key for `--tls-cert`.
+    #[arg(long, requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+    /// Also accept WebSocket clients on this address.
+    #[arg(long)]
+    pub ws_addr: Option<String>,
+    /// Also accept QUIC clients on this UDP address, using the TLS
+    /// certificate.
+    #[arg(long, requires = "tls_cert")]
+    pub quic_addr: Option<String>,
+    /// Also accept local clients on a Unix socket at this path.
+    #[arg(long)]
+    pub unix_socket: Option<PathBuf>,
+    /// Permissions of the Unix socket file, in octal.
+    #[arg(long, default_value = "660", value_parser = parse_mode)]
+    pub unix_socket_mode: u32,
+    /// Only accept Unix socket clients running as this user id, may be
+    /// repeated.
+    #[arg(long = "unix-allow-uid")]
+    pub unix_allowed_uids: Vec<u32>,
+    /// Serve the HTTP API, e.g. incoming webhooks, on this address.
+    #[arg(long)]
+    pub http_addr: Option<String>,
+    /// Allow posting into CHANNEL over HTTP with `Authorization: Bearer
+    /// TOKEN`, may be repeated.
+    #[arg(long = "incoming-webhook", value_name = "CHANNEL=TOKEN", value_parser = parse_hook_token, requires = "http_addr")]
+    pub hook_tokens: Vec<(String, String)>,
+    /// Allow using the admin API under `/api` with `Authorization: Bearer
+    /// TOKEN`, may be repeated.
+    #[arg(long = "admin-token", value_name = "TOKEN", requires = "http_addr")]
+    pub admin_tokens: Vec<String>,
+    /// Serve Prometheus metrics under `/metrics` on this address, e.g.
+    /// `127.0.0.1:9100`.
+    #[arg(long)]
+    pub metrics_addr: Option<String>,
+    /// POST new messages, joins and channels to this URL as JSON, may be
+    /// repeated. `CHANNEL=URL` only sends the events of one channel.
+    #[arg(long = "webhook", value_name = "[CHANNEL=]URL")]
+    pub webhooks: Vec<Webhook>,
+    /// Sign webhook bodies with HMAC-SHA256 using this secret.
+    #[arg(long)]
+    pub webhook_secret: Option<String>,
+    /// Accept `chatctl` on a Unix socket at this path, usable only by the
+    /// user running the server.
+    #[arg(long)]
+    pub control_socket: Option<PathBuf>,
+    /// Delete the messages of CHANNEL older than an age such as `30d`, beyond
+    /// a number of messages, or both as in `30d,10000`. May be repeated,
+    /// channels without one keep their messages forever.
+    #[arg(long = "retention", value_name = "CHANNEL=RETENTION", value_parser = parse_retention)]
+    pub retention: Vec<(String, Retention)>,
+    /// Add the channel exported to this JSON Lines file on start, see
+    /// `chatctl export`. May be repeated.
+    #[arg(long = "import", value_name = "ARCHIVE")]
+    pub imports: Vec<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<ServerCommand>,
+}
+
+/// Work on exported channels instead of serving.
+///
+/// There is no `export`: channels only live in the memory of a running
+/// server, so they are exported with `chatctl export`. Importing on start is
+/// `--import`, into a running server `chatctl import`.
+#[derive(Subcommand)]
+pub enum ServerCommand {
+    /// Render a channel exported as JSON Lines as an HTML transcript.
+    Transcript { archive: PathBuf, out: PathBuf },
+}
+
+/// Administers a running server through its control socket.
+#[derive(Parser)]
+#[command(name = "chatctl", author, version, about, long_about = None)]
+pub struct CtlCli {
+    /// The `--control-socket` of the server.
+    #[arg(short, long, default_value = "chat-control.sock")]
+    pub socket: PathBuf,
+    #[command(subcommand)]
+    pub command: CtlCommand,
+}
+
+#[derive(Subcommand)]
+pub enum CtlCommand {
+    /// List the logged in users.
+    Sessions,
+    /// List the channels.
+    Channels,
+    /// Show a channel with its members and newest messages.
+    Channel { name: String },
+    /// Post a message as the server, into every channel unless one is given.
+    Notice {
+        body: String,
+        #[arg(long)]
+        channel: Option<String>,
+    },
+    /// Disconnect a session, see `sessions` for the ids.
+    Kick { session: u64 },
+    /// Disconnect a user and refuse their logins until unbanned.
+    Ban { username: String },
+    /// Let a banned user log in again.
+    Unban { username: String },
+    /// List the banned users.
+    Bans,
+    /// Create a channel.
+    Create {
+        name: String,
+        #[arg(long)]
+        cover: Option<String>,
+    },
+    /// Delete a channel and its history.
+    Delete { name: String },
+    /// Set how long a channel keeps its messages: `forever`, an age such as
+    /// `30d`, a number of messages, or both as in `30d,10000`.
+    Retention { name: String, retention: Retention },
+    /// Change the log filter, e.g. `server=debug`.
+    LogLevel { filter: String },
+    /// Write every channel with its history as JSON to a file.
+    Snapshot { path: PathBuf },
+    /// Write a channel with its messages and reactions as JSON Lines to a
+    /// file, or as an HTML transcript.
+    Export {
+        name: String,
+        path: PathBuf,
+        #[arg(long)]
+        html: bool,
+    },
+    /// Add the channel exported to a JSON Lines file, under another name
+    /// with `--as`.
+    Import {
+        path: PathBuf,
+        #[arg(long = "as", value_name = "NAME")]
+        name: Option<String>,
+    },
+}
+
+impl From<CtlCommand> for Request {
+    fn from(command: CtlCommand) -> Self {
+        match command {
+            CtlCommand::Sessions => Request::Sessions,
+            CtlCommand::Channels => Request::Channels,
+            CtlCommand::Channel { name } => Request::Channel { name },
+            CtlCommand::Notice { body, channel } => Request::Notice { body, channel },
+            CtlCommand::Kick { session } => Request::Kick { session },
+            CtlCommand::Ban { username } => Request::Ban { username },
+            CtlCommand::Unban { username } => Request::Unban { username },
+            CtlCommand::Bans => Request::Bans,
+            CtlCommand::Create { name, cover } => Request::CreateChannel { name, cover },
+            CtlCommand::Delete { name } => Request::DeleteChannel { name },
+            CtlCommand::Retention { name, retention } => Request::SetRetention { name, retention },
+            CtlCommand::LogLevel { filter } => Request::LogLevel { filter },
+            CtlCommand::Snapshot { path } => Request::Snapshot { path },
+            CtlCommand::Export { name, path, html } => Request::Export { name, path, html },
+            CtlCommand::Import { path, name } => Request::Import { path, name },
+        }
+    }
+}
+
+fn parse_mode(mode: &str) -> Result<u32, std::num::ParseIntError> {
+    u32::from_str_radix(mode, 8)
+}
+
+fn parse_hook_token(hook: &str) -> Result<(String, String), String> {
+    match hook.split_once('=') {
+        Some((channel, token)) if !channel.is_empty() && !token.is_empty() => {
+            Ok((channel.to_string(), token.to_string()))
+        }
+        _ => Err(format!("{hook} is not CHANNEL=TOKEN")),
+    }
+}
+
+fn parse_retention(retention: &str) -> Result<(String, Retention), String> {
+    match retention.split_once('=') {
+        Some((channel, retention)) if !channel.is_empty() => {
+            Ok((channel.to_string(), retention.parse()?))
+        }
+        _ => Err(format!("{retention} is not CHANNEL=RETENTION")),
+    }
 }
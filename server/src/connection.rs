@@ -0,0 +1,78 @@
+use std::{fmt::Display, net::SocketAddr};
+
+use futures::{Sink, Stream};
+use protocol::{ChatCodec, Frame, ProtocolError};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::codec::Framed;
+
+/// A bidirectional stream of `Frame`s, the only thing `Server` needs from a
+/// transport.
+///
+/// Byte streams (TCP, TLS, Unix sockets, in-memory pipes) become connections
+/// through [`framed`], message based transports such as WebSockets implement
+/// it directly.
+pub trait Connection:
+    Stream<Item = Result<Frame, ProtocolError>> + Sink<Frame, Error = ProtocolError> + Unpin + Send
+{
+}
+
+impl<T> Connection for T where
+    T: Stream<Item = Result<Frame, ProtocolError>>
+        + Sink<Frame, Error = ProtocolError>
+        + Unpin
+        + Send
+{
+}
+
+/// Turns any byte stream into a `Connection` using the chat codec.
+pub fn framed<S>(stream: S) -> Framed<S, ChatCodec>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    Framed::new(stream, ChatCodec::new())
+}
+
+/// Identifies a connected peer independently of the transport it came in on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PeerAddr {
+    /// TCP, TLS and WebSocket peers.
+    Net(SocketAddr),
+    /// Unix socket peers are unnamed, they are numbered as they connect.
+    Unix(u64),
+    /// Every bidirectional QUIC stream is a session of its own, identified by
+    /// the connection and stream ids since the remote address may change.
+    Quic(usize, u64),
+    /// Connections handed to `Server::handle` by the embedding code, e.g.
+    /// in-memory pipes in tests.
+    Local(u64),
+    /// Messages posted over HTTP, such as incoming webhooks. Never a member
+    /// of a channel, so broadcasts from it reach everyone.
+    Http,
+    /// Read-only subscribers of the HTTP event stream, numbered as they
+    /// connect.
+    Subscriber(u64),
+    /// Notices sent through the control socket, never a channel member
+    /// either.
+    Control,
+}
+
+impl PeerAddr {
+    /// Whether this is a read-only event stream rather than a session.
+    pub fn is_subscriber(&self) -> bool {
+        matches!(self, PeerAddr::Subscriber(_))
+    }
+}
+
+impl Display for PeerAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PeerAddr::Net(addr) => write!(f, "{addr}"),
+            PeerAddr::Unix(id) => write!(f, "unix:{id}"),
+            PeerAddr::Quic(connection, stream) => write!(f, "quic:{connection}/{stream}"),
+            PeerAddr::Local(id) => write!(f, "local:{id}"),
+            PeerAddr::Http => write!(f, "http"),
+            PeerAddr::Subscriber(id) => write!(f, "subscriber:{id}"),
+            PeerAddr::Control => write!(f, "control"),
+        }
+    }
+}
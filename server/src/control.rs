@@ -0,0 +1,415 @@
+use std::{
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use futures::{SinkExt, StreamExt};
+use protocol::{Channel, ErrorCode, Message, User};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::net::UnixStream;
+use tokio_util::codec::{Framed, LinesCodec, LinesCodecError};
+
+use crate::{
+    archive::Archive,
+    connection::PeerAddr,
+    http::{self, ChannelInfo, SessionInfo},
+    retention::Retention,
+    server::{Refusal, MAX_MESSAGE_LEN},
+    Server,
+};
+
+/// Name server notices are posted under.
+const NOTICE_NAME: &str = "server";
+/// Longest request or response line on the control socket.
+const MAX_LINE_LEN: usize = 64 * 1024 * 1024;
+/// Newest messages shown by `Request::Channel`.
+const RECENT_MESSAGES: usize = 10;
+
+/// What `chatctl` can ask of a running server over the control socket, see
+/// `Server::listen_control`. Requests and responses are exchanged as one JSON
+/// object per line, e.g. `{"command":"kick","session":3}`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    /// Lists the logged in users.
+    Sessions,
+    /// Lists the channels.
+    Channels,
+    /// Shows one channel with its members and newest messages.
+    Channel {
+        name: String,
+    },
+    /// Posts `body` as the server into `channel`, or into every channel.
+    Notice {
+        body: String,
+        channel: Option<String>,
+    },
+    /// Disconnects a session, the user may log in again.
+    Kick {
+        session: u64,
+    },
+    /// Disconnects every session of `username` and refuses its logins until
+    /// it is unbanned.
+    Ban {
+        username: String,
+    },
+    Unban {
+        username: String,
+    },
+    /// Lists the banned usernames.
+    Bans,
+    CreateChannel {
+        name: String,
+        cover: Option<String>,
+    },
+    /// Deletes a channel and its history.
+    DeleteChannel {
+        name: String,
+    },
+    /// Changes how long a channel keeps its messages.
+    SetRetention {
+        name: String,
+        retention: Retention,
+    },
+    /// Replaces the log filter, e.g. `server=debug`, see
+    /// `logging::set_filter`.
+    LogLevel {
+        filter: String,
+    },
+    /// Writes a `Snapshot` of every channel as JSON to `path`, on the
+    /// server's machine.
+    Snapshot {
+        path: PathBuf,
+    },
+    /// Writes the channel called `name` with its history to `path`, on the
+    /// server's machine, as JSON Lines or, with `html`, as a transcript, see
+    /// `Archive`.
+    Export {
+        name: String,
+        path: PathBuf,
+        #[serde(default)]
+        html: bool,
+    },
+    /// Adds the channel exported to `path`, on the server's machine, under
+    /// its own name or `name`.
+    Import {
+        path: PathBuf,
+        name: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(tag = "reply", content = "data", rename_all = "snake_case")]
+pub enum Response {
+    Done,
+    Sessions(Vec<SessionInfo>),
+    Channels(Vec<ChannelInfo>),
+    Channel(ChannelState),
+    /// Ids of the disconnected sessions.
+    Kicked(Vec<u64>),
+    Bans(Vec<String>),
+    Snapshot {
+        path: PathBuf,
+        channels: usize,
+        messages: usize,
+    },
+    Exported {
+        path: PathBuf,
+        messages: usize,
+    },
+    Imported {
+        name: String,
+        messages: usize,
+    },
+    Error {
+        code: ErrorCode,
+        reason: String,
+    },
+}
+
+/// Answer to `Request::Channel`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct ChannelState {
+    pub info: ChannelInfo,
+    /// Usernames of the members, or their address for sessions that are
+    /// not logged in. Event stream subscribers are left out. Sorted.
+    pub members: Vec<String>,
+    /// Oldest first.
+    pub recent: Vec<Message>,
+}
+
+/// The channels and their history at one point in time, written by
+/// `Request::Snapshot`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct Snapshot {
+    pub taken: DateTime<Utc>,
+    /// Sorted by name.
+    pub channels: Vec<Channel>,
+}
+
+/// Answers the requests coming in on one control socket connection until it
+/// is closed or the server shuts down.
+pub(crate) async fn serve(server: Arc<Server>, stream: UnixStream) {
+    let mut lines = Framed::new(stream, LinesCodec::new_with_max_length(MAX_LINE_LEN));
+    loop {
+        let line = tokio::select! {
+            _ = server.shutdown.cancelled() => break,
+            line = lines.next() => line,
+        };
+        let response = match line {
+            Some(Ok(line)) => match serde_json::from_str(&line) {
+                Ok(request) => {
+                    tracing::info!("control request {:?}", request);
+                    handle(&server, request)
+                        .await
+                        .unwrap_or_else(|(code, reason)| Response::Error { code, reason })
+                }
+                Err(e) => Response::Error {
+                    code: ErrorCode::InvalidFrame,
+                    reason: format!("invalid request: {e}"),
+                },
+            },
+            Some(Err(e)) => {
+                tracing::info!("control connection failed; error = {:?}", e);
+                break;
+            }
+            None => break,
+        };
+        let line = serde_json::to_string(&response).expect("responses are always serializable");
+        if lines.send(line).await.is_err() {
+            break;
+        }
+    }
+}
+
+async fn handle(server: &Server, request: Request) -> Result<Response, Refusal> {
+    match request {
+        Request::Sessions => {
+            let channels = server.channels.lock().await;
+            Ok(Response::Sessions(http::session_infos(server, &channels)))
+        }
+        Request::Channels => {
+            let channels = server.channels.lock().await;
+            Ok(Response::Channels(http::channel_infos(&channels)))
+        }
+        Request::Channel { name } => {
+            let channels = server.channels.lock().await;
+            let Some(shared) = channels.get(&name) else {
+                return Err((ErrorCode::NotFound, format!("no channel named {name}")));
+            };
+            let sessions = server.sessions.list();
+            let mut members: Vec<String> = shared
+                .members()
+                .map(|(addr, _)| {
+                    sessions
+                        .iter()
+                        .find(|session| session.addr == *addr)
+                        .map_or_else(|| addr.to_string(), |session| session.user.username.clone())
+                })
+                .collect();
+            members.sort();
+            let skip = shared.messages.len().saturating_sub(RECENT_MESSAGES);
+            Ok(Response::Channel(ChannelState {
+                info: ChannelInfo::new(shared),
+                members,
+                recent: shared.messages[skip..].to_vec(),
+            }))
+        }
+        Request::Notice { body, channel } => {
+            if body.trim().is_empty() {
+                return Err((
+                    ErrorCode::InvalidFrame,
+                    "the notice must not be empty".to_string(),
+                ));
+            }
+            if body.len() > MAX_MESSAGE_LEN {
+                return Err((
+                    ErrorCode::TooLarge,
+                    format!("messages are limited to {MAX_MESSAGE_LEN} bytes"),
+                ));
+            }
+
+            let mut channels = server.channels.lock().await;
+            let names: Vec<String> = match channel {
+                Some(name) if channels.contains_key(&name) => vec![name],
+                Some(name) => {
+                    return Err((ErrorCode::NotFound, format!("no channel named {name}")));
+                }
+                None => channels.keys().cloned().collect(),
+            };
+            let from = User {
+                username: NOTICE_NAME.to_string(),
+                color: None,
+                avatar: None,
+                bot: true,
+            };
+            for name in names {
+                if let Some(shared) = channels.get_mut(&name) {
+                    let message = Message::new(from.clone(), name, body.clone());
+                    server.publish(shared, PeerAddr::Control, message).await;
+                }
+            }
+            Ok(Response::Done)
+        }
+        Request::Kick { session } => {
+            if !server.sessions.kick(session) {
+                return Err((ErrorCode::NotFound, format!("no session {session}")));
+            }
+            Ok(Response::Kicked(vec![session]))
+        }
+        Request::Ban { username } => Ok(Response::Kicked(server.sessions.ban(&username))),
+        Request::Unban { username } => {
+            if !server.sessions.unban(&username) {
+                return Err((ErrorCode::NotFound, format!("{username} is not banned")));
+            }
+            Ok(Response::Done)
+        }
+        Request::Bans => Ok(Response::Bans(server.sessions.bans())),
+        Request::CreateChannel { name, cover } => {
+            let mut channels = server.channels.lock().await;
+            let channel = Channel {
+                name,
+                cover,
+                messages: vec![],
+            };
+            server.create_channel(&mut channels, channel, None, None)?;
+            Ok(Response::Done)
+        }
+        Request::DeleteChannel { name } => {
+            let mut channels = server.channels.lock().await;
+            server.delete_channel(&mut channels, &name)?;
+            Ok(Response::Done)
+        }
+        Request::SetRetention { name, retention } => {
+            server.set_retention(&name, retention).await?;
+            Ok(Response::Done)
+        }
+        Request::LogLevel { filter } => {
+            crate::logging::set_filter(&filter)?;
+            tracing::info!("log filter changed to {}", filter);
+            Ok(Response::Done)
+        }
+        Request::Snapshot { path } => {
+            let snapshot = {
+                let channels = server.channels.lock().await;
+                let mut channels: Vec<Channel> = channels
+                    .values()
+                    .map(|shared| Channel {
+                        name: shared.name.clone(),
+                        cover: shared.cover.clone(),
+                        messages: shared.messages.clone(),
+                    })
+                    .collect();
+                channels.sort_by(|a, b| a.name.cmp(&b.name));
+                Snapshot {
+                    taken: Utc::now(),
+                    channels,
+                }
+            };
+            let json = serde_json::to_vec_pretty(&snapshot).expect("snapshots are serializable");
+            write_atomically(&path, json)
+                .await
+                .map_err(|e| io_refusal("write", &path, e))?;
+            Ok(Response::Snapshot {
+                path,
+                channels: snapshot.channels.len(),
+                messages: snapshot
+                    .channels
+                    .iter()
+                    .map(|channel| channel.messages.len())
+                    .sum(),
+            })
+        }
+        Request::Export { name, path, html } => {
+            let archive = server.export(&name).await?;
+            let mut contents = vec![];
+            let written = if html {
+                archive.write_html(&mut contents)
+            } else {
+                archive.write_jsonl(&mut contents)
+            };
+            written.expect("writing into memory cannot fail");
+            write_atomically(&path, contents)
+                .await
+                .map_err(|e| io_refusal("write", &path, e))?;
+            Ok(Response::Exported {
+                path,
+                messages: archive.messages.len(),
+            })
+        }
+        Request::Import { path, name } => {
+            let contents = tokio::fs::read(&path)
+                .await
+                .map_err(|e| io_refusal("read", &path, e))?;
+            let archive = Archive::read_jsonl(contents.as_slice()).map_err(|e| {
+                let reason = format!("failed to import {}: {e}", path.display());
+                (ErrorCode::InvalidFrame, reason)
+            })?;
+            let messages = archive.messages.len();
+            let name = server.import(archive, name).await?;
+            Ok(Response::Imported { name, messages })
+        }
+    }
+}
+
+/// Writes `contents` next to `path` first and moves them into place, so a
+/// reader never sees half of them.
+async fn write_atomically(path: &Path, contents: Vec<u8>) -> io::Result<()> {
+    let mut partial = OsString::from(path);
+    partial.push(".partial");
+    tokio::fs::write(&partial, contents).await?;
+    tokio::fs::rename(&partial, path).await
+}
+
+/// Refusal for failing to `action` the file at `path`.
+fn io_refusal(action: &str, path: &Path, e: io::Error) -> Refusal {
+    let code = match e.kind() {
+        io::ErrorKind::NotFound => ErrorCode::NotFound,
+        io::ErrorKind::PermissionDenied => ErrorCode::Forbidden,
+        _ => ErrorCode::ServerBusy,
+    };
+    (code, format!("failed to {action} {}: {e}", path.display()))
+}
+
+#[derive(Debug, Error)]
+pub enum ControlError {
+    #[error("control socket error: {0}")]
+    Io(#[from] io::Error),
+    #[error("control socket error: {0}")]
+    Lines(#[from] LinesCodecError),
+    #[error("invalid control message: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("the server closed the control socket")]
+    Closed,
+    #[error("{code}: {reason}")]
+    Rejected { code: ErrorCode, reason: String },
+}
+
+/// Talks to a running server over its control socket, as `chatctl` does.
+pub struct ControlClient {
+    lines: Framed<UnixStream, LinesCodec>,
+}
+
+impl ControlClient {
+    pub async fn connect(path: impl AsRef<Path>) -> Result<Self, ControlError> {
+        let stream = UnixStream::connect(path).await?;
+        Ok(ControlClient {
+            lines: Framed::new(stream, LinesCodec::new_with_max_length(MAX_LINE_LEN)),
+        })
+    }
+
+    /// Sends `request` and waits for the response, a `Response::Error`
+    /// becomes `ControlError::Rejected`.
+    pub async fn request(&mut self, request: &Request) -> Result<Response, ControlError> {
+        self.lines.send(serde_json::to_string(request)?).await?;
+        let line = self.lines.next().await.ok_or(ControlError::Closed)??;
+        match serde_json::from_str(&line)? {
+            Response::Error { code, reason } => Err(ControlError::Rejected { code, reason }),
+            response => Ok(response),
+        }
+    }
+}
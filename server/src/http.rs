@@ -0,0 +1,577 @@
+use std::{collections::HashMap, convert::Infallible, sync::Arc};
+
+use axum::{
+    body::Bytes,
+    extract::{DefaultBodyLimit, Path, Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{self, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{delete, get, post, put},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use futures::{stream, Stream, StreamExt};
+use protocol::{Channel, ErrorCode, Frame, Message, User};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    archive::Archive,
+    connection::PeerAddr,
+    retention::Retention,
+    server::{Shared, MAX_MESSAGE_LEN},
+    webhooks::Delivery,
+    Server,
+};
+
+/// Name incoming webhook messages are posted under unless they bring one.
+const DEFAULT_HOOK_NAME: &str = "webhook";
+/// Messages per history page unless the request asks for a number.
+const DEFAULT_PAGE: u32 = 50;
+/// Largest archive accepted by `POST /api/channels/{channel}/import`.
+const MAX_ARCHIVE_LEN: usize = 64 * 1024 * 1024;
+
+/// The HTTP API served on `Server::listen_http`.
+///
+/// `POST /hooks/{channel}` posts a message into `channel`, provided the
+/// request carries the channel's token from `Server::hook_tokens` as
+/// `Authorization: Bearer <token>`:
+///
+/// ```text
+/// curl -H "Authorization: Bearer $TOKEN" -H "Content-Type: application/json" \
+///      -d '{"body": "build #42 passed", "username": "ci"}' \
+///      http://127.0.0.1:8080/hooks/builds
+/// ```
+///
+/// Everything under `/api` needs one of `Server::admin_tokens` the same way:
+///
+/// - `GET /api/channels` lists the channels as `ChannelInfo`s.
+/// - `POST /api/channels` creates the `NewChannel` in the body.
+/// - `DELETE /api/channels/{channel}` deletes a channel and its history.
+/// - `PUT /api/channels/{channel}/retention` sets how long the channel keeps
+///   its messages to the `Retention` in the body, e.g.
+///   `{"max_age_secs": 2592000, "max_count": null}`.
+/// - `GET /api/channels/{channel}/messages?before=SEQ&limit=N` returns a
+///   `HistoryPage`, the newest messages unless `before` is given.
+/// - `GET /api/channels/{channel}/export` returns the channel with its
+///   history as JSON Lines, see `Archive`, or as an HTML transcript with
+///   `?format=html`.
+/// - `POST /api/channels/{channel}/import` adds the channel exported in the
+///   body under the name `channel`.
+/// - `GET /api/sessions` lists the logged in users as `SessionInfo`s.
+/// - `DELETE /api/sessions/{id}` disconnects a session.
+/// - `GET /api/webhooks/deliveries` lists the recent outgoing webhook
+///   attempts as `Delivery`s, oldest first, failed ones included.
+///
+/// `GET /events?channels=a,b` streams what happens in the given channels, or
+/// in all of them, as Server-Sent Events. It takes an admin token too, also
+/// as `?token=` since browsers cannot set headers on an `EventSource`. Events
+/// are named `message`, `reaction`, `deleted`, `joined`, `left` and
+/// `channels` and carry JSON. `joined` and `left` have the `user` and the
+/// `channel`, which `left` lacks when the user disconnected.
+/// Messages have their sequence number as event id, so a reconnecting client
+/// sending `Last-Event-ID` (or `?last_event_id=` on the first connection)
+/// gets the messages it missed first.
+pub fn router(server: Arc<Server>) -> Router {
+    let api = Router::new()
+        .route("/channels", get(list_channels).post(create_channel))
+        .route("/channels/{channel}", delete(delete_channel))
+        .route("/channels/{channel}/messages", get(history))
+        .route("/channels/{channel}/retention", put(set_retention))
+        .route("/channels/{channel}/export", get(export))
+        .route(
+            "/channels/{channel}/import",
+            post(import).layer(DefaultBodyLimit::max(MAX_ARCHIVE_LEN)),
+        )
+        .route("/sessions", get(list_sessions))
+        .route("/sessions/{id}", delete(kick))
+        .route("/webhooks/deliveries", get(deliveries))
+        .route_layer(middleware::from_fn_with_state(
+            Arc::clone(&server),
+            require_admin,
+        ));
+
+    Router::new()
+        .route("/hooks/{channel}", post(incoming_hook))
+        .route("/events", get(events))
+        .nest("/api", api)
+        .with_state(server)
+}
+
+/// Body of an incoming webhook request.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct HookMessage {
+    pub body: String,
+    /// Shown instead of `webhook` as the sender.
+    pub username: Option<String>,
+    pub avatar: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct ChannelInfo {
+    pub name: String,
+    pub cover: Option<String>,
+    /// Sessions currently in the channel, event streams are not counted.
+    pub members: usize,
+    pub messages: usize,
+    /// Sequence number of the newest message.
+    pub last_seq: Option<u64>,
+    pub retention: Retention,
+}
+
+impl ChannelInfo {
+    pub(crate) fn new(shared: &Shared) -> Self {
+        ChannelInfo {
+            name: shared.name.clone(),
+            cover: shared.cover.clone(),
+            members: shared.members().count(),
+            messages: shared.messages.len(),
+            last_seq: shared.messages.last().map(|msg| msg.seq),
+            retention: shared.retention,
+        }
+    }
+}
+
+/// Every channel, sorted by name.
+pub(crate) fn channel_infos(channels: &HashMap<String, Shared>) -> Vec<ChannelInfo> {
+    let mut infos: Vec<ChannelInfo> = channels.values().map(ChannelInfo::new).collect();
+    infos.sort_by(|a, b| a.name.cmp(&b.name));
+    infos
+}
+
+/// Body of `POST /api/channels`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct NewChannel {
+    pub name: String,
+    pub cover: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct HistoryPage {
+    pub channel: String,
+    /// Oldest first.
+    pub messages: Vec<Message>,
+    /// `before` for the next older page, `None` on the last one.
+    pub next: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct HistoryQuery {
+    before: Option<u64>,
+    limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ExportQuery {
+    /// `jsonl`, the default, or `html`.
+    format: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct EventsQuery {
+    /// Comma separated channel names.
+    channels: Option<String>,
+    token: Option<String>,
+    last_event_id: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct SessionInfo {
+    pub id: u64,
+    pub user: User,
+    /// Where the peer connected from, e.g. `127.0.0.1:50312` or `unix:3`.
+    pub addr: String,
+    pub connected: DateTime<Utc>,
+    /// Channels the peer is in, sorted.
+    pub channels: Vec<String>,
+}
+
+/// Every session, oldest first, with the channels it is in.
+pub(crate) fn session_infos(
+    server: &Server,
+    channels: &HashMap<String, Shared>,
+) -> Vec<SessionInfo> {
+    server
+        .sessions
+        .list()
+        .into_iter()
+        .map(|session| {
+            let mut joined: Vec<String> = channels
+                .values()
+                .filter(|shared| shared.peers.contains_key(&session.addr))
+                .map(|shared| shared.name.clone())
+                .collect();
+            joined.sort();
+            SessionInfo {
+                id: session.id,
+                user: session.user,
+                addr: session.addr.to_string(),
+                connected: session.connected,
+                channels: joined,
+            }
+        })
+        .collect()
+}
+
+/// A refused request, answered with a matching status and the code and
+/// reason as JSON.
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub reason: String,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, reason: impl Into<String>) -> Self {
+        ApiError {
+            code,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl From<(ErrorCode, String)> for ApiError {
+    fn from((code, reason): (ErrorCode, String)) -> Self {
+        ApiError { code, reason }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match self.code {
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::AlreadyExists => StatusCode::CONFLICT,
+            ErrorCode::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::InvalidFrame => StatusCode::BAD_REQUEST,
+            ErrorCode::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::ServerBusy => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        (status, Json(self)).into_response()
+    }
+}
+
+async fn incoming_hook(
+    State(server): State<Arc<Server>>,
+    Path(channel): Path<String>,
+    headers: HeaderMap,
+    Json(hook): Json<HookMessage>,
+) -> Result<Json<Message>, ApiError> {
+    // Channels without a token are not told apart from missing ones.
+    let Some(token) = server.hook_tokens.get(&channel) else {
+        return Err(ApiError::new(
+            ErrorCode::NotFound,
+            format!("no webhook for {channel}"),
+        ));
+    };
+    if !bearer(&headers).is_some_and(|given| same(given, token)) {
+        server
+            .metrics
+            .auth_failures
+            .with_label_values(&["http"])
+            .inc();
+        return Err(ApiError::new(ErrorCode::Unauthorized, "invalid token"));
+    }
+    if hook.body.trim().is_empty() {
+        return Err(ApiError::new(
+            ErrorCode::InvalidFrame,
+            "the body must not be empty",
+        ));
+    }
+    if hook.body.len() > MAX_MESSAGE_LEN {
+        return Err(ApiError::new(
+            ErrorCode::TooLarge,
+            format!("messages are limited to {MAX_MESSAGE_LEN} bytes"),
+        ));
+    }
+
+    let from = User {
+        username: hook
+            .username
+            .unwrap_or_else(|| DEFAULT_HOOK_NAME.to_string()),
+        color: None,
+        avatar: hook.avatar,
+        bot: true,
+    };
+    crate::server::validate_user(&from)?;
+
+    let mut channels = server.channels.lock().await;
+    let Some(shared) = channels.get_mut(&channel) else {
+        return Err(ApiError::new(
+            ErrorCode::NotFound,
+            format!("no channel named {channel}"),
+        ));
+    };
+    let message = Message::new(from, channel, hook.body);
+    Ok(Json(server.publish(shared, PeerAddr::Http, message).await))
+}
+
+/// The token of an `Authorization: Bearer <token>` header.
+fn bearer(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+/// Compares tokens in time independent of where they differ.
+fn same(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |differs, (a, b)| differs | (a ^ b))
+            == 0
+}
+
+/// Lets only requests with an admin token through to the admin API.
+async fn require_admin(
+    State(server): State<Arc<Server>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    admin(&server, bearer(request.headers()))?;
+    Ok(next.run(request).await)
+}
+
+fn admin(server: &Server, given: Option<&str>) -> Result<(), ApiError> {
+    if !given.is_some_and(|given| server.admin_tokens.iter().any(|token| same(given, token))) {
+        server
+            .metrics
+            .auth_failures
+            .with_label_values(&["http"])
+            .inc();
+        return Err(ApiError::new(
+            ErrorCode::Unauthorized,
+            "an admin token is required",
+        ));
+    }
+    Ok(())
+}
+
+async fn events(
+    State(server): State<Arc<Server>>,
+    Query(query): Query<EventsQuery>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<sse::Event, Infallible>>>, ApiError> {
+    admin(&server, bearer(&headers).or(query.token.as_deref()))?;
+
+    let channels: Vec<String> = query
+        .channels
+        .iter()
+        .flat_map(|channels| channels.split(','))
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect();
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|id| id.to_str().ok()?.parse().ok())
+        .or(query.last_event_id);
+    let mut subscription = server.subscribe(&channels, last_event_id).await?;
+    tracing::info!("{} subscribed to {:?}", subscription.addr, channels);
+
+    let backlog = std::mem::take(&mut subscription.backlog);
+    let missed = stream::iter(backlog.into_iter().map(|msg| Ok(message_event(&msg))));
+    let shutdown = server.shutdown.clone();
+    let live = stream::unfold(subscription, move |mut subscription| {
+        let shutdown = shutdown.clone();
+        async move {
+            loop {
+                let frame = tokio::select! {
+                    _ = shutdown.cancelled() => return None,
+                    frame = subscription.rx.recv() => frame?,
+                };
+                if let Some(event) = frame_event(frame) {
+                    return Some((Ok(event), subscription));
+                }
+            }
+        }
+    });
+    Ok(Sse::new(missed.chain(live)).keep_alive(KeepAlive::default()))
+}
+
+fn message_event(msg: &Message) -> sse::Event {
+    sse::Event::default()
+        .event("message")
+        .id(msg.seq.to_string())
+        .data(serde_json::to_string(msg).unwrap_or_default())
+}
+
+/// The event for a frame broadcast to subscribers, `None` for frames they do
+/// not care about.
+fn frame_event(frame: Frame) -> Option<sse::Event> {
+    let (name, data) = match frame {
+        Frame::Message(msg) => return Some(message_event(&msg)),
+        Frame::React {
+            from,
+            channel,
+            seq,
+            emoji,
+        } => (
+            "reaction",
+            json!({"from": from, "channel": channel, "seq": seq, "emoji": emoji}),
+        ),
+        Frame::Deleted { channel, seqs } => ("deleted", json!({"channel": channel, "seqs": seqs})),
+        Frame::PeerJoined { channel, user } => {
+            ("joined", json!({"channel": channel, "user": user}))
+        }
+        Frame::PeerLeft { channel, user } => ("left", json!({"channel": channel, "user": user})),
+        // Leaving the server leaves every channel at once.
+        Frame::Disconnect(user) => ("left", json!({"user": user})),
+        Frame::Bulk(_, channels) => (
+            "channels",
+            json!(channels
+                .iter()
+                .map(|channel| &channel.name)
+                .collect::<Vec<_>>()),
+        ),
+        _ => return None,
+    };
+    Some(sse::Event::default().event(name).data(data.to_string()))
+}
+
+async fn list_channels(State(server): State<Arc<Server>>) -> Json<Vec<ChannelInfo>> {
+    Json(channel_infos(&*server.channels.lock().await))
+}
+
+async fn create_channel(
+    State(server): State<Arc<Server>>,
+    Json(channel): Json<NewChannel>,
+) -> Result<(StatusCode, Json<ChannelInfo>), ApiError> {
+    let mut channels = server.channels.lock().await;
+    let channel = Channel {
+        name: channel.name,
+        cover: channel.cover,
+        messages: vec![],
+    };
+    server.create_channel(&mut channels, channel.clone(), None, None)?;
+    Ok((
+        StatusCode::CREATED,
+        Json(ChannelInfo::new(&channels[&channel.name])),
+    ))
+}
+
+async fn delete_channel(
+    State(server): State<Arc<Server>>,
+    Path(channel): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let mut channels = server.channels.lock().await;
+    server.delete_channel(&mut channels, &channel)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn set_retention(
+    State(server): State<Arc<Server>>,
+    Path(channel): Path<String>,
+    Json(retention): Json<Retention>,
+) -> Result<Json<ChannelInfo>, ApiError> {
+    server.set_retention(&channel, retention).await?;
+    let channels = server.channels.lock().await;
+    let Some(shared) = channels.get(&channel) else {
+        return Err(ApiError::new(
+            ErrorCode::NotFound,
+            format!("no channel named {channel}"),
+        ));
+    };
+    Ok(Json(ChannelInfo::new(shared)))
+}
+
+async fn history(
+    State(server): State<Arc<Server>>,
+    Path(channel): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<HistoryPage>, ApiError> {
+    let channels = server.channels.lock().await;
+    let Some(shared) = channels.get(&channel) else {
+        return Err(ApiError::new(
+            ErrorCode::NotFound,
+            format!("no channel named {channel}"),
+        ));
+    };
+    let messages = shared.page(query.before, query.limit.unwrap_or(DEFAULT_PAGE));
+    let next = messages.first().map(|msg| msg.seq).filter(|&oldest| {
+        shared
+            .messages
+            .first()
+            .is_some_and(|first| first.seq < oldest)
+    });
+    Ok(Json(HistoryPage {
+        channel,
+        messages,
+        next,
+    }))
+}
+
+async fn export(
+    State(server): State<Arc<Server>>,
+    Path(channel): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, ApiError> {
+    let archive = server.export(&channel).await?;
+    let mut body = vec![];
+    let content_type = match query.format.as_deref() {
+        None | Some("jsonl") => {
+            archive
+                .write_jsonl(&mut body)
+                .expect("writing into memory cannot fail");
+            "application/jsonl"
+        }
+        Some("html") => {
+            archive
+                .write_html(&mut body)
+                .expect("writing into memory cannot fail");
+            "text/html; charset=utf-8"
+        }
+        Some(format) => {
+            return Err(ApiError::new(
+                ErrorCode::InvalidFrame,
+                format!("{format} is neither jsonl nor html"),
+            ));
+        }
+    };
+    Ok(([(header::CONTENT_TYPE, content_type)], body).into_response())
+}
+
+async fn import(
+    State(server): State<Arc<Server>>,
+    Path(channel): Path<String>,
+    body: Bytes,
+) -> Result<(StatusCode, Json<ChannelInfo>), ApiError> {
+    let archive = Archive::read_jsonl(&body[..])
+        .map_err(|e| ApiError::new(ErrorCode::InvalidFrame, e.to_string()))?;
+    server.import(archive, Some(channel.clone())).await?;
+    let channels = server.channels.lock().await;
+    let Some(shared) = channels.get(&channel) else {
+        return Err(ApiError::new(
+            ErrorCode::NotFound,
+            format!("no channel named {channel}"),
+        ));
+    };
+    Ok((StatusCode::CREATED, Json(ChannelInfo::new(shared))))
+}
+
+async fn list_sessions(State(server): State<Arc<Server>>) -> Json<Vec<SessionInfo>> {
+    let channels = server.channels.lock().await;
+    Json(session_infos(&server, &channels))
+}
+
+async fn kick(
+    State(server): State<Arc<Server>>,
+    Path(id): Path<u64>,
+) -> Result<StatusCode, ApiError> {
+    if !server.sessions.kick(id) {
+        return Err(ApiError::new(
+            ErrorCode::NotFound,
+            format!("no session {id}"),
+        ));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn deliveries(State(server): State<Arc<Server>>) -> Json<Vec<Delivery>> {
+    Json(server.webhooks.deliveries())
+}
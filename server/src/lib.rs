@@ -1,3 +1,15 @@
+pub mod archive;
 pub mod cli;
+pub mod connection;
+pub mod control;
+pub mod http;
+pub mod logging;
+pub mod metrics;
+pub mod retention;
+pub mod search;
 pub mod server;
+pub mod sessions;
+pub mod unix;
+pub mod webhooks;
+pub mod websocket;
 pub use server::Server;
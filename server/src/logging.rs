@@ -0,0 +1,46 @@
+use std::sync::OnceLock;
+
+use protocol::ErrorCode;
+use tracing_subscriber::{
+    filter::ParseError, fmt::format::FmtSpan, prelude::*, reload, EnvFilter, Registry,
+};
+
+use crate::server::Refusal;
+
+/// Swaps the filter of the subscriber installed by `init`.
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// Installs the subscriber logging to stdout, filtered by `RUST_LOG` and
+/// `server=info`. Several servers may be bound in one process (e.g. in
+/// tests), only the first call installs it.
+pub(crate) fn init() -> Result<(), ParseError> {
+    let filter = EnvFilter::from_default_env().add_directive("server=info".parse()?);
+    let (filter, handle) = reload::Layer::new(filter);
+    let installed = tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer().with_span_events(FmtSpan::FULL))
+        .try_init();
+    if installed.is_ok() {
+        let _ = FILTER.set(handle);
+    }
+    Ok(())
+}
+
+/// Replaces the log filter, given in the `RUST_LOG` syntax such as
+/// `server=debug,protocol=trace`, while the server is running.
+pub fn set_filter(filter: &str) -> Result<(), Refusal> {
+    let filter = EnvFilter::try_new(filter)
+        .map_err(|e| (ErrorCode::InvalidFrame, format!("invalid log filter: {e}")))?;
+    let Some(handle) = FILTER.get() else {
+        return Err((
+            ErrorCode::Forbidden,
+            "logging was set up outside of the server".to_string(),
+        ));
+    };
+    handle.reload(filter).map_err(|e| {
+        (
+            ErrorCode::ServerBusy,
+            format!("failed to change the log filter: {e}"),
+        )
+    })
+}
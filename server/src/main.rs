@@ -1,9 +1,63 @@
+use clap::Parser;
+use server::archive::Archive;
+use server::cli::{ServerCli, ServerCommand};
 use server::Server;
-use std::error::Error;
+use std::{error::Error, fs::File, io::BufReader};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let server = Server::bind("127.0.0.1:9999").await?;
+    let args = ServerCli::parse();
+    if let Some(ServerCommand::Transcript { archive, out }) = args.command {
+        let archive = Archive::read_jsonl(BufReader::new(File::open(archive)?))?;
+        archive.write_html(File::create(out)?)?;
+        return Ok(());
+    }
+
+    let mut server = Server::bind(&args.addr).await?;
+    if let (Some(cert), Some(key)) = (args.tls_cert, args.tls_key) {
+        server.tls = Some(protocol::tls::server_config(cert, key)?);
+    }
+    if let Some(ws_addr) = args.ws_addr {
+        server.listen_ws(&ws_addr).await?;
+    }
+    if let Some(http_addr) = args.http_addr {
+        server.listen_http(&http_addr).await?;
+    }
+    server.hook_tokens = args.hook_tokens.into_iter().collect();
+    server.admin_tokens = args.admin_tokens;
+    if let Some(metrics_addr) = args.metrics_addr {
+        server.listen_metrics(&metrics_addr).await?;
+    }
+    if let Some(quic_addr) = args.quic_addr {
+        server.listen_quic(&quic_addr)?;
+    }
+    if let Some(path) = args.unix_socket {
+        server.listen_unix(path, args.unix_socket_mode)?;
+        if let Some(unix) = server.unix.as_mut() {
+            if !args.unix_allowed_uids.is_empty() {
+                unix.allowed_uids = Some(args.unix_allowed_uids);
+            }
+        }
+    }
+    if let Some(path) = args.control_socket {
+        server.listen_control(path)?;
+    }
+    server.webhooks.hooks = args.webhooks;
+    server.webhooks.secret = args.webhook_secret;
+    for path in args.imports {
+        let archive = Archive::read_jsonl(BufReader::new(File::open(&path)?))
+            .map_err(|e| format!("failed to import {}: {e}", path.display()))?;
+        server
+            .import(archive, None)
+            .await
+            .map_err(|(_, reason)| reason)?;
+    }
+    for (channel, retention) in args.retention {
+        server
+            .set_retention(&channel, retention)
+            .await
+            .map_err(|(_, reason)| reason)?;
+    }
     server.run().await?;
     Ok(())
 }
@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+
+use crate::Server;
+
+/// What the server counts, served in the Prometheus text format on
+/// `Server::listen_metrics`.
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    registry: Registry,
+    /// Logged in peers.
+    pub connected_peers: IntGauge,
+    /// Connections turned away because `max_connetions` was reached.
+    pub connections_rejected: IntCounter,
+    /// Accepted messages by channel, `rate()` gives messages per second.
+    pub messages: IntCounterVec,
+    /// Frames that could not be decoded, each ends its connection.
+    pub decode_failures: IntCounter,
+    /// Frames waiting to be written to peers, summed over all peers.
+    pub outbound_queue: IntGauge,
+    /// Time to hand an accepted message to its channel's peers and store it
+    /// in the channel's history.
+    pub storage_latency: Histogram,
+    /// Refused logins and API requests by `kind`: `session`, `unix`,
+    /// `control` or `http`.
+    pub auth_failures: IntCounterVec,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        let registry = Registry::new_custom(Some("chat".to_string()), None)
+            .expect("the prefix is a valid metric name");
+        let metrics = Metrics {
+            connected_peers: IntGauge::new("connected_peers", "Logged in peers.").unwrap(),
+            connections_rejected: IntCounter::new(
+                "connections_rejected_total",
+                "Connections turned away because the connection limit was reached.",
+            )
+            .unwrap(),
+            messages: IntCounterVec::new(
+                Opts::new("messages_total", "Accepted messages."),
+                &["channel"],
+            )
+            .unwrap(),
+            decode_failures: IntCounter::new(
+                "frame_decode_failures_total",
+                "Frames that could not be decoded.",
+            )
+            .unwrap(),
+            outbound_queue: IntGauge::new(
+                "outbound_queue_depth",
+                "Frames waiting to be written to peers.",
+            )
+            .unwrap(),
+            storage_latency: Histogram::with_opts(
+                HistogramOpts::new(
+                    "storage_latency_seconds",
+                    "Time to deliver and store a message in its channel history.",
+                )
+                .buckets(vec![
+                    0.000_01, 0.000_05, 0.000_1, 0.000_5, 0.001, 0.005, 0.01, 0.05, 0.1,
+                ]),
+            )
+            .unwrap(),
+            auth_failures: IntCounterVec::new(
+                Opts::new("auth_failures_total", "Refused logins and API requests."),
+                &["kind"],
+            )
+            .unwrap(),
+            registry,
+        };
+
+        let collectors: [Box<dyn prometheus::core::Collector>; 7] = [
+            Box::new(metrics.connected_peers.clone()),
+            Box::new(metrics.connections_rejected.clone()),
+            Box::new(metrics.messages.clone()),
+            Box::new(metrics.decode_failures.clone()),
+            Box::new(metrics.outbound_queue.clone()),
+            Box::new(metrics.storage_latency.clone()),
+            Box::new(metrics.auth_failures.clone()),
+        ];
+        for collector in collectors {
+            metrics
+                .registry
+                .register(collector)
+                .expect("metric names are unique");
+        }
+        metrics
+    }
+}
+
+impl Metrics {
+    /// Every metric in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("metrics are always encodable");
+        String::from_utf8(buffer).expect("the text format is utf-8")
+    }
+}
+
+/// `GET /metrics` for Prometheus to scrape.
+pub fn router(server: Arc<Server>) -> Router {
+    Router::new()
+        .route("/metrics", get(scrape))
+        .with_state(server)
+}
+
+async fn scrape(State(server): State<Arc<Server>>) -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+        server.metrics.render(),
+    )
+}
@@ -0,0 +1,106 @@
+use std::{fmt::Display, str::FromStr, time::Duration};
+
+use chrono::{DateTime, Utc};
+use protocol::Message;
+use serde::{Deserialize, Serialize};
+
+/// How long a channel keeps its messages. Messages beyond either limit are
+/// deleted by the server, see `Server::retention_interval`; without limits
+/// they are kept forever.
+///
+/// Written as `forever` or a comma separated list of an age such as `30d`,
+/// `12h`, `15m` or `90s` and a number of messages, e.g. `30d,10000`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Retention {
+    /// Messages created longer ago than this many seconds are deleted.
+    pub max_age_secs: Option<u64>,
+    /// Only this many of the newest messages are kept.
+    pub max_count: Option<usize>,
+}
+
+impl Retention {
+    /// Whether messages are kept forever.
+    pub fn is_forever(&self) -> bool {
+        self.max_age_secs.is_none() && self.max_count.is_none()
+    }
+
+    /// Removes the messages, oldest first, this policy no longer keeps at
+    /// `now`, returning their sequence numbers.
+    pub(crate) fn expire(&self, messages: &mut Vec<Message>, now: DateTime<Utc>) -> Vec<u64> {
+        let excess = self
+            .max_count
+            .map_or(0, |max| messages.len().saturating_sub(max));
+        let cutoff = self.max_age_secs.and_then(|secs| {
+            let age = chrono::Duration::from_std(Duration::from_secs(secs)).ok()?;
+            now.checked_sub_signed(age)
+        });
+
+        let mut expired = vec![];
+        let mut index = 0;
+        messages.retain(|msg| {
+            let keep = index >= excess && cutoff.is_none_or(|cutoff| msg.created >= cutoff);
+            index += 1;
+            if !keep {
+                expired.push(msg.seq);
+            }
+            keep
+        });
+        expired
+    }
+}
+
+impl Display for Retention {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut limits = vec![];
+        if let Some(secs) = self.max_age_secs {
+            let age = [("d", 86_400), ("h", 3_600), ("m", 60)]
+                .into_iter()
+                .find(|(_, unit)| secs > 0 && secs % unit == 0)
+                .map_or_else(
+                    || format!("{secs}s"),
+                    |(suffix, unit)| format!("{}{suffix}", secs / unit),
+                );
+            limits.push(age);
+        }
+        if let Some(count) = self.max_count {
+            limits.push(count.to_string());
+        }
+        if limits.is_empty() {
+            write!(f, "forever")
+        } else {
+            write!(f, "{}", limits.join(","))
+        }
+    }
+}
+
+impl FromStr for Retention {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut retention = Retention::default();
+        if s == "forever" {
+            return Ok(retention);
+        }
+        for limit in s.split(',').map(str::trim) {
+            let unit = match limit.char_indices().last() {
+                Some((at, 'd')) => Some((at, 86_400)),
+                Some((at, 'h')) => Some((at, 3_600)),
+                Some((at, 'm')) => Some((at, 60)),
+                Some((at, 's')) => Some((at, 1)),
+                _ => None,
+            };
+            let invalid = || format!("{limit} is neither an age like 30d nor a message count");
+            match unit {
+                Some((at, unit)) if retention.max_age_secs.is_none() => {
+                    let amount: u64 = limit[..at].parse().map_err(|_| invalid())?;
+                    retention.max_age_secs = Some(amount.checked_mul(unit).ok_or_else(invalid)?);
+                }
+                None if retention.max_count.is_none() => {
+                    retention.max_count = Some(limit.parse().map_err(|_| invalid())?);
+                }
+                _ => return Err(format!("{s} has more than one age or count")),
+            }
+        }
+        Ok(retention)
+    }
+}
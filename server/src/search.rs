@@ -0,0 +1,146 @@
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use protocol::Message;
+use rusqlite::{params, Connection};
+
+/// Full-text index of the messages of every channel, kept in an in-memory
+/// SQLite FTS5 table whose rowids are the message sequence numbers. SQLite
+/// integers are signed, sequence numbers never get that large.
+///
+/// It only knows where a match is, the messages themselves are looked up in
+/// the channel history, so anything removed from there has to be removed
+/// here too.
+#[derive(Debug)]
+pub struct SearchIndex {
+    db: Mutex<Connection>,
+}
+
+/// What to look for, see `Frame::Search`.
+#[derive(Debug, Clone)]
+pub(crate) struct Query<'a> {
+    /// Words that all have to occur, each also matching as a prefix.
+    pub text: &'a str,
+    /// Channels to look in.
+    pub channels: &'a [String],
+    /// Username of the sender.
+    pub from: Option<&'a str>,
+    pub before: Option<DateTime<Utc>>,
+    pub after: Option<DateTime<Utc>>,
+    /// Only messages with a lower sequence number.
+    pub below: Option<u64>,
+}
+
+impl Default for SearchIndex {
+    fn default() -> Self {
+        let db = Connection::open_in_memory().expect("sqlite can always open a memory database");
+        db.execute_batch(
+            "CREATE VIRTUAL TABLE messages USING fts5(
+                body,
+                channel UNINDEXED,
+                username UNINDEXED,
+                created UNINDEXED,
+                tokenize = 'unicode61 remove_diacritics 2'
+            );",
+        )
+        .expect("sqlite is built with fts5");
+        SearchIndex { db: Mutex::new(db) }
+    }
+}
+
+impl SearchIndex {
+    pub(crate) fn insert(&self, msg: &Message) {
+        let inserted = self.db.lock().unwrap().execute(
+            "INSERT INTO messages (rowid, body, channel, username, created)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                msg.seq as i64,
+                msg.body,
+                msg.channel,
+                msg.from.username,
+                msg.created.timestamp_millis()
+            ],
+        );
+        if let Err(e) = inserted {
+            tracing::warn!("failed to index message {}; error = {:?}", msg.seq, e);
+        }
+    }
+
+    /// Forgets the messages numbered `seqs`.
+    pub(crate) fn remove(&self, seqs: &[u64]) {
+        let mut db = self.db.lock().unwrap();
+        let removed = db.transaction().and_then(|tx| {
+            {
+                let mut statement = tx.prepare_cached("DELETE FROM messages WHERE rowid = ?1")?;
+                for seq in seqs {
+                    statement.execute(params![*seq as i64])?;
+                }
+            }
+            tx.commit()
+        });
+        if let Err(e) = removed {
+            tracing::warn!("failed to unindex {:?}; error = {:?}", seqs, e);
+        }
+    }
+
+    /// Forgets every message of `channel`.
+    pub(crate) fn remove_channel(&self, channel: &str) {
+        let removed = self
+            .db
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM messages WHERE channel = ?1", params![channel]);
+        if let Err(e) = removed {
+            tracing::warn!("failed to unindex {}; error = {:?}", channel, e);
+        }
+    }
+
+    /// Channels and sequence numbers of up to `limit` matches, newest first.
+    /// Queries without words match nothing.
+    pub(crate) fn search(
+        &self,
+        query: &Query,
+        limit: usize,
+    ) -> rusqlite::Result<Vec<(String, u64)>> {
+        let Some(expression) = match_expression(query.text) else {
+            return Ok(vec![]);
+        };
+        let channels = serde_json::to_string(query.channels).expect("names are serializable");
+        let db = self.db.lock().unwrap();
+        let mut statement = db.prepare_cached(
+            "SELECT channel, rowid FROM messages
+             WHERE messages MATCH ?1
+               AND channel IN (SELECT value FROM json_each(?2))
+               AND (?3 IS NULL OR username = ?3)
+               AND (?4 IS NULL OR created < ?4)
+               AND (?5 IS NULL OR created > ?5)
+               AND (?6 IS NULL OR rowid < ?6)
+             ORDER BY rowid DESC
+             LIMIT ?7",
+        )?;
+        let rows = statement.query_map(
+            params![
+                expression,
+                channels,
+                query.from,
+                query.before.map(|before| before.timestamp_millis()),
+                query.after.map(|after| after.timestamp_millis()),
+                query.below.map(|seq| seq as i64),
+                limit as i64,
+            ],
+            |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as u64)),
+        )?;
+        rows.collect()
+    }
+}
+
+/// Turns what a user typed into an FTS5 expression matching every word, as
+/// a prefix, without letting FTS5 operators or quotes through.
+fn match_expression(text: &str) -> Option<String> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("\"{word}\"*"))
+        .collect();
+    (!words.is_empty()).then(|| words.join(" "))
+}
@@ -1,38 +1,100 @@
-use bytes::Bytes;
 use std::{
     collections::HashMap,
-    io,
+    fs, io,
     net::{SocketAddr, ToSocketAddrs},
-    sync::Arc,
+    os::unix::fs::MetadataExt,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
+use chrono::{DateTime, Utc};
 use futures::{SinkExt, StreamExt};
-use tokio::sync::{mpsc, Mutex};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, Mutex, Notify};
 use tokio::{
-    net::{TcpListener, TcpStream},
+    net::{TcpListener, TcpStream, UnixStream},
     sync::{OwnedSemaphorePermit, Semaphore, TryAcquireError},
+    task::JoinSet,
 };
-use tokio_util::codec::{BytesCodec, Framed};
+use tokio_util::{either::Either, sync::CancellationToken};
 
-use protocol::{Channel, ConnectionError, Frame, Message};
+use protocol::{quic::BiStream, Channel, ConnectionError, ErrorCode, Frame, Message, User};
+use tokio_rustls::{rustls::ServerConfig, server::TlsStream, TlsAcceptor};
+
+use crate::{
+    archive::{Archive, ChannelMeta, ARCHIVE_VERSION},
+    connection::{framed, Connection, PeerAddr},
+    metrics::Metrics,
+    retention::Retention,
+    search::{self, SearchIndex},
+    sessions::{Session, Sessions},
+    unix::UnixSocket,
+    webhooks::{self, Webhooks},
+    websocket::WsTransport,
+};
 
 pub type Tx = mpsc::UnboundedSender<Frame>;
-type Rx = mpsc::UnboundedReceiver<Frame>;
+pub(crate) type Rx = mpsc::UnboundedReceiver<Frame>;
+
+/// Why a command was refused, sent back as `Frame::Rejected` or
+/// `Frame::Error`.
+pub(crate) type Refusal = (ErrorCode, String);
 
 const MAX_CONNECTIONS: usize = 64;
+/// Longest message body the server accepts, in bytes.
+pub(crate) const MAX_MESSAGE_LEN: usize = 16 * 1024;
+/// Longest user or channel name the server accepts, in bytes.
+pub(crate) const MAX_NAME_LEN: usize = 64;
+/// Longest time a message may ask to be kept for with `Message::ttl`.
+pub(crate) const MAX_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// Longest emoji, or short text, a `Frame::React` may carry, in bytes.
+const MAX_REACTION_LEN: usize = 32;
+/// Most messages returned for one `Frame::History`.
+pub(crate) const MAX_HISTORY_PAGE: usize = 500;
+/// Most messages returned for one `Frame::Search`.
+const MAX_SEARCH_PAGE: usize = 100;
+/// Longest `Frame::Search` query, in bytes.
+const MAX_QUERY_LEN: usize = 256;
+/// Every peer is in this channel, it cannot be deleted.
+const DEFAULT_CHANNEL: &str = "default";
+/// Default `Server::shutdown_timeout`.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+/// Hint sent to clients in `Frame::ServerShutdown`.
+const RECONNECT_AFTER: Duration = Duration::from_secs(10);
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+/// Number of unanswered `Frame::Ping`s after which a peer is considered dead.
+const MAX_MISSED_HEARTBEATS: u32 = 3;
+/// How often messages outliving their channel's `Retention` are deleted.
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60);
 
 #[derive(Debug)]
 pub struct Shared {
-    pub peers: HashMap<SocketAddr, Tx>,
+    pub peers: HashMap<PeerAddr, Tx>,
     pub messages: Vec<Message>,
     pub name: String,
     pub cover: Option<String>,
+    pub retention: Retention,
+    /// Reactions to the messages still in `messages`, oldest first.
+    pub reactions: Vec<Reaction>,
+}
+
+/// `from` reacted with `emoji` to the message numbered `seq`, see
+/// `Frame::React`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Reaction {
+    pub seq: u64,
+    pub from: User,
+    pub emoji: String,
 }
 
 impl Shared {
     /// Creates a new shared state for peer.
     /// ```
-    /// let shared = chat::server::Shared::new();
+    /// let shared = server::server::Shared::new("default".to_string(), None);
     ///
     /// assert_eq!(shared.peers.len(), 0);
     /// ```
@@ -42,21 +104,42 @@ impl Shared {
             name,
             cover,
             messages: vec![],
+            retention: Retention::default(),
+            reactions: vec![],
         }
     }
 
-    pub fn with_peers(name: String, cover: Option<String>, peers: HashMap<SocketAddr, Tx>) -> Self {
+    pub fn with_peers(name: String, cover: Option<String>, peers: HashMap<PeerAddr, Tx>) -> Self {
         Shared {
             peers,
             name,
             cover,
             messages: vec![],
+            retention: Retention::default(),
+            reactions: vec![],
         }
     }
 
+    /// The peers that are sessions, leaving out event stream subscribers.
+    pub fn members(&self) -> impl Iterator<Item = (&PeerAddr, &Tx)> {
+        self.peers.iter().filter(|(addr, _)| !addr.is_subscriber())
+    }
+
+    /// Up to `limit` messages, at most `MAX_HISTORY_PAGE`, right before
+    /// sequence number `before` or the newest ones, oldest first.
+    pub fn page(&self, before: Option<u64>, limit: u32) -> Vec<Message> {
+        let older = self
+            .messages
+            .iter()
+            .filter(|msg| before.is_none_or(|before| msg.seq < before));
+        let limit = (limit as usize).min(MAX_HISTORY_PAGE);
+        let skip = older.clone().count().saturating_sub(limit);
+        older.skip(skip).cloned().collect()
+    }
+
     /// Send a `LineCodec` encoded message to every peer, except
     /// for the sender.
-    async fn broadcast(&mut self, sender: SocketAddr, frame: &Frame) {
+    async fn broadcast(&mut self, sender: PeerAddr, frame: &Frame) {
         for peer in self.peers.iter_mut() {
             if *peer.0 != sender {
                 let _ = peer.1.send(frame.clone());
@@ -65,30 +148,49 @@ impl Shared {
     }
 }
 
-struct Peer {
+/// A read-only peer, see `Server::subscribe`. It leaves its channels when
+/// dropped.
+pub(crate) struct Subscription {
+    pub addr: PeerAddr,
+    pub rx: Rx,
+    pub backlog: Vec<Message>,
+    server: Arc<Server>,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let (server, addr) = (Arc::clone(&self.server), self.addr);
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move {
+                let mut channels = server.channels.lock().await;
+                server.unfiltered.lock().unwrap().remove(&addr);
+                for shared in channels.values_mut() {
+                    shared.peers.remove(&addr);
+                }
+            });
+        }
+    }
+}
+
+struct Peer<T> {
+    tx: Tx,
     rx: Rx,
     // stream: Framed<TcpStream, ChatCodec>,
-    stream: Framed<TcpStream, BytesCodec>,
+    stream: T,
 }
 
-impl Peer {
+impl<T> Peer<T> {
     /// Create a new instance of `Peer`.
-    pub async fn new(
-        state: Arc<Mutex<HashMap<String, Shared>>>,
-        stream: Framed<TcpStream, BytesCodec>,
-    ) -> io::Result<Peer> {
-        // Get the client socket address
-        let addr = stream.get_ref().peer_addr()?;
-
+    pub fn new(state: &mut HashMap<String, Shared>, stream: T, addr: PeerAddr) -> Peer<T> {
         // Create a channel for this peer
         let (tx, rx) = mpsc::unbounded_channel();
 
         // Add an entry for this `Peer` in the shared state map.
-        state.lock().await.iter_mut().for_each(|(_, v)| {
+        state.iter_mut().for_each(|(_, v)| {
             v.peers.insert(addr, tx.clone());
         });
 
-        Ok(Peer { stream, rx })
+        Peer { stream, tx, rx }
     }
 }
 
@@ -99,22 +201,61 @@ pub struct Server {
     pub channels: Arc<Mutex<HashMap<String, Shared>>>,
     pub messages: Arc<Mutex<Vec<Message>>>,
     pub max_connetions: Arc<Semaphore>,
+    pub shutdown: CancellationToken,
+    /// How long `run` waits for connections to flush after a shutdown was
+    /// requested before aborting them.
+    pub shutdown_timeout: Duration,
+    pub heartbeat_interval: Duration,
+    pub max_missed_heartbeats: u32,
+    /// How often every channel's `Shared::retention` is enforced.
+    pub retention_interval: Duration,
+    /// When set, every accepted connection has to complete a TLS handshake
+    /// before it is processed.
+    pub tls: Option<Arc<ServerConfig>>,
+    pub ws_listener: Option<TcpListener>,
+    pub http_listener: Option<TcpListener>,
+    pub metrics_listener: Option<TcpListener>,
+    /// Tokens allowing to post into a channel over HTTP, by channel name,
+    /// see `http::router`.
+    pub hook_tokens: HashMap<String, String>,
+    pub unix: Option<UnixSocket>,
+    /// Where `chatctl` connects to administer the server, see
+    /// `control::Request`.
+    pub control: Option<UnixSocket>,
+    pub quic: Option<quinn::Endpoint>,
+    /// Told about new messages, joins and channels.
+    pub webhooks: Webhooks,
+    pub sessions: Sessions,
+    pub metrics: Metrics,
+    /// Full-text index of every channel's history. Shared with the blocking
+    /// tasks running the searches.
+    pub search: Arc<SearchIndex>,
+    /// Tokens allowing to use the admin API, see `http::router`.
+    pub admin_tokens: Vec<String>,
+    /// Sequence number of the last accepted message.
+    last_seq: AtomicU64,
+    /// When the next message with a `ttl` expires, if any does.
+    next_expiry: std::sync::Mutex<Option<DateTime<Utc>>>,
+    /// Wakes the expiry task when `next_expiry` moved closer.
+    expiry_changed: Notify,
+    last_subscriber: AtomicU64,
+    /// Event stream subscribers that did not pick channels. Channels added
+    /// later are streamed to them too. Only changed under the `channels`
+    /// lock.
+    unfiltered: std::sync::Mutex<HashMap<PeerAddr, Tx>>,
 }
 
 impl Server {
     pub async fn bind(
         addr: impl ToSocketAddrs + std::fmt::Display,
-    ) -> Result<&'static mut Self, ConnectionError> {
-        use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
-        tracing_subscriber::fmt()
-            .with_env_filter(EnvFilter::from_default_env().add_directive("server=info".parse()?))
-            .with_span_events(FmtSpan::FULL)
-            .init();
-
-        tracing::info!("server running on {}", addr);
+    ) -> Result<Self, ConnectionError> {
+        crate::logging::init()?;
 
-        let addr = addr.to_socket_addrs().unwrap().next().unwrap();
+        let addr = resolve(addr)?;
         let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+
+        tracing::info!("server running on {}", addr);
 
         let mut channels = HashMap::new();
         channels.insert(
@@ -133,180 +274,1327 @@ impl Server {
         );
         let channels = Arc::new(Mutex::new(channels));
 
-        Ok(Box::leak(Box::new(Server {
+        Ok(Server {
             addr,
             listener,
             channels,
             messages: Arc::new(Mutex::new(vec![])),
             max_connetions: Arc::new(Semaphore::new(MAX_CONNECTIONS)),
-        })))
+            shutdown: CancellationToken::new(),
+            shutdown_timeout: SHUTDOWN_TIMEOUT,
+            heartbeat_interval: HEARTBEAT_INTERVAL,
+            max_missed_heartbeats: MAX_MISSED_HEARTBEATS,
+            retention_interval: RETENTION_INTERVAL,
+            tls: None,
+            ws_listener: None,
+            http_listener: None,
+            metrics_listener: None,
+            hook_tokens: HashMap::new(),
+            unix: None,
+            control: None,
+            quic: None,
+            webhooks: Webhooks::default(),
+            sessions: Sessions::default(),
+            metrics: Metrics::default(),
+            search: Arc::default(),
+            admin_tokens: vec![],
+            last_seq: AtomicU64::new(0),
+            next_expiry: std::sync::Mutex::new(None),
+            expiry_changed: Notify::new(),
+            last_subscriber: AtomicU64::new(0),
+            unfiltered: std::sync::Mutex::default(),
+        })
     }
 
-    pub async fn run(&'static self) -> Result<(), ConnectionError> {
+    /// Accepts connections until SIGINT/SIGTERM is received or `shutdown` is
+    /// cancelled, then notifies every peer and waits up to `shutdown_timeout`
+    /// for their connections to finish.
+    pub async fn run(mut self) -> Result<(), ConnectionError> {
+        let shutdown = self.shutdown.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = shutdown_signal() => {
+                    tracing::info!("shutdown signal received");
+                    shutdown.cancel();
+                }
+                _ = shutdown.cancelled() => {}
+            }
+        });
+
+        let http_listener = self.http_listener.take();
+        let metrics_listener = self.metrics_listener.take();
+        let server = Arc::new(self);
+        if let Some(listener) = http_listener {
+            server.serve_http(listener, crate::http::router(Arc::clone(&server)));
+        }
+        if let Some(listener) = metrics_listener {
+            server.serve_http(listener, crate::metrics::router(Arc::clone(&server)));
+        }
+        server.expire_messages();
+        let mut connections = JoinSet::new();
+        let mut unix_peers: u64 = 0;
+
         loop {
-            // Asynchronously wait for an inbound TcpStream.
-            let (stream, addr) = self.listener.accept().await?;
-
-            // Clone a handle to the `Shared` state for the new connection.
-            let peers = Arc::clone(&self.channels);
-            let connections = Arc::clone(&self.max_connetions);
-
-            // Spawn our handler to be run asynchronously.
-            let permit = connections.try_acquire_owned();
-            tokio::spawn(async move {
-                tracing::debug!("accepted connection");
-                if let Err(e) = self.process(peers, stream, addr, permit).await {
-                    tracing::info!("an error occurred; error = {:?}", e);
+            tokio::select! {
+                _ = server.shutdown.cancelled() => break,
+                // Reap finished connections so the set does not grow forever.
+                Some(_) = connections.join_next(), if !connections.is_empty() => {}
+                // Asynchronously wait for an inbound TcpStream.
+                result = server.listener.accept() => {
+                    let (stream, addr) = result?;
+
+                    // Spawn our handler to be run asynchronously.
+                    let server = Arc::clone(&server);
+                    connections.spawn(async move {
+                        tracing::debug!("accepted connection");
+                        if let Some(stream) = server.accept_tls(stream, addr).await {
+                            server.serve(framed(stream), PeerAddr::Net(addr)).await;
+                        }
+                    });
+                }
+                // WebSocket clients share the channels and connection limit
+                // with raw TCP clients.
+                result = accept(&server.ws_listener) => {
+                    let (stream, addr) = result?;
+
+                    let server = Arc::clone(&server);
+                    connections.spawn(async move {
+                        tracing::debug!("accepted websocket connection");
+                        let Some(stream) = server.accept_tls(stream, addr).await else {
+                            return;
+                        };
+                        match tokio_tungstenite::accept_async(stream).await {
+                            Ok(ws) => server.serve(WsTransport::new(ws), PeerAddr::Net(addr)).await,
+                            Err(e) => {
+                                tracing::info!("{}: websocket handshake failed; error = {:?}", addr, e);
+                            }
+                        }
+                    });
+                }
+                // Local clients speak the same protocol over a Unix socket.
+                result = accept_unix(&server.unix) => {
+                    let stream = result?;
+                    unix_peers += 1;
+                    let addr = PeerAddr::Unix(unix_peers);
+
+                    let server = Arc::clone(&server);
+                    connections.spawn(async move {
+                        tracing::debug!("accepted unix connection {:?}", stream.peer_cred());
+                        let authorized = server
+                            .unix
+                            .as_ref()
+                            .map(|unix| unix.authorize(&stream))
+                            .unwrap_or(false);
+                        let mut chat = framed(stream);
+
+                        if !authorized {
+                            tracing::info!("{}: rejected unix peer {:?}", addr, chat.get_ref().peer_cred());
+                            server.metrics.auth_failures.with_label_values(&["unix"]).inc();
+                            let _ = chat
+                                .send(Frame::error(ErrorCode::Unauthorized, "unix peer is not allowed"))
+                                .await;
+                            return;
+                        }
+
+                        server.serve(chat, addr).await;
+                    });
+                }
+                result = accept_unix(&server.control) => {
+                    let stream = result?;
+                    let server = Arc::clone(&server);
+                    connections.spawn(async move {
+                        let authorized = server
+                            .control
+                            .as_ref()
+                            .is_some_and(|control| control.authorize(&stream));
+                        if !authorized {
+                            tracing::info!("rejected control peer {:?}", stream.peer_cred());
+                            server.metrics.auth_failures.with_label_values(&["control"]).inc();
+                            return;
+                        }
+                        crate::control::serve(Arc::clone(&server), stream).await;
+                    });
+                }
+                Some(incoming) = accept_quic(&server.quic) => {
+                    let server = Arc::clone(&server);
+                    connections.spawn(async move {
+                        match incoming.await {
+                            Ok(connection) => server.serve_quic(connection).await,
+                            Err(e) => tracing::info!("quic handshake failed; error = {:?}", e),
+                        }
+                    });
+                }
+            }
+        }
+
+        tracing::info!(
+            "shutting down, waiting for {} connection(s)",
+            connections.len()
+        );
+        let drained = tokio::time::timeout(server.shutdown_timeout, async {
+            while connections.join_next().await.is_some() {}
+        })
+        .await;
+        if drained.is_err() {
+            tracing::warn!(
+                "shutdown deadline reached, aborting {} connection(s)",
+                connections.len()
+            );
+            connections.shutdown().await;
+        }
+        if let Some(quic) = &server.quic {
+            quic.close(0u32.into(), b"server is shutting down");
+        }
+
+        Ok(())
+    }
+
+    /// Starts listening for WebSocket clients on `addr`, returning the bound
+    /// address.
+    pub async fn listen_ws(
+        &mut self,
+        addr: impl ToSocketAddrs + std::fmt::Display,
+    ) -> Result<SocketAddr, ConnectionError> {
+        let addr = resolve(addr)?;
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        tracing::info!("websocket listener running on {}", addr);
+
+        self.ws_listener = Some(listener);
+        Ok(addr)
+    }
+
+    /// Starts serving the HTTP API, see `http::router`, on `addr`, returning
+    /// the bound address.
+    pub async fn listen_http(
+        &mut self,
+        addr: impl ToSocketAddrs + std::fmt::Display,
+    ) -> Result<SocketAddr, ConnectionError> {
+        let addr = resolve(addr)?;
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        tracing::info!("http listener running on {}", addr);
+
+        self.http_listener = Some(listener);
+        Ok(addr)
+    }
+
+    /// Starts serving Prometheus metrics, see `metrics::router`, on `addr`,
+    /// returning the bound address.
+    pub async fn listen_metrics(
+        &mut self,
+        addr: impl ToSocketAddrs + std::fmt::Display,
+    ) -> Result<SocketAddr, ConnectionError> {
+        let addr = resolve(addr)?;
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        tracing::info!("metrics listener running on {}", addr);
+
+        self.metrics_listener = Some(listener);
+        Ok(addr)
+    }
+
+    /// Serves `router` on `listener` in the background until the server shuts
+    /// down.
+    fn serve_http(&self, listener: TcpListener, router: axum::Router) {
+        let shutdown = self.shutdown.clone();
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, router)
+                .with_graceful_shutdown(async move { shutdown.cancelled().await })
+                .await
+            {
+                tracing::error!("http listener failed; error = {:?}", e);
+            }
+        });
+    }
+
+    /// Deletes the messages outliving their channel's retention every
+    /// `retention_interval`, and those outliving their `ttl` as soon as they
+    /// do, in the background until the server shuts down.
+    fn expire_messages(self: &Arc<Self>) {
+        let server = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(server.retention_interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                let next_expiry = *server.next_expiry.lock().unwrap();
+                let expiry = async {
+                    match next_expiry {
+                        Some(at) => {
+                            let wait = (at - Utc::now()).to_std().unwrap_or_default();
+                            tokio::time::sleep(wait).await
+                        }
+                        None => std::future::pending().await,
+                    }
+                };
+                tokio::select! {
+                    _ = server.shutdown.cancelled() => break,
+                    _ = interval.tick() => server.expire(Utc::now()).await,
+                    _ = expiry => server.expire(Utc::now()).await,
+                    // Wait for the new, closer deadline instead.
+                    _ = server.expiry_changed.notified() => {}
+                }
+            }
+        });
+    }
+
+    /// Deletes the messages that outlived their `ttl` or that every channel's
+    /// retention no longer keeps at `now`, and tells the channel's peers
+    /// which ones are gone.
+    pub async fn expire(&self, now: DateTime<Utc>) {
+        let mut channels = self.channels.lock().await;
+        let mut next_expiry = None;
+        for shared in channels.values_mut() {
+            let mut seqs = shared.retention.expire(&mut shared.messages, now);
+            shared.messages.retain(|msg| match msg.expires {
+                Some(expires) if expires <= now => {
+                    seqs.push(msg.seq);
+                    false
+                }
+                Some(expires) => {
+                    next_expiry =
+                        Some(next_expiry.map_or(expires, |next: DateTime<Utc>| next.min(expires)));
+                    true
                 }
+                None => true,
             });
+            if !seqs.is_empty() {
+                seqs.sort_unstable();
+                tracing::info!("expired {} message(s) in {}", seqs.len(), shared.name);
+                self.remove(shared, seqs);
+            }
+        }
+        *self.next_expiry.lock().unwrap() = next_expiry;
+    }
+
+    /// Forgets the messages numbered `seqs`, already taken out of `shared`'s
+    /// history, with their reactions and tells everyone in the channel.
+    pub(crate) fn remove(&self, shared: &mut Shared, seqs: Vec<u64>) {
+        shared
+            .reactions
+            .retain(|reaction| !seqs.contains(&reaction.seq));
+        self.search.remove(&seqs);
+        let frame = Frame::Deleted {
+            channel: shared.name.clone(),
+            seqs,
+        };
+        for tx in shared.peers.values() {
+            let _ = tx.send(frame.clone());
         }
     }
 
-    async fn process(
+    /// Changes how long the channel called `name` keeps its messages, taking
+    /// effect with the next `expire`.
+    pub async fn set_retention(&self, name: &str, retention: Retention) -> Result<(), Refusal> {
+        let mut channels = self.channels.lock().await;
+        let Some(shared) = channels.get_mut(name) else {
+            return Err((ErrorCode::NotFound, format!("no channel named {name}")));
+        };
+        shared.retention = retention;
+        tracing::info!("{} now keeps messages {}", name, retention);
+        Ok(())
+    }
+
+    /// Starts listening for local clients on a Unix socket at `path`, with the
+    /// socket file restricted to `mode`.
+    pub fn listen_unix(
+        &mut self,
+        path: impl AsRef<Path>,
+        mode: u32,
+    ) -> Result<(), ConnectionError> {
+        let unix = UnixSocket::bind(path, mode)?;
+        tracing::info!("unix listener running on {}", unix.path().display());
+
+        self.unix = Some(unix);
+        Ok(())
+    }
+
+    /// Starts listening for `chatctl` on a Unix socket at `path`, which only
+    /// the user running the server may connect to.
+    pub fn listen_control(&mut self, path: impl AsRef<Path>) -> Result<(), ConnectionError> {
+        let mut control = UnixSocket::bind(path, 0o600)?;
+        // The socket file belongs to the server's effective user. Root gets
+        // past the file mode, but not past this.
+        control.allowed_uids = Some(vec![fs::metadata(control.path())?.uid()]);
+        tracing::info!("control socket listening on {}", control.path().display());
+
+        self.control = Some(control);
+        Ok(())
+    }
+
+    /// Starts listening for QUIC clients on `addr`, returning the bound
+    /// address. QUIC is always encrypted, so `tls` has to be configured first.
+    pub fn listen_quic(
+        &mut self,
+        addr: impl ToSocketAddrs + std::fmt::Display,
+    ) -> Result<SocketAddr, ConnectionError> {
+        let Some(tls) = &self.tls else {
+            return Err(ConnectionError::Quic(
+                "a tls certificate is required for quic".to_string(),
+            ));
+        };
+        let addr = resolve(addr)?;
+        let endpoint = protocol::quic::server_endpoint(addr, tls)?;
+        let addr = endpoint.local_addr()?;
+        tracing::info!("quic listener running on {}", addr);
+
+        self.quic = Some(endpoint);
+        Ok(addr)
+    }
+
+    /// Serves every bidirectional stream the client opens on `connection` as
+    /// a separate session, until the client goes away or the server shuts
+    /// down.
+    async fn serve_quic(self: Arc<Self>, connection: quinn::Connection) {
+        let id = connection.stable_id();
+        tracing::debug!(
+            "accepted quic connection {} from {}",
+            id,
+            connection.remote_address()
+        );
+        let mut sessions = JoinSet::new();
+
+        loop {
+            tokio::select! {
+                _ = self.shutdown.cancelled() => break,
+                Some(_) = sessions.join_next(), if !sessions.is_empty() => {}
+                result = connection.accept_bi() => match result {
+                    Ok((send, recv)) => {
+                        let addr = PeerAddr::Quic(id, send.id().index());
+                        let server = Arc::clone(&self);
+                        sessions.spawn(async move {
+                            server.serve(framed(BiStream::new(send, recv)), addr).await;
+                        });
+                    }
+                    Err(e) => {
+                        tracing::debug!("quic connection {} closed; error = {:?}", id, e);
+                        break;
+                    }
+                },
+            }
+        }
+
+        while sessions.join_next().await.is_some() {}
+        // Closing the connection would discard the final frames still in
+        // flight, let the client hang up once it has read them.
+        if self.shutdown.is_cancelled() {
+            let _ = tokio::time::timeout(self.shutdown_timeout, connection.closed()).await;
+        }
+    }
+
+    /// Performs the TLS handshake if TLS is configured, `None` if it failed.
+    async fn accept_tls(
         &self,
-        state: Arc<Mutex<HashMap<String, Shared>>>,
         stream: TcpStream,
         addr: SocketAddr,
-        acquired_permit: Result<OwnedSemaphorePermit, TryAcquireError>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut chat = Framed::new(stream, BytesCodec::new());
+    ) -> Option<Either<TcpStream, TlsStream<TcpStream>>> {
+        match self.tls.clone() {
+            Some(config) => match TlsAcceptor::from(config).accept(stream).await {
+                Ok(stream) => Some(Either::Right(stream)),
+                Err(e) => {
+                    tracing::info!("{}: tls handshake failed; error = {:?}", addr, e);
+                    None
+                }
+            },
+            None => Some(Either::Left(stream)),
+        }
+    }
 
+    /// Runs a chat session over an established connection until the peer
+    /// leaves, counting it against `max_connetions`. Every listener ends up
+    /// here; embedders and tests can hand in any `Connection`, such as an
+    /// in-memory pipe wrapped with `framed`.
+    pub async fn handle<C: Connection>(
+        &self,
+        connection: C,
+        addr: PeerAddr,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let permit = Arc::clone(&self.max_connetions).try_acquire_owned();
+        self.process(Arc::clone(&self.channels), connection, addr, permit)
+            .await
+    }
+
+    /// `handle` for spawned connections, errors are only logged.
+    async fn serve<C: Connection>(&self, connection: C, addr: PeerAddr) {
+        if let Err(e) = self.handle(connection, addr).await {
+            tracing::info!("an error occurred; error = {:?}", e);
+        }
+    }
+
+    async fn process<C: Connection>(
+        &self,
+        state: Arc<Mutex<HashMap<String, Shared>>>,
+        mut chat: C,
+        addr: PeerAddr,
+        acquired_permit: Result<OwnedSemaphorePermit, TryAcquireError>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         if acquired_permit.is_err() {
-            let bytes: Bytes = Frame::Error("Max connections reached".to_string())
-                .try_into()
-                .unwrap();
-            chat.send(bytes).await.unwrap();
+            self.metrics.connections_rejected.inc();
+            chat.send(Frame::error(
+                ErrorCode::ServerBusy,
+                "max connections reached",
+            ))
+            .await?;
             tracing::info!("{}: max connections reached", &addr);
             return Ok(());
         }
 
-        let user = match chat.next().await {
-            Some(Ok(bytes)) => {
-                let frame: Frame = bytes.freeze().try_into().unwrap();
-                if let Frame::Authorize(user) = frame {
-                    let channels: Vec<Channel> = self
-                        .channels
-                        .lock()
-                        .await
-                        .iter()
-                        .map(|(_, v)| Channel {
-                            name: v.name.to_owned(),
-                            cover: v.cover.to_owned(),
-                            messages: v.messages.to_owned(),
-                        })
-                        .collect();
-                    let bytes: Bytes = Frame::Bulk(vec![], channels).try_into().unwrap();
-                    chat.send(bytes).await.unwrap();
-
-                    user
-                } else {
-                    tracing::error!("Failed to get username from {}. Client disconnected.", addr);
-                    return Ok(());
-                }
+        let first = tokio::select! {
+            _ = self.shutdown.cancelled() => return Ok(()),
+            first = chat.next() => first,
+        };
+
+        let user = match first {
+            Some(Ok(Frame::Authorize(user))) => user,
+            Some(Ok(_)) => {
+                tracing::error!("Failed to get username from {}. Client disconnected.", addr);
+                self.metrics
+                    .auth_failures
+                    .with_label_values(&["session"])
+                    .inc();
+                chat.send(Frame::error(
+                    ErrorCode::Unauthorized,
+                    "the first frame has to be Authorize",
+                ))
+                .await?;
+                return Ok(());
+            }
+            Some(Err(e)) => {
+                tracing::error!("Failed to get username from {}. Client disconnected.", addr);
+                self.metrics.decode_failures.inc();
+                chat.send(Frame::error(e.code(), e.to_string())).await?;
+                return Ok(());
             }
-            _ => {
+            None => {
                 tracing::error!("Failed to get username from {}. Client disconnected.", addr);
                 return Ok(());
             }
         };
+        let admitted = validate_user(&user).and_then(|()| {
+            self.sessions
+                .register(&user, addr)
+                .map_err(|banned| (ErrorCode::Forbidden, banned.to_string()))
+        });
+        let session = match admitted {
+            Ok(session) => session,
+            Err((code, reason)) => {
+                tracing::info!("{}: rejected user; reason = {}", addr, reason);
+                self.metrics
+                    .auth_failures
+                    .with_label_values(&["session"])
+                    .inc();
+                chat.send(Frame::error(code, reason)).await?;
+                return Ok(());
+            }
+        };
 
-        let mut peer = Peer::new(state.clone(), chat).await?;
+        // Snapshot the channels and add the peer under one lock, so no
+        // message falls between the history it gets and its first broadcast.
+        let (mut peer, channels) = {
+            let mut state = state.lock().await;
+            let channels = channel_list(&state);
+            (Peer::new(&mut state, chat, addr), channels)
+        };
+        self.metrics.connected_peers.inc();
+        let result = match peer.stream.send(Frame::Bulk(vec![], channels)).await {
+            Ok(()) => self.session(state, &mut peer, &session).await,
+            Err(e) => Err(e.into()),
+        };
 
-        loop {
-            tokio::select! {
-                // A message was received from a peer. Send it to the current user.
-                Some(frame) = peer.rx.recv() => {
-                    let bytes: Bytes = frame.try_into().unwrap();
-                    peer.stream.send(bytes).await?;
-                }
-                result = peer.stream.next() => match result {
-                    // A message was received from the current user, we should
-                    // broadcast this message to the other users.
-                    Some(Ok(msg)) => {
-                        let frame: Frame = msg.freeze().try_into().unwrap();
-                        match frame {
-                            Frame::Message(msg) => {
-                                let frame = Frame::Message(msg.clone());
-                                let bytes: Bytes = frame.clone().try_into().unwrap();
-                                peer.stream.send(bytes).await?;
-
-                                state
-                                    .lock()
-                                    .await
-                                    .get_mut(&msg.channel)
-                                    .unwrap()
-                                    .broadcast(addr, &frame)
-                                    .await;
-                                state
-                                    .lock()
-                                    .await
-                                    .get_mut(&msg.channel)
-                                    .unwrap()
-                                    .messages
-                                    .push(msg);
-                            },
-                            Frame::Channel(channel) => {
-                                let name = &channel.name.to_owned();
-
-                                let peers: HashMap<SocketAddr, Tx>  = state
-                                    .lock()
-                                    .await
-                                    .get("default")
-                                    .unwrap()
-                                    .peers
-                                    .iter()
-                                    .map(|(addr, tx)| (*addr, tx.clone()))
-                                    .collect();
-                                state
-                                    .lock()
-                                    .await
-                                    .insert(
-                                        name.to_string(),
-                                        Shared::with_peers(
-                                            name.to_string(),
-                                            channel.cover.to_owned(),
-                                            peers
-                                        )
-                                    );
-
-
-                                let channels: Vec<Channel> = self.channels.lock().await.iter().map(|(_, v)| {
-                                    Channel {
-                                        name: v.name.to_owned(),
-                                        cover: v.cover.to_owned(),
-                                        messages: v.messages.to_owned(),
+        // However the session ended, the peer must not linger in any channel.
+        self.sessions.remove(session.id);
+        self.metrics.connected_peers.dec();
+        self.disconnect(addr, &user).await;
+        drop(acquired_permit);
+        tracing::info!("{} ({}) disconnected", user.username, addr);
+
+        result
+    }
+
+    /// Runs the frame loop for an authorized peer until it disconnects, stops
+    /// answering heartbeats or the server shuts down.
+    async fn session<C: Connection>(
+        &self,
+        state: Arc<Mutex<HashMap<String, Shared>>>,
+        peer: &mut Peer<C>,
+        session: &Session,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let (user, addr) = (&session.user, session.addr);
+        let mut heartbeat = tokio::time::interval(self.heartbeat_interval);
+        // The first tick completes immediately, there is nothing to check yet.
+        heartbeat.tick().await;
+        let mut ping_id: u64 = 0;
+        let mut missed_heartbeats: u32 = 0;
+        // This peer's share of the outbound queue gauge, taken back on the way
+        // out.
+        let mut queued: i64 = 0;
+
+        let result = async {
+            loop {
+                tokio::select! {
+                    // The server is going down: deliver whatever is still queued
+                    // for this peer and tell it when to come back.
+                    _ = self.shutdown.cancelled() => {
+                        while let Ok(frame) = peer.rx.try_recv() {
+                            peer.stream.feed(frame).await?;
+                        }
+                        peer.stream
+                            .send(Frame::ServerShutdown {
+                                reason: "server is shutting down".to_string(),
+                                reconnect_after: Some(RECONNECT_AFTER),
+                            })
+                            .await?;
+                        peer.stream.close().await?;
+                        break;
+                    }
+                    _ = session.kicked.cancelled() => {
+                        tracing::info!("{} ({}) was kicked", user.username, addr);
+                        peer.stream
+                            .send(Frame::error(ErrorCode::Forbidden, "kicked by an administrator"))
+                            .await?;
+                        break;
+                    }
+                    _ = heartbeat.tick() => {
+                        if missed_heartbeats >= self.max_missed_heartbeats {
+                            tracing::info!(
+                                "{} missed {} heartbeats, disconnecting",
+                                user.username,
+                                missed_heartbeats
+                            );
+                            break;
+                        }
+                        missed_heartbeats += 1;
+                        ping_id += 1;
+                        peer.stream
+                            .send(Frame::Ping {
+                                id: ping_id,
+                                interval: self.heartbeat_interval,
+                            })
+                            .await?;
+                    }
+                    // A message was received from a peer. Send it to the current user.
+                    Some(frame) = peer.rx.recv() => {
+                        let depth = peer.rx.len() as i64;
+                        self.metrics.outbound_queue.add(depth - queued);
+                        queued = depth;
+                        peer.stream.send(frame).await?;
+                    }
+                    result = peer.stream.next() => match result {
+                        // A message was received from the current user, we should
+                        // broadcast this message to the other users.
+                        Some(Ok(frame)) => {
+                            // Any traffic proves the peer is alive, not only pongs.
+                            missed_heartbeats = 0;
+                            match frame {
+                                Frame::Ping { id, .. } => {
+                                    peer.stream.send(Frame::Pong(id)).await?;
+                                },
+                                Frame::Pong(_) => {},
+                                Frame::Request { id, frame } => {
+                                    let reply = match self.command(&state, addr, user, &peer.tx, *frame).await {
+                                        Ok(frames) => {
+                                            for frame in frames {
+                                                peer.stream.feed(frame).await?;
+                                            }
+                                            Frame::Ack(id)
+                                        }
+                                        Err((code, reason)) => Frame::Rejected { id, code, reason },
+                                    };
+                                    peer.stream.send(reply).await?;
+                                },
+                                frame @ (Frame::Message(_)
+                                | Frame::Channel(_)
+                                | Frame::Join(_)
+                                | Frame::Leave(_)
+                                | Frame::History { .. }
+                                | Frame::Search { .. }
+                                | Frame::React { .. }) => {
+                                    match self.command(&state, addr, user, &peer.tx, frame).await {
+                                        Ok(frames) => {
+                                            for frame in frames {
+                                                peer.stream.feed(frame).await?;
+                                            }
+                                            peer.stream.flush().await?;
+                                        }
+                                        Err((code, reason)) => peer.stream.send(Frame::error(code, reason)).await?,
                                     }
-                                }).collect();
-                                let frame = Frame::Bulk(vec![], channels);
-                                let bytes: Bytes = frame.clone().try_into().unwrap();
-                                peer.stream.send(bytes).await.unwrap();
-                                state
-                                    .lock()
-                                    .await
-                                    .get_mut("default")
-                                    .unwrap()
-                                    .broadcast(addr, &frame)
-                                    .await;
-                            },
-                            _ => {
+                                },
+                                _ => {
 
+                                }
                             }
                         }
+                        // An error occurred. The codec cannot recover from it, so
+                        // tell the peer what was wrong before hanging up.
+                        Some(Err(e)) => {
+                            tracing::error!(
+                                "an error occurred while processing messages for {}; error = {:?}",
+                                user.username,
+                                e
+                            );
+                            self.metrics.decode_failures.inc();
+                            peer.stream.send(Frame::error(e.code(), e.to_string())).await?;
+                            break;
+                        }
+                        // The stream has been exhausted.
+                        None => break,
+                    },
+                }
+            }
+            Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+        }
+        .await;
+
+        self.metrics.outbound_queue.sub(queued);
+        result
+    }
+
+    /// Carries out a command from the peer at `addr`, returning the frames to
+    /// send back to it or why it was refused.
+    async fn command(
+        &self,
+        state: &Mutex<HashMap<String, Shared>>,
+        addr: PeerAddr,
+        user: &User,
+        tx: &Tx,
+        frame: Frame,
+    ) -> Result<Vec<Frame>, Refusal> {
+        match frame {
+            Frame::Message(msg) => {
+                if msg.from.username != user.username {
+                    return Err((
+                        ErrorCode::Forbidden,
+                        "messages have to be sent as the logged in user".to_string(),
+                    ));
+                }
+                validate_message(&msg)?;
+
+                let mut state = state.lock().await;
+                let shared = member(&mut state, &msg.channel, addr)?;
+                let msg = self.publish(shared, addr, msg).await;
+                Ok(vec![Frame::Message(msg)])
+            }
+            Frame::React {
+                from,
+                channel,
+                seq,
+                emoji,
+            } => {
+                if from.username != user.username {
+                    return Err((
+                        ErrorCode::Forbidden,
+                        "reactions have to be sent as the logged in user".to_string(),
+                    ));
+                }
+                validate_emoji(&emoji)?;
+
+                let mut state = state.lock().await;
+                let shared = member(&mut state, &channel, addr)?;
+                if !shared.messages.iter().any(|msg| msg.seq == seq) {
+                    return Err((
+                        ErrorCode::NotFound,
+                        format!("no message {seq} in {channel}"),
+                    ));
+                }
+
+                shared.reactions.push(Reaction {
+                    seq,
+                    from: from.clone(),
+                    emoji: emoji.clone(),
+                });
+                let frame = Frame::React {
+                    from,
+                    channel,
+                    seq,
+                    emoji,
+                };
+                shared.broadcast(addr, &frame).await;
+                Ok(vec![frame])
+            }
+            Frame::Channel(channel) => {
+                let mut state = state.lock().await;
+                let frame =
+                    self.create_channel(&mut state, channel, Some((addr, tx)), Some(user))?;
+                Ok(vec![frame])
+            }
+            Frame::Join(name) => {
+                let mut state = state.lock().await;
+                let Some(shared) = state.get_mut(&name) else {
+                    return Err((ErrorCode::NotFound, format!("no channel named {name}")));
+                };
+
+                shared.peers.insert(addr, tx.clone());
+                let joined = Frame::PeerJoined {
+                    channel: name.clone(),
+                    user: user.clone(),
+                };
+                shared.broadcast(addr, &joined).await;
+                self.webhooks.notify(webhooks::Event::Join {
+                    channel: name,
+                    user: user.clone(),
+                });
+                Ok(vec![Frame::Joined(Channel {
+                    name: shared.name.to_owned(),
+                    cover: shared.cover.to_owned(),
+                    messages: shared.messages.to_owned(),
+                })])
+            }
+            Frame::Leave(name) => {
+                let mut state = state.lock().await;
+                let shared = member(&mut state, &name, addr)?;
+                shared.peers.remove(&addr);
+                let left = Frame::PeerLeft {
+                    channel: name,
+                    user: user.clone(),
+                };
+                shared.broadcast(addr, &left).await;
+                Ok(vec![])
+            }
+            Frame::History {
+                channel,
+                before,
+                limit,
+            } => {
+                let mut state = state.lock().await;
+                let shared = member(&mut state, &channel, addr)?;
+                let messages = shared.page(before, limit);
+                Ok(vec![Frame::HistoryPage { channel, messages }])
+            }
+            Frame::Search {
+                query,
+                channel,
+                from,
+                before,
+                after,
+                cursor,
+                limit,
+            } => {
+                if query.len() > MAX_QUERY_LEN {
+                    return Err((
+                        ErrorCode::TooLarge,
+                        format!("search queries are limited to {MAX_QUERY_LEN} bytes"),
+                    ));
+                }
+
+                // Only channels the peer is in are searched.
+                let channels = {
+                    let mut state = state.lock().await;
+                    match channel {
+                        Some(name) => vec![member(&mut state, &name, addr)?.name.clone()],
+                        None => state
+                            .values()
+                            .filter(|shared| shared.peers.contains_key(&addr))
+                            .map(|shared| shared.name.clone())
+                            .collect(),
                     }
-                    // An error occurred.
-                    Some(Err(e)) => {
-                        tracing::error!(
-                            "an error occurred while processing messages for {}; error = {:?}",
-                            user.username,
-                            e
-                        );
-                    }
-                    // The stream has been exhausted.
-                    None => break,
-                },
+                };
+                let limit = (limit as usize).min(MAX_SEARCH_PAGE);
+                // SQLite blocks, so the query runs off the runtime and without
+                // holding up the channels.
+                let index = Arc::clone(&self.search);
+                let text = query.clone();
+                let searched = tokio::task::spawn_blocking(move || {
+                    let search = search::Query {
+                        text: &text,
+                        channels: &channels,
+                        from: from.as_deref(),
+                        before,
+                        after,
+                        below: cursor,
+                    };
+                    // One more than asked for tells whether there is a next page.
+                    index.search(&search, limit + 1)
+                })
+                .await;
+                let found = match searched {
+                    Ok(found) => found.map_err(|e| e.to_string()),
+                    Err(e) => Err(e.to_string()),
+                };
+                let mut found = found.map_err(|e| {
+                    tracing::warn!("search for {:?} failed; error = {}", query, e);
+                    (ErrorCode::ServerBusy, format!("search failed: {e}"))
+                })?;
+                let more = found.len() > limit;
+                found.truncate(limit);
+                // Matches deleted in the meantime are left out.
+                let state = state.lock().await;
+                let messages: Vec<Message> = found
+                    .iter()
+                    .filter_map(|(channel, seq)| {
+                        let messages = &state.get(channel)?.messages;
+                        let index = messages.binary_search_by_key(seq, |msg| msg.seq).ok()?;
+                        Some(messages[index].clone())
+                    })
+                    .collect();
+                let next = found.last().map(|(_, seq)| *seq).filter(|_| more);
+                Ok(vec![Frame::SearchResults {
+                    query,
+                    messages,
+                    next,
+                }])
+            }
+            _ => Err((
+                ErrorCode::InvalidFrame,
+                "only messages, channels, joins, leaves, history, searches and reactions can be \
+                 requested"
+                    .to_string(),
+            )),
+        }
+    }
+
+    /// Adds `channel`, starting out with the peers of the default channel and
+    /// `creator`, and sends the new channel list to everyone but the creator.
+    /// Returns that list for the creator.
+    pub(crate) fn create_channel(
+        &self,
+        state: &mut HashMap<String, Shared>,
+        channel: Channel,
+        creator: Option<(PeerAddr, &Tx)>,
+        by: Option<&User>,
+    ) -> Result<Frame, Refusal> {
+        self.add_channel(state, Shared::new(channel.name, channel.cover), creator, by)
+    }
+
+    /// `create_channel` for a channel that may come with history.
+    fn add_channel(
+        &self,
+        state: &mut HashMap<String, Shared>,
+        mut shared: Shared,
+        creator: Option<(PeerAddr, &Tx)>,
+        by: Option<&User>,
+    ) -> Result<Frame, Refusal> {
+        check_channel_name(state, &shared.name)?;
+
+        // Subscribers only get the channels they asked for.
+        shared.peers = state
+            .get(DEFAULT_CHANNEL)
+            .map(|shared| {
+                shared
+                    .members()
+                    .map(|(addr, tx)| (*addr, tx.clone()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        shared.peers.extend(self.unfiltered.lock().unwrap().clone());
+        if let Some((addr, tx)) = creator {
+            shared.peers.insert(addr, tx.clone());
+        }
+        self.webhooks.notify(webhooks::Event::ChannelCreated {
+            channel: shared.name.clone(),
+            cover: shared.cover.clone(),
+            by: by.cloned(),
+        });
+        state.insert(shared.name.clone(), shared);
+
+        let frame = Frame::Bulk(vec![], channel_list(state));
+        for (peer, tx) in everyone(state) {
+            if creator.is_none_or(|(addr, _)| peer != addr) {
+                let _ = tx.send(frame.clone());
+            }
+        }
+        Ok(frame)
+    }
+
+    /// The channel called `name` with its history and reactions, as written
+    /// by `Archive::write_jsonl`.
+    pub async fn export(&self, name: &str) -> Result<Archive, Refusal> {
+        let channels = self.channels.lock().await;
+        let Some(shared) = channels.get(name) else {
+            return Err((ErrorCode::NotFound, format!("no channel named {name}")));
+        };
+        Ok(Archive {
+            channel: ChannelMeta {
+                version: ARCHIVE_VERSION,
+                name: shared.name.clone(),
+                cover: shared.cover.clone(),
+                retention: shared.retention,
+                exported: Utc::now(),
+            },
+            messages: shared.messages.clone(),
+            reactions: shared.reactions.clone(),
+        })
+    }
+
+    /// Adds the channel in `archive`, or under the name `rename`, with its
+    /// history and sends the new channel list to everyone. Messages are
+    /// renumbered after the messages of this server, so archives of any
+    /// server can be imported. Returns the channel's name.
+    pub async fn import(
+        &self,
+        archive: Archive,
+        rename: Option<String>,
+    ) -> Result<String, Refusal> {
+        let name = rename.unwrap_or(archive.channel.name);
+        let mut shared = Shared::new(name.clone(), archive.channel.cover);
+        shared.retention = archive.channel.retention;
+
+        // The archive is checked like live traffic, and completely before
+        // any sequence number is taken.
+        let mut messages = archive.messages;
+        messages.sort_by_key(|msg| msg.seq);
+        if let Some(pair) = messages.windows(2).find(|pair| pair[0].seq == pair[1].seq) {
+            return Err((
+                ErrorCode::InvalidFrame,
+                format!("message {} is in the archive twice", pair[0].seq),
+            ));
+        }
+        for msg in &messages {
+            validate_user(&msg.from)
+                .and_then(|()| validate_message(msg))
+                .map_err(|(code, reason)| (code, format!("message {}: {reason}", msg.seq)))?;
+        }
+        // Where each message ends up, counted from the first new number.
+        let offsets: HashMap<u64, u64> = messages
+            .iter()
+            .zip(0..)
+            .map(|(msg, offset)| (msg.seq, offset))
+            .collect();
+        let reactions = archive
+            .reactions
+            .into_iter()
+            .map(|reaction| {
+                let Some(&offset) = offsets.get(&reaction.seq) else {
+                    return Err((
+                        ErrorCode::InvalidFrame,
+                        format!(
+                            "reaction to message {}, which is not in the archive",
+                            reaction.seq
+                        ),
+                    ));
+                };
+                validate_user(&reaction.from)
+                    .and_then(|()| validate_emoji(&reaction.emoji))
+                    .map_err(|(code, reason)| {
+                        (
+                            code,
+                            format!("reaction to message {}: {reason}", reaction.seq),
+                        )
+                    })?;
+                Ok((offset, reaction))
+            })
+            .collect::<Result<Vec<_>, Refusal>>()?;
+
+        let mut state = self.channels.lock().await;
+        // A refused import must not leave a gap in the sequence numbers. The
+        // lock keeps the name free until it is added.
+        check_channel_name(&state, &name)?;
+        let first = self
+            .last_seq
+            .fetch_add(messages.len() as u64, Ordering::Relaxed)
+            + 1;
+        for (mut msg, offset) in messages.into_iter().zip(0..) {
+            msg.seq = first + offset;
+            msg.channel = name.clone();
+            shared.messages.push(msg);
+        }
+        shared.reactions = reactions
+            .into_iter()
+            .map(|(offset, reaction)| Reaction {
+                seq: first + offset,
+                ..reaction
+            })
+            .collect();
+        let imported = shared.messages.clone();
+        self.add_channel(&mut state, shared, None, None)?;
+
+        for msg in &imported {
+            self.search.insert(msg);
+            if let Some(expires) = msg.expires {
+                self.schedule_expiry(expires);
             }
         }
+        tracing::info!("imported {} with {} message(s)", name, imported.len());
+        Ok(name)
+    }
 
+    /// Removes the channel called `name` with its history and sends the
+    /// remaining channels to everyone. The default channel cannot be deleted.
+    pub(crate) fn delete_channel(
+        &self,
+        state: &mut HashMap<String, Shared>,
+        name: &str,
+    ) -> Result<(), Refusal> {
+        if name == DEFAULT_CHANNEL {
+            return Err((
+                ErrorCode::Forbidden,
+                format!("the {DEFAULT_CHANNEL} channel cannot be deleted"),
+            ));
+        }
+        let recipients = everyone(state);
+        if state.remove(name).is_none() {
+            return Err((ErrorCode::NotFound, format!("no channel named {name}")));
+        }
+        self.search.remove_channel(name);
+        // Never counted if nothing was ever posted there.
+        let _ = self.metrics.messages.remove_label_values(&[name]);
+
+        let frame = Frame::Bulk(vec![], channel_list(state));
+        for tx in recipients.values() {
+            let _ = tx.send(frame.clone());
+        }
         Ok(())
     }
+
+    /// Adds a read-only peer to the channels called `names`, or to every
+    /// channel if there are none, that receives what is broadcast there from
+    /// now on. The messages after sequence number `after` come with it, taken
+    /// under the same lock so none is missed or received twice.
+    pub(crate) async fn subscribe(
+        self: &Arc<Self>,
+        names: &[String],
+        after: Option<u64>,
+    ) -> Result<Subscription, Refusal> {
+        let mut state = self.channels.lock().await;
+        if let Some(name) = names.iter().find(|name| !state.contains_key(*name)) {
+            return Err((ErrorCode::NotFound, format!("no channel named {name}")));
+        }
+
+        let addr = PeerAddr::Subscriber(self.last_subscriber.fetch_add(1, Ordering::Relaxed) + 1);
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut backlog = vec![];
+        for shared in state.values_mut() {
+            if names.is_empty() || names.contains(&shared.name) {
+                shared.peers.insert(addr, tx.clone());
+                if let Some(after) = after {
+                    backlog.extend(
+                        shared
+                            .messages
+                            .iter()
+                            .filter(|msg| msg.seq > after)
+                            .cloned(),
+                    );
+                }
+            }
+        }
+        backlog.sort_by_key(|msg| msg.seq);
+        if names.is_empty() {
+            self.unfiltered.lock().unwrap().insert(addr, tx);
+        }
+
+        Ok(Subscription {
+            addr,
+            rx,
+            backlog,
+            server: Arc::clone(self),
+        })
+    }
+
+    /// Stamps `msg` with the next sequence number and its expiry, sends it to
+    /// everyone in `shared` but `sender`, keeps it in the channel's history
+    /// and tells the webhooks about it.
+    pub(crate) async fn publish(
+        &self,
+        shared: &mut Shared,
+        sender: PeerAddr,
+        mut msg: Message,
+    ) -> Message {
+        let timer = self.metrics.storage_latency.start_timer();
+        msg.seq = self.last_seq.fetch_add(1, Ordering::Relaxed) + 1;
+        msg.expires = msg
+            .ttl
+            .and_then(|ttl| Some(Utc::now() + chrono::Duration::from_std(ttl).ok()?));
+        if let Some(expires) = msg.expires {
+            self.schedule_expiry(expires);
+        }
+        shared.broadcast(sender, &Frame::Message(msg.clone())).await;
+        shared.messages.push(msg.clone());
+        self.search.insert(&msg);
+        timer.observe_duration();
+        self.metrics
+            .messages
+            .with_label_values(&[&msg.channel])
+            .inc();
+        self.webhooks.notify(webhooks::Event::Message {
+            channel: msg.channel.clone(),
+            message: msg.clone(),
+        });
+        msg
+    }
+
+    /// Makes sure the expiry task wakes up by `expires`.
+    fn schedule_expiry(&self, expires: DateTime<Utc>) {
+        let mut next_expiry = self.next_expiry.lock().unwrap();
+        if next_expiry.is_none_or(|next| expires < next) {
+            *next_expiry = Some(expires);
+            self.expiry_changed.notify_one();
+        }
+    }
+
+    /// Removes the peer from every channel's peer map and lets the remaining
+    /// peers know that `user` left.
+    async fn disconnect(&self, addr: PeerAddr, user: &User) {
+        let mut channels = self.channels.lock().await;
+        let mut remaining: HashMap<PeerAddr, Tx> = HashMap::new();
+        for shared in channels.values_mut() {
+            let joined = shared.peers.remove(&addr).is_some();
+            // Subscribers only hear of users in the channels they picked.
+            remaining.extend(
+                shared
+                    .peers
+                    .iter()
+                    .filter(|(peer, _)| joined || !peer.is_subscriber())
+                    .map(|(peer, tx)| (*peer, tx.clone())),
+            );
+        }
+        for tx in remaining.values() {
+            let _ = tx.send(Frame::Disconnect(user.clone()));
+        }
+    }
+}
+
+fn resolve(addr: impl ToSocketAddrs + std::fmt::Display) -> Result<SocketAddr, ConnectionError> {
+    addr.to_socket_addrs()?.next().ok_or_else(|| {
+        ConnectionError::AlreadyInUse(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{addr} did not resolve to an address"),
+        ))
+    })
+}
+
+/// Every channel with its history, as sent in `Frame::Bulk`.
+fn channel_list(state: &HashMap<String, Shared>) -> Vec<Channel> {
+    state
+        .values()
+        .map(|v| Channel {
+            name: v.name.to_owned(),
+            cover: v.cover.to_owned(),
+            messages: v.messages.to_owned(),
+        })
+        .collect()
+}
+
+/// Refuses `name` for a new channel if it is empty, too long or taken.
+fn check_channel_name(state: &HashMap<String, Shared>, name: &str) -> Result<(), Refusal> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return Err((
+            ErrorCode::InvalidFrame,
+            format!("channel names have to be 1 to {MAX_NAME_LEN} bytes"),
+        ));
+    }
+    if state.contains_key(name) {
+        return Err((
+            ErrorCode::AlreadyExists,
+            format!("channel {name} already exists"),
+        ));
+    }
+    Ok(())
+}
+
+/// Checks the parts of a message its sender chose: the body's length and
+/// the `ttl`.
+fn validate_message(msg: &Message) -> Result<(), Refusal> {
+    if msg.body.len() > MAX_MESSAGE_LEN {
+        return Err((
+            ErrorCode::TooLarge,
+            format!("messages are limited to {MAX_MESSAGE_LEN} bytes"),
+        ));
+    }
+    if msg.ttl.is_some_and(|ttl| ttl.is_zero() || ttl > MAX_TTL) {
+        return Err((
+            ErrorCode::InvalidFrame,
+            format!(
+                "a ttl has to be longer than zero and at most {} days",
+                MAX_TTL.as_secs() / 86_400
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn validate_emoji(emoji: &str) -> Result<(), Refusal> {
+    if emoji.is_empty() || emoji.len() > MAX_REACTION_LEN {
+        return Err((
+            ErrorCode::InvalidFrame,
+            format!("reactions have to be 1 to {MAX_REACTION_LEN} bytes"),
+        ));
+    }
+    Ok(())
+}
+
+/// Every peer in any channel.
+fn everyone(state: &HashMap<String, Shared>) -> HashMap<PeerAddr, Tx> {
+    state
+        .values()
+        .flat_map(|shared| shared.peers.iter())
+        .map(|(addr, tx)| (*addr, tx.clone()))
+        .collect()
+}
+
+/// The channel called `name`, provided `addr` is in it.
+fn member<'a>(
+    state: &'a mut HashMap<String, Shared>,
+    name: &str,
+    addr: PeerAddr,
+) -> Result<&'a mut Shared, Refusal> {
+    let Some(shared) = state.get_mut(name) else {
+        return Err((ErrorCode::NotFound, format!("no channel named {name}")));
+    };
+    if !shared.peers.contains_key(&addr) {
+        return Err((ErrorCode::Forbidden, format!("join {name} first")));
+    }
+    Ok(shared)
+}
+
+/// Checks the user a peer logs in as.
+pub(crate) fn validate_user(user: &User) -> Result<(), Refusal> {
+    if user.username.trim().is_empty() {
+        return Err((
+            ErrorCode::InvalidFrame,
+            "the username must not be empty".to_string(),
+        ));
+    }
+    if user.username.len() > MAX_NAME_LEN {
+        return Err((
+            ErrorCode::TooLarge,
+            format!("usernames are limited to {MAX_NAME_LEN} bytes"),
+        ));
+    }
+    Ok(())
+}
+
+/// Accepts from an optional listener, never resolving when it is not set.
+async fn accept(listener: &Option<TcpListener>) -> io::Result<(TcpStream, SocketAddr)> {
+    match listener {
+        Some(listener) => listener.accept().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn accept_unix(unix: &Option<UnixSocket>) -> io::Result<UnixStream> {
+    match unix {
+        Some(unix) => unix.accept().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn accept_quic(endpoint: &Option<quinn::Endpoint>) -> Option<quinn::Incoming> {
+    match endpoint {
+        Some(endpoint) => endpoint.accept().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Resolves once the process receives SIGINT or SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
 }
@@ -0,0 +1,122 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use chrono::{DateTime, Utc};
+use protocol::User;
+use thiserror::Error;
+use tokio_util::sync::CancellationToken;
+
+use crate::connection::PeerAddr;
+
+/// A logged in peer.
+#[derive(Debug, Clone)]
+pub struct Session {
+    /// Numbered as peers log in, never reused.
+    pub id: u64,
+    pub user: User,
+    pub addr: PeerAddr,
+    pub connected: DateTime<Utc>,
+    /// Cancelled to end the session, see `Sessions::kick`.
+    pub kicked: CancellationToken,
+}
+
+/// Why `Sessions::register` refused a login.
+#[derive(Debug, Error)]
+#[error("banned by an administrator")]
+pub struct Banned;
+
+/// Every logged in peer, for administration.
+#[derive(Debug, Default)]
+pub struct Sessions {
+    sessions: Mutex<HashMap<u64, Session>>,
+    last_id: AtomicU64,
+    /// Usernames that may not log in.
+    banned: Mutex<HashSet<String>>,
+}
+
+impl Sessions {
+    /// Adds a session for `user`, unless they are banned. Checked under the
+    /// same lock `ban` takes first, so a login racing a ban is either refused
+    /// or registered in time to be kicked.
+    pub(crate) fn register(&self, user: &User, addr: PeerAddr) -> Result<Session, Banned> {
+        let banned = self.banned.lock().unwrap();
+        if banned.contains(&user.username) {
+            return Err(Banned);
+        }
+        let session = Session {
+            id: self.last_id.fetch_add(1, Ordering::Relaxed) + 1,
+            user: user.clone(),
+            addr,
+            connected: Utc::now(),
+            kicked: CancellationToken::new(),
+        };
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(session.id, session.clone());
+        Ok(session)
+    }
+
+    pub(crate) fn remove(&self, id: u64) {
+        self.sessions.lock().unwrap().remove(&id);
+    }
+
+    /// The current sessions, oldest first.
+    pub fn list(&self) -> Vec<Session> {
+        let mut sessions: Vec<Session> = self.sessions.lock().unwrap().values().cloned().collect();
+        sessions.sort_by_key(|session| session.id);
+        sessions
+    }
+
+    /// Disconnects session `id`, false if there is no such session. The peer
+    /// is free to log in again.
+    pub fn kick(&self, id: u64) -> bool {
+        match self.sessions.lock().unwrap().get(&id) {
+            Some(session) => {
+                session.kicked.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Disconnects every session of `username` and refuses its logins from
+    /// now on, returning the ids of the kicked sessions.
+    pub fn ban(&self, username: &str) -> Vec<u64> {
+        self.banned.lock().unwrap().insert(username.to_string());
+        let mut kicked: Vec<u64> = self
+            .sessions
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|session| session.user.username == username)
+            .map(|session| {
+                session.kicked.cancel();
+                session.id
+            })
+            .collect();
+        kicked.sort();
+        kicked
+    }
+
+    /// Lets `username` log in again, false if it was not banned.
+    pub fn unban(&self, username: &str) -> bool {
+        self.banned.lock().unwrap().remove(username)
+    }
+
+    pub fn is_banned(&self, username: &str) -> bool {
+        self.banned.lock().unwrap().contains(username)
+    }
+
+    /// The banned usernames, sorted.
+    pub fn bans(&self) -> Vec<String> {
+        let mut bans: Vec<String> = self.banned.lock().unwrap().iter().cloned().collect();
+        bans.sort();
+        bans
+    }
+}
@@ -0,0 +1,87 @@
+use std::{
+    fs::{self, DirBuilder, Permissions},
+    io,
+    os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+use tokio::net::{UnixListener, UnixStream};
+
+/// Listener for local clients such as bots and sidecar tooling.
+///
+/// Who may connect is decided by the permissions of the socket file and,
+/// when `allowed_uids` is set, by the credentials of the connecting process.
+#[derive(Debug)]
+pub struct UnixSocket {
+    listener: UnixListener,
+    path: PathBuf,
+    /// When set, only processes running as one of these users are accepted.
+    pub allowed_uids: Option<Vec<u32>>,
+}
+
+impl UnixSocket {
+    /// Binds the socket at `path` and restricts it to `mode`, e.g. `0o660`.
+    pub fn bind(path: impl AsRef<Path>, mode: u32) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        // A socket left behind by a previous run would make bind fail, but
+        // never remove anything that is not a socket.
+        if let Ok(metadata) = fs::symlink_metadata(&path) {
+            if metadata.file_type().is_socket() {
+                fs::remove_file(&path)?;
+            }
+        }
+
+        // The socket is created with the process umask, so it is bound in a
+        // directory nobody else can enter and only linked into place once it
+        // has `mode`. Linking fails rather than replace whatever appeared at
+        // `path` in the meantime.
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let staging = path.with_file_name(format!(".{name}.{}", std::process::id()));
+        DirBuilder::new().mode(0o700).create(&staging)?;
+        let staged = staging.join("socket");
+        let bound = UnixListener::bind(&staged).and_then(|listener| {
+            fs::set_permissions(&staged, Permissions::from_mode(mode))?;
+            fs::hard_link(&staged, &path)?;
+            Ok(listener)
+        });
+        let _ = fs::remove_file(&staged);
+        let _ = fs::remove_dir(&staging);
+        let listener = bound?;
+
+        Ok(Self {
+            listener,
+            path,
+            allowed_uids: None,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub async fn accept(&self) -> io::Result<UnixStream> {
+        let (stream, _) = self.listener.accept().await?;
+        Ok(stream)
+    }
+
+    /// Checks the connecting process against `allowed_uids`.
+    pub fn authorize(&self, stream: &UnixStream) -> bool {
+        let Some(allowed) = &self.allowed_uids else {
+            return true;
+        };
+        match stream.peer_cred() {
+            Ok(cred) => allowed.contains(&cred.uid()),
+            Err(e) => {
+                tracing::info!("failed to read unix peer credentials; error = {:?}", e);
+                false
+            }
+        }
+    }
+}
+
+impl Drop for UnixSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,292 @@
+use std::{
+    collections::VecDeque,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use protocol::{Message, User};
+use serde::Serialize;
+use sha2::Sha256;
+
+/// Header carrying `sha256=<hex>`, the HMAC-SHA256 of the body keyed with the
+/// webhook secret.
+pub const SIGNATURE_HEADER: &str = "X-Chat-Signature";
+/// Header carrying the event name, e.g. `message`.
+pub const EVENT_HEADER: &str = "X-Chat-Event";
+/// Header carrying the delivery id, the same for every attempt.
+pub const DELIVERY_HEADER: &str = "X-Chat-Delivery";
+
+/// Attempts kept in the delivery log, older ones are dropped.
+const MAX_DELIVERY_LOG: usize = 256;
+const MAX_ATTEMPTS: u32 = 5;
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// How long a receiver has to answer one attempt.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A URL that gets every event of `channel` POSTed to it, or of every
+/// channel if `channel` is `None`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Webhook {
+    pub url: String,
+    pub channel: Option<String>,
+}
+
+impl FromStr for Webhook {
+    type Err = String;
+
+    /// Parses `[CHANNEL=]URL`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (channel, url) = match s.split_once('=') {
+            Some((channel, url)) if !channel.contains("://") => (Some(channel.to_string()), url),
+            _ => (None, s),
+        };
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return Err(format!("{url} is not an http(s) url"));
+        }
+        Ok(Webhook {
+            url: url.to_string(),
+            channel,
+        })
+    }
+}
+
+/// What happened, POSTed as JSON with the name in an `event` field.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Message {
+        channel: String,
+        message: Message,
+    },
+    Join {
+        channel: String,
+        user: User,
+    },
+    ChannelCreated {
+        channel: String,
+        cover: Option<String>,
+        /// `None` when created through the admin API.
+        by: Option<User>,
+    },
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Message { .. } => "message",
+            Event::Join { .. } => "join",
+            Event::ChannelCreated { .. } => "channel_created",
+        }
+    }
+
+    pub fn channel(&self) -> &str {
+        match self {
+            Event::Message { channel, .. }
+            | Event::Join { channel, .. }
+            | Event::ChannelCreated { channel, .. } => channel,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    delivery: u64,
+    timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// One attempt to deliver an event, see `Webhooks::deliveries`.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Delivery {
+    pub id: u64,
+    pub url: String,
+    pub event: &'static str,
+    /// Starts at 1.
+    pub attempt: u32,
+    pub at: DateTime<Utc>,
+    /// The HTTP status, if the receiver answered at all.
+    pub status: Option<u16>,
+    pub error: Option<String>,
+}
+
+impl Delivery {
+    pub fn succeeded(&self) -> bool {
+        self.status
+            .is_some_and(|status| (200..300).contains(&status))
+    }
+}
+
+/// The outgoing webhooks of a server.
+///
+/// Every event is delivered in its own task, so a slow or failing receiver
+/// holds up neither the chat nor the other events; receivers that care about
+/// order should go by `message.seq` or `timestamp`. Failed attempts are
+/// retried with exponential backoff, except when the receiver answered with a
+/// client error other than 429.
+#[derive(Debug)]
+pub struct Webhooks {
+    pub hooks: Vec<Webhook>,
+    /// Signs every body when set, see `SIGNATURE_HEADER`.
+    pub secret: Option<String>,
+    pub max_attempts: u32,
+    pub min_backoff: Duration,
+    pub max_backoff: Duration,
+    client: reqwest::Client,
+    last_delivery: AtomicU64,
+    log: Arc<Mutex<VecDeque<Delivery>>>,
+}
+
+impl Default for Webhooks {
+    fn default() -> Self {
+        Webhooks {
+            hooks: vec![],
+            secret: None,
+            max_attempts: MAX_ATTEMPTS,
+            min_backoff: MIN_BACKOFF,
+            max_backoff: MAX_BACKOFF,
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .expect("the http client has no configuration that can fail"),
+            last_delivery: AtomicU64::new(0),
+            log: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+}
+
+impl Webhooks {
+    /// Sends `event` to every hook interested in its channel.
+    pub fn notify(&self, event: Event) {
+        let hooks: Vec<String> = self
+            .hooks
+            .iter()
+            .filter(|hook| {
+                hook.channel
+                    .as_deref()
+                    .is_none_or(|channel| channel == event.channel())
+            })
+            .map(|hook| hook.url.clone())
+            .collect();
+
+        for url in hooks {
+            let id = self.last_delivery.fetch_add(1, Ordering::Relaxed) + 1;
+            let payload = Payload {
+                delivery: id,
+                timestamp: Utc::now(),
+                event: &event,
+            };
+            let body = match serde_json::to_vec(&payload) {
+                Ok(body) => body,
+                Err(e) => {
+                    tracing::error!("failed to encode webhook event; error = {:?}", e);
+                    continue;
+                }
+            };
+            let attempt = Attempt {
+                id,
+                url,
+                event: event.name(),
+                signature: self.secret.as_deref().map(|secret| sign(secret, &body)),
+                body,
+            };
+            tokio::spawn(attempt.deliver(
+                self.client.clone(),
+                self.max_attempts,
+                (self.min_backoff, self.max_backoff),
+                Arc::clone(&self.log),
+            ));
+        }
+    }
+
+    /// Recent delivery attempts, oldest first.
+    pub fn deliveries(&self) -> Vec<Delivery> {
+        self.log.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// `sha256=<hex>` of the HMAC-SHA256 of `body` keyed with `secret`.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any size");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+struct Attempt {
+    id: u64,
+    url: String,
+    event: &'static str,
+    body: Vec<u8>,
+    signature: Option<String>,
+}
+
+impl Attempt {
+    async fn deliver(
+        self,
+        client: reqwest::Client,
+        max_attempts: u32,
+        (min_backoff, max_backoff): (Duration, Duration),
+        log: Arc<Mutex<VecDeque<Delivery>>>,
+    ) {
+        let mut backoff = min_backoff;
+        for attempt in 1..=max_attempts {
+            let mut request = client
+                .post(&self.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(EVENT_HEADER, self.event)
+                .header(DELIVERY_HEADER, self.id)
+                .body(self.body.clone());
+            if let Some(signature) = &self.signature {
+                request = request.header(SIGNATURE_HEADER, signature);
+            }
+
+            let (status, error) = match request.send().await {
+                Ok(response) => (Some(response.status()), None),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            let delivery = Delivery {
+                id: self.id,
+                url: self.url.clone(),
+                event: self.event,
+                attempt,
+                at: Utc::now(),
+                status: status.map(|status| status.as_u16()),
+                error,
+            };
+            let done = delivery.succeeded();
+            {
+                let mut log = log.lock().unwrap();
+                if log.len() == MAX_DELIVERY_LOG {
+                    log.pop_front();
+                }
+                log.push_back(delivery);
+            }
+
+            if done {
+                return;
+            }
+            let retry = status.is_none_or(|status| {
+                status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            });
+            if !retry || attempt == max_attempts {
+                tracing::warn!(
+                    "giving up on webhook delivery {} to {} after {} attempt(s)",
+                    self.id,
+                    self.url,
+                    attempt
+                );
+                return;
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(max_backoff);
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{ready, Sink, SinkExt, Stream, StreamExt};
+use protocol::{Frame, ProtocolError};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
+
+/// Carries `Frame`s over a WebSocket connection.
+///
+/// Each binary message holds one bincode encoded frame. A client whose first
+/// message is a text message speaks JSON instead: its text messages are
+/// decoded as JSON `Frame`s and every frame sent to it is encoded as JSON.
+pub struct WsTransport<S> {
+    inner: WebSocketStream<S>,
+    json: Option<bool>,
+}
+
+impl<S> WsTransport<S> {
+    pub fn new(inner: WebSocketStream<S>) -> Self {
+        Self { inner, json: None }
+    }
+}
+
+fn ws_error(e: impl std::error::Error + Send + Sync + 'static) -> ProtocolError {
+    ProtocolError::Encode(io::Error::other(e))
+}
+
+impl<S> Stream for WsTransport<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Item = Result<Frame, ProtocolError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let message = match ready!(self.inner.poll_next_unpin(cx)) {
+                Some(Ok(message)) => message,
+                Some(Err(e)) => return Poll::Ready(Some(Err(ws_error(e)))),
+                None => return Poll::Ready(None),
+            };
+            match message {
+                Message::Binary(bytes) => {
+                    self.json.get_or_insert(false);
+                    let frame = bincode::deserialize(&bytes).map_err(|_| ProtocolError::Decode);
+                    return Poll::Ready(Some(frame));
+                }
+                Message::Text(text) => {
+                    self.json.get_or_insert(true);
+                    let frame =
+                        serde_json::from_str(text.as_str()).map_err(|_| ProtocolError::Decode);
+                    return Poll::Ready(Some(frame));
+                }
+                Message::Close(_) => return Poll::Ready(None),
+                // Control frames are answered by tungstenite itself.
+                Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => continue,
+            }
+        }
+    }
+}
+
+impl<S> Sink<Frame> for WsTransport<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Error = ProtocolError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready_unpin(cx).map_err(ws_error)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Frame) -> Result<(), Self::Error> {
+        let message = if self.json == Some(true) {
+            Message::Text(serde_json::to_string(&item).map_err(ws_error)?.into())
+        } else {
+            Message::Binary(bincode::serialize(&item)?.into())
+        };
+        self.inner.start_send_unpin(message).map_err(ws_error)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_flush_unpin(cx).map_err(ws_error)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_close_unpin(cx).map_err(ws_error)
+    }
+}
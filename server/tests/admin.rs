@@ -0,0 +1,210 @@
+mod common;
+
+use std::{net::SocketAddr, time::Duration};
+
+use common::{connect, next, start_http, user, ADMIN_TOKEN};
+use futures::{SinkExt, StreamExt};
+use protocol::{ErrorCode, Frame, Message};
+use reqwest::{Method, StatusCode};
+use serde_json::json;
+use server::http::{ChannelInfo, HistoryPage, SessionInfo};
+
+async fn api(
+    http: SocketAddr,
+    method: Method,
+    path: &str,
+    body: Option<serde_json::Value>,
+) -> reqwest::Response {
+    let mut request = reqwest::Client::new()
+        .request(method, format!("http://{http}/api{path}"))
+        .bearer_auth(ADMIN_TOKEN);
+    if let Some(body) = body {
+        request = request.json(&body);
+    }
+    request.send().await.unwrap()
+}
+
+fn channel_names(frame: Frame) -> Vec<String> {
+    let Frame::Bulk(_, channels) = frame else {
+        panic!("expected the channel list, got {frame:?}");
+    };
+    let mut names: Vec<String> = channels.into_iter().map(|channel| channel.name).collect();
+    names.sort();
+    names
+}
+
+#[tokio::test]
+async fn the_api_needs_an_admin_token() {
+    let (_, http) = start_http().await;
+    let client = reqwest::Client::new();
+    let url = format!("http://{http}/api/channels");
+
+    let response = client.get(&url).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = client.get(&url).bearer_auth("guess").send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = client.get(&url).bearer_auth("t0ken").send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn channels_are_listed_created_and_deleted() {
+    let (addr, http) = start_http().await;
+    let mut alice = connect(addr, "alice").await;
+
+    let channels: Vec<ChannelInfo> = api(http, Method::GET, "/channels", None)
+        .await
+        .json()
+        .await
+        .unwrap();
+    let names: Vec<&str> = channels.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, ["another", "default"]);
+    assert!(channels.iter().all(|channel| channel.members == 1));
+
+    let response = api(
+        http,
+        Method::POST,
+        "/channels",
+        Some(json!({"name": "ops", "cover": null})),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let created: ChannelInfo = response.json().await.unwrap();
+    assert_eq!(created.name, "ops");
+    assert_eq!(created.members, 1);
+    assert_eq!(
+        channel_names(next(&mut alice).await),
+        ["another", "default", "ops"]
+    );
+
+    let response = api(
+        http,
+        Method::POST,
+        "/channels",
+        Some(json!({"name": "ops", "cover": null})),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let response = api(http, Method::DELETE, "/channels/ops", None).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(
+        channel_names(next(&mut alice).await),
+        ["another", "default"]
+    );
+
+    let response = api(http, Method::DELETE, "/channels/ops", None).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = api(http, Method::DELETE, "/channels/default", None).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn retention_is_set_per_channel() {
+    let (_, http) = start_http().await;
+
+    let response = api(
+        http,
+        Method::PUT,
+        "/channels/default/retention",
+        Some(json!({"max_age_secs": 2592000, "max_count": null})),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let info: ChannelInfo = response.json().await.unwrap();
+    assert_eq!(info.retention.to_string(), "30d");
+
+    let channels: Vec<ChannelInfo> = api(http, Method::GET, "/channels", None)
+        .await
+        .json()
+        .await
+        .unwrap();
+    let another = channels.iter().find(|c| c.name == "another").unwrap();
+    assert!(another.retention.is_forever());
+
+    let response = api(
+        http,
+        Method::PUT,
+        "/channels/nowhere/retention",
+        Some(json!({"max_age_secs": null, "max_count": 10})),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn history_is_paginated() {
+    let (addr, http) = start_http().await;
+    let mut alice = connect(addr, "alice").await;
+    for i in 1..=5 {
+        let message = Message::new(user("alice"), "default".to_string(), format!("m{i}"));
+        alice.send(Frame::Message(message)).await.unwrap();
+        assert!(matches!(next(&mut alice).await, Frame::Message(_)));
+    }
+
+    let mut bodies = vec![];
+    let mut path = "/channels/default/messages?limit=2".to_string();
+    loop {
+        let page: HistoryPage = api(http, Method::GET, &path, None)
+            .await
+            .json()
+            .await
+            .unwrap();
+        assert!(page.messages.len() <= 2);
+        let mut older: Vec<String> = page.messages.into_iter().map(|m| m.body).collect();
+        older.append(&mut bodies);
+        bodies = older;
+        match page.next {
+            Some(before) => path = format!("/channels/default/messages?limit=2&before={before}"),
+            None => break,
+        }
+    }
+    assert_eq!(bodies, ["m1", "m2", "m3", "m4", "m5"]);
+
+    let response = api(http, Method::GET, "/channels/nowhere/messages", None).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn sessions_are_listed_and_kicked() {
+    let (addr, http) = start_http().await;
+    let mut alice = connect(addr, "alice").await;
+    let _bob = connect(addr, "bob").await;
+
+    let sessions: Vec<SessionInfo> = api(http, Method::GET, "/sessions", None)
+        .await
+        .json()
+        .await
+        .unwrap();
+    let names: Vec<&str> = sessions
+        .iter()
+        .map(|session| session.user.username.as_str())
+        .collect();
+    assert_eq!(names, ["alice", "bob"]);
+    assert_eq!(sessions[0].channels, ["another", "default"]);
+
+    let path = format!("/sessions/{}", sessions[0].id);
+    let response = api(http, Method::DELETE, &path, None).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(
+        next(&mut alice).await,
+        Frame::error(ErrorCode::Forbidden, "kicked by an administrator")
+    );
+    let closed = tokio::time::timeout(Duration::from_secs(5), alice.next())
+        .await
+        .unwrap();
+    assert!(closed.is_none());
+
+    // The session is gone once the server has cleaned up after it.
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let sessions: Vec<SessionInfo> = api(http, Method::GET, "/sessions", None)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].user.username, "bob");
+
+    let response = api(http, Method::DELETE, &path, None).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,314 @@
+mod common;
+
+use std::{
+    fs::File,
+    io::BufReader,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use chrono::Utc;
+use common::{connect, next, say, user, ADMIN_TOKEN};
+use futures::SinkExt;
+use protocol::{Channel, ErrorCode, Frame, Message};
+use reqwest::StatusCode;
+use server::{
+    archive::{Archive, ArchiveError, ChannelMeta, ARCHIVE_VERSION},
+    control::{ControlClient, ControlError, Request, Response},
+    http::ChannelInfo,
+    retention::Retention,
+    server::Reaction,
+    Server,
+};
+
+/// Starts a server with a control socket in `dir` and the admin token,
+/// returning its chat and HTTP addresses and the socket's path.
+async fn start(dir: &Path) -> (SocketAddr, SocketAddr, PathBuf) {
+    let socket = dir.join("control.sock");
+    let mut server = Server::bind("127.0.0.1:0").await.unwrap();
+    server.listen_control(&socket).unwrap();
+    let http = server.listen_http("127.0.0.1:0").await.unwrap();
+    server.admin_tokens = vec![ADMIN_TOKEN.to_string()];
+    let addr = server.addr;
+    tokio::spawn(server.run());
+    (addr, http, socket)
+}
+
+fn read(path: &Path) -> Archive {
+    Archive::read_jsonl(BufReader::new(File::open(path).unwrap())).unwrap()
+}
+
+#[tokio::test]
+async fn channels_move_between_servers() {
+    let dir = tempfile::tempdir().unwrap();
+    let (addr, _, socket) = start(dir.path()).await;
+    let mut alice = connect(addr, "alice").await;
+    let ops = Channel {
+        name: "ops".to_string(),
+        cover: Some("https://example.com/ops.png".to_string()),
+        messages: vec![],
+    };
+    alice.send(Frame::Channel(ops)).await.unwrap();
+    assert!(matches!(next(&mut alice).await, Frame::Bulk(..)));
+    let deploying = say(&mut alice, "alice", "ops", "deploying").await.seq;
+    say(&mut alice, "alice", "ops", "done").await;
+    alice
+        .send(Frame::React {
+            from: user("alice"),
+            channel: "ops".to_string(),
+            seq: deploying,
+            emoji: "🚀".to_string(),
+        })
+        .await
+        .unwrap();
+    assert!(matches!(next(&mut alice).await, Frame::React { .. }));
+
+    let mut control = ControlClient::connect(&socket).await.unwrap();
+    let retention = Request::SetRetention {
+        name: "ops".to_string(),
+        retention: "30d".parse().unwrap(),
+    };
+    control.request(&retention).await.unwrap();
+    let exported = dir.path().join("ops.jsonl");
+    let export = Request::Export {
+        name: "ops".to_string(),
+        path: exported.clone(),
+        html: false,
+    };
+    assert_eq!(
+        control.request(&export).await.unwrap(),
+        Response::Exported {
+            path: exported.clone(),
+            messages: 2,
+        }
+    );
+
+    // The other server has messages of its own, so the imported ones are
+    // renumbered.
+    let other = tempfile::tempdir().unwrap();
+    let (other_addr, _, other_socket) = start(other.path()).await;
+    let mut bob = connect(other_addr, "bob").await;
+    for body in ["one", "two", "three"] {
+        say(&mut bob, "bob", "default", body).await;
+    }
+    let mut other_control = ControlClient::connect(&other_socket).await.unwrap();
+    let import = Request::Import {
+        path: exported.clone(),
+        name: None,
+    };
+    assert_eq!(
+        other_control.request(&import).await.unwrap(),
+        Response::Imported {
+            name: "ops".to_string(),
+            messages: 2,
+        }
+    );
+    let Frame::Bulk(_, channels) = next(&mut bob).await else {
+        panic!("expected the new channel list");
+    };
+    assert!(channels.iter().any(|channel| channel.name == "ops"));
+
+    let reexported = other.path().join("ops.jsonl");
+    let export = Request::Export {
+        name: "ops".to_string(),
+        path: reexported.clone(),
+        html: false,
+    };
+    other_control.request(&export).await.unwrap();
+    let (before, after) = (read(&exported), read(&reexported));
+    assert_eq!(after.channel.cover, before.channel.cover);
+    assert_eq!(after.channel.retention.to_string(), "30d");
+    let contents = |archive: &Archive| -> Vec<(String, String)> {
+        archive
+            .messages
+            .iter()
+            .map(|msg| (msg.from.username.clone(), msg.body.clone()))
+            .collect()
+    };
+    assert_eq!(contents(&after), contents(&before));
+    assert_ne!(after.messages[0].seq, before.messages[0].seq);
+    assert_eq!(after.reactions.len(), 1);
+    assert_eq!(after.reactions[0].seq, after.messages[0].seq);
+    assert_eq!(after.reactions[0].emoji, "🚀");
+
+    // Importing again needs another name.
+    assert!(matches!(
+        other_control.request(&import).await,
+        Err(ControlError::Rejected {
+            code: ErrorCode::AlreadyExists,
+            ..
+        })
+    ));
+    // The refused import took no sequence numbers.
+    let next_seq = say(&mut bob, "bob", "default", "four").await.seq;
+    assert_eq!(next_seq, after.messages[1].seq + 1);
+    let import = Request::Import {
+        path: exported,
+        name: Some("ops-archive".to_string()),
+    };
+    assert_eq!(
+        other_control.request(&import).await.unwrap(),
+        Response::Imported {
+            name: "ops-archive".to_string(),
+            messages: 2,
+        }
+    );
+}
+
+#[tokio::test]
+async fn channels_are_exported_and_imported_over_http() {
+    let dir = tempfile::tempdir().unwrap();
+    let (addr, http, _) = start(dir.path()).await;
+    let mut alice = connect(addr, "alice").await;
+    say(&mut alice, "alice", "default", "<b>hi</b>").await;
+
+    let client = reqwest::Client::new();
+    let url = |path: &str| format!("http://{http}/api/channels/{path}");
+    let response = client
+        .get(url("default/export"))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let jsonl = response.bytes().await.unwrap();
+    assert_eq!(Archive::read_jsonl(&jsonl[..]).unwrap().messages.len(), 1);
+
+    let response = client
+        .post(url("copy/import"))
+        .bearer_auth(ADMIN_TOKEN)
+        .body(jsonl)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let info: ChannelInfo = response.json().await.unwrap();
+    assert_eq!((info.name.as_str(), info.messages), ("copy", 1));
+
+    let response = client
+        .get(url("copy/export?format=html"))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers()["content-type"],
+        "text/html; charset=utf-8"
+    );
+    let html = response.text().await.unwrap();
+    assert!(html.contains("&lt;b&gt;hi&lt;/b&gt;"));
+
+    let response = client
+        .post(url("broken/import"))
+        .bearer_auth(ADMIN_TOKEN)
+        .body("not json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+fn archive() -> Archive {
+    let mut script = Message::new(
+        user("mallory"),
+        "ops".to_string(),
+        "<script>alert('hi')</script>".to_string(),
+    );
+    script.seq = 4;
+    Archive {
+        channel: ChannelMeta {
+            version: ARCHIVE_VERSION,
+            name: "ops".to_string(),
+            cover: None,
+            retention: Retention::default(),
+            exported: Utc::now(),
+        },
+        messages: vec![script],
+        reactions: vec![Reaction {
+            seq: 4,
+            from: user("alice"),
+            emoji: "👀".to_string(),
+        }],
+    }
+}
+
+#[test]
+fn archives_read_back_what_was_written() {
+    let archive = archive();
+    let mut jsonl = vec![];
+    archive.write_jsonl(&mut jsonl).unwrap();
+    assert_eq!(String::from_utf8_lossy(&jsonl).lines().count(), 3);
+    assert_eq!(Archive::read_jsonl(&jsonl[..]).unwrap(), archive);
+}
+
+#[test]
+fn transcripts_escape_what_users_wrote() {
+    let mut html = vec![];
+    archive().write_html(&mut html).unwrap();
+    let html = String::from_utf8(html).unwrap();
+    assert!(html.contains("<title>#ops</title>"));
+    assert!(html.contains("&lt;script&gt;alert(&#39;hi&#39;)&lt;/script&gt;"));
+    assert!(!html.contains("<script>"));
+    assert!(html.contains("<li title=\"alice\">👀</li>"));
+}
+
+#[test]
+fn broken_archives_are_refused() {
+    let mut jsonl = vec![];
+    archive().write_jsonl(&mut jsonl).unwrap();
+    let jsonl = String::from_utf8(jsonl).unwrap();
+    let lines: Vec<&str> = jsonl.lines().collect();
+
+    let garbled = format!("{}\n{{\"type\":\"message\"}}\n", lines[0]);
+    assert!(matches!(
+        Archive::read_jsonl(garbled.as_bytes()),
+        Err(ArchiveError::Json { line: 2, .. })
+    ));
+    let headless = lines[1..].join("\n");
+    assert!(matches!(
+        Archive::read_jsonl(headless.as_bytes()),
+        Err(ArchiveError::MissingChannel)
+    ));
+    let newer = lines[0].replace(
+        &format!("\"version\":{ARCHIVE_VERSION}"),
+        &format!("\"version\":{}", ARCHIVE_VERSION + 1),
+    );
+    assert!(matches!(
+        Archive::read_jsonl(newer.as_bytes()),
+        Err(ArchiveError::UnsupportedVersion(_))
+    ));
+}
+
+#[tokio::test]
+async fn archives_are_held_to_the_limits_of_live_traffic() {
+    let server = Server::bind("127.0.0.1:0").await.unwrap();
+    let refused = |change: fn(&mut Archive)| {
+        let mut archive = archive();
+        change(&mut archive);
+        let server = &server;
+        async move { server.import(archive, None).await.unwrap_err().0 }
+    };
+
+    let repeated = |archive: &mut Archive| {
+        let mut again = archive.messages[0].clone();
+        again.body = "same seq".to_string();
+        archive.messages.push(again);
+    };
+    assert_eq!(refused(repeated).await, ErrorCode::InvalidFrame);
+    let dangling = |archive: &mut Archive| archive.reactions[0].seq = 5;
+    assert_eq!(refused(dangling).await, ErrorCode::InvalidFrame);
+    let long = |archive: &mut Archive| archive.messages[0].body = "x".repeat(64 * 1024);
+    assert_eq!(refused(long).await, ErrorCode::TooLarge);
+    let nameless = |archive: &mut Archive| archive.messages[0].from = user(" ");
+    assert_eq!(refused(nameless).await, ErrorCode::InvalidFrame);
+    let emoji = |archive: &mut Archive| archive.reactions[0].emoji = "👀".repeat(100);
+    assert_eq!(refused(emoji).await, ErrorCode::InvalidFrame);
+    let anonymous = |archive: &mut Archive| archive.reactions[0].from = user("");
+    assert_eq!(refused(anonymous).await, ErrorCode::InvalidFrame);
+
+    // None of them took a channel or sequence numbers.
+    assert_eq!(server.import(archive(), None).await.unwrap(), "ops");
+    let channels = server.channels.lock().await;
+    assert_eq!(channels["ops"].messages[0].seq, 1);
+    assert_eq!(channels["ops"].reactions[0].seq, 1);
+}
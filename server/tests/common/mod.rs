@@ -0,0 +1,143 @@
+//! Fixtures shared by the server tests. Every test file is its own crate and
+//! uses only some of them.
+#![allow(dead_code)]
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use futures::{SinkExt, StreamExt};
+use protocol::{ChatCodec, Frame, Message, User};
+use server::{
+    connection::{framed, PeerAddr},
+    Server,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, DuplexStream},
+    net::TcpStream,
+};
+use tokio_util::codec::Framed;
+
+/// A client connected over TCP.
+pub type Chat = Framed<TcpStream, ChatCodec>;
+/// A client connected over an in-memory pipe, see `connect_local`.
+pub type LocalChat = Framed<DuplexStream, ChatCodec>;
+
+/// Admin token of the servers started by `start_http`.
+pub const ADMIN_TOKEN: &str = "t0ken";
+
+pub fn user(name: &str) -> User {
+    User {
+        username: name.to_string(),
+        color: None,
+        avatar: None,
+        bot: false,
+    }
+}
+
+/// Clears the sequence number the server stamps on messages, so echoes
+/// compare equal to what was sent.
+pub fn unsequenced(frame: Frame) -> Frame {
+    match frame {
+        Frame::Message(mut message) => {
+            assert!(message.seq > 0, "message was not sequenced");
+            message.seq = 0;
+            Frame::Message(message)
+        }
+        frame => frame,
+    }
+}
+
+/// Starts a server serving the HTTP API with `ADMIN_TOKEN`, returning its
+/// chat and HTTP addresses.
+pub async fn start_http() -> (SocketAddr, SocketAddr) {
+    let mut server = Server::bind("127.0.0.1:0").await.unwrap();
+    let http = server.listen_http("127.0.0.1:0").await.unwrap();
+    server.admin_tokens = vec![ADMIN_TOKEN.to_string()];
+    let addr = server.addr;
+    tokio::spawn(server.run());
+    (addr, http)
+}
+
+pub async fn next<S>(chat: &mut Framed<S, ChatCodec>) -> Frame
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    tokio::time::timeout(Duration::from_secs(5), chat.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap()
+}
+
+/// Logs in as `name` over `stream`, returning the server's first answer.
+pub async fn authorize<S>(stream: S, name: &str) -> (Framed<S, ChatCodec>, Frame)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut chat = framed(stream);
+    chat.send(Frame::Authorize(user(name))).await.unwrap();
+    let frame = next(&mut chat).await;
+    (chat, frame)
+}
+
+/// Logs in as `name` over `stream` and expects the channel list back.
+pub async fn authorized<S>(stream: S, name: &str) -> Framed<S, ChatCodec>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (chat, frame) = authorize(stream, name).await;
+    assert!(matches!(frame, Frame::Bulk(..)), "{name} got {frame:?}");
+    chat
+}
+
+pub async fn connect(addr: SocketAddr, name: &str) -> Chat {
+    authorized(TcpStream::connect(addr).await.unwrap(), name).await
+}
+
+/// Hands one end of an in-memory pipe to the server without logging in yet.
+/// The session task must end cleanly whatever the client sends.
+pub fn open(server: &Arc<Server>, id: u64) -> LocalChat {
+    let (client, remote) = tokio::io::duplex(64 * 1024);
+    let server = Arc::clone(server);
+    tokio::spawn(async move {
+        server
+            .handle(framed(remote), PeerAddr::Local(id))
+            .await
+            .unwrap();
+    });
+    framed(client)
+}
+
+/// Connects to `server` over an in-memory pipe as `PeerAddr::Local(id)` and
+/// logs in as `name`.
+pub async fn connect_local(server: &Arc<Server>, id: u64, name: &str) -> LocalChat {
+    let mut chat = open(server, id);
+    chat.send(Frame::Authorize(user(name))).await.unwrap();
+    assert!(matches!(next(&mut chat).await, Frame::Bulk(..)));
+    chat
+}
+
+/// Posts `message` and waits for the echo.
+pub async fn post<S>(chat: &mut Framed<S, ChatCodec>, message: Message) -> Message
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    chat.send(Frame::Message(message)).await.unwrap();
+    let Frame::Message(message) = next(chat).await else {
+        panic!("expected the echo");
+    };
+    message
+}
+
+/// Posts `body` as `name` into `channel` and waits for the echo.
+pub async fn say<S>(
+    chat: &mut Framed<S, ChatCodec>,
+    name: &str,
+    channel: &str,
+    body: &str,
+) -> Message
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let message = Message::new(user(name), channel.to_string(), body.to_string());
+    post(chat, message).await
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::{connect_local, next, unsequenced, user};
+use futures::SinkExt;
+use protocol::{ChatCodec, Encoding, ErrorCode, Frame, Message};
+use server::{
+    connection::{framed, PeerAddr},
+    Server,
+};
+use tokio_util::codec::Framed;
+
+#[tokio::test]
+async fn sessions_run_over_in_memory_pipes() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+
+    let mut alice = connect_local(&server, 1, "alice").await;
+    let mut bob = connect_local(&server, 2, "bob").await;
+
+    let message = Message::new(user("alice"), "default".to_string(), "hi".to_string());
+    alice.send(Frame::Message(message.clone())).await.unwrap();
+    assert_eq!(
+        unsequenced(next(&mut alice).await),
+        Frame::Message(message.clone())
+    );
+    assert_eq!(unsequenced(next(&mut bob).await), Frame::Message(message));
+
+    drop(alice);
+    assert_eq!(next(&mut bob).await, Frame::Disconnect(user("alice")));
+}
+
+#[tokio::test]
+async fn frames_written_back_to_back_are_not_merged() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+
+    let mut alice = connect_local(&server, 1, "alice").await;
+    let mut bob = connect_local(&server, 2, "bob").await;
+
+    let messages: Vec<Message> = (0..100)
+        .map(|i| Message::new(user("alice"), "default".to_string(), i.to_string()))
+        .collect();
+    for message in &messages {
+        alice.feed(Frame::Message(message.clone())).await.unwrap();
+    }
+    alice.flush().await.unwrap();
+
+    for message in messages {
+        assert_eq!(unsequenced(next(&mut bob).await), Frame::Message(message));
+    }
+}
+
+#[tokio::test]
+async fn connections_count_against_the_limit() {
+    let mut server = Server::bind("127.0.0.1:0").await.unwrap();
+    server.max_connetions = Arc::new(tokio::sync::Semaphore::new(1));
+    let server = Arc::new(server);
+
+    let _alice = connect_local(&server, 1, "alice").await;
+
+    let (client, remote) = tokio::io::duplex(1024);
+    let handle = {
+        let server = Arc::clone(&server);
+        tokio::spawn(async move { server.handle(framed(remote), PeerAddr::Local(2)).await })
+    };
+    let mut bob = framed(client);
+    assert_eq!(
+        next(&mut bob).await,
+        Frame::error(ErrorCode::ServerBusy, "max connections reached")
+    );
+    handle.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn clients_with_different_encodings_chat_together() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let mut bob = connect_local(&server, 1, "bob").await;
+
+    let mut peers = vec![];
+    for (id, encoding) in [(2, Encoding::Json), (3, Encoding::MessagePack)] {
+        let (client, remote) = tokio::io::duplex(64 * 1024);
+        let server = Arc::clone(&server);
+        tokio::spawn(async move {
+            server
+                .handle(framed(remote), PeerAddr::Local(id))
+                .await
+                .unwrap();
+        });
+
+        let mut chat = Framed::new(client, ChatCodec::with_encoding(encoding));
+        chat.send(Frame::Authorize(user(encoding.name())))
+            .await
+            .unwrap();
+        assert!(matches!(next(&mut chat).await, Frame::Bulk(..)));
+        assert_eq!(chat.codec().encoding(), encoding);
+        peers.push(chat);
+    }
+
+    // Everyone gets every message in their own encoding.
+    for i in 0..peers.len() {
+        let name = peers[i].codec().encoding().name();
+        let message = Message::new(user(name), "default".to_string(), name.to_string());
+        peers[i]
+            .send(Frame::Message(message.clone()))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            unsequenced(next(&mut bob).await),
+            Frame::Message(message.clone())
+        );
+        for chat in peers.iter_mut() {
+            assert_eq!(
+                unsequenced(next(chat).await),
+                Frame::Message(message.clone())
+            );
+        }
+    }
+}
@@ -0,0 +1,247 @@
+mod common;
+
+use std::{net::SocketAddr, os::unix::fs::PermissionsExt, path::Path, sync::Arc, time::Duration};
+
+use common::{authorize, connect, next, open, user, Chat};
+use futures::{SinkExt, StreamExt};
+use protocol::{ErrorCode, Frame, Message};
+use server::{
+    control::{ControlClient, ControlError, Request, Response, Snapshot},
+    Server,
+};
+use tokio::net::TcpStream;
+
+/// Starts a server with its control socket at `socket`, returning its chat
+/// address.
+async fn start(socket: &Path) -> SocketAddr {
+    let mut server = Server::bind("127.0.0.1:0").await.unwrap();
+    server.listen_control(socket).unwrap();
+    let addr = server.addr;
+    tokio::spawn(server.run());
+    addr
+}
+
+async fn login(addr: SocketAddr, name: &str) -> (Chat, Frame) {
+    authorize(TcpStream::connect(addr).await.unwrap(), name).await
+}
+
+#[tokio::test]
+async fn sessions_are_listed_kicked_and_banned() {
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("control.sock");
+    let addr = start(&socket).await;
+    let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    let _alice = connect(addr, "alice").await;
+    let mut bob = connect(addr, "bob").await;
+    let mut control = ControlClient::connect(&socket).await.unwrap();
+
+    let Response::Sessions(sessions) = control.request(&Request::Sessions).await.unwrap() else {
+        panic!("expected the sessions");
+    };
+    let names: Vec<&str> = sessions
+        .iter()
+        .map(|session| session.user.username.as_str())
+        .collect();
+    assert_eq!(names, ["alice", "bob"]);
+
+    let ban = Request::Ban {
+        username: "bob".to_string(),
+    };
+    assert_eq!(
+        control.request(&ban).await.unwrap(),
+        Response::Kicked(vec![sessions[1].id])
+    );
+    assert_eq!(
+        next(&mut bob).await,
+        Frame::error(ErrorCode::Forbidden, "kicked by an administrator")
+    );
+    let (_, refused) = login(addr, "bob").await;
+    assert_eq!(
+        refused,
+        Frame::error(ErrorCode::Forbidden, "banned by an administrator")
+    );
+    assert_eq!(
+        control.request(&Request::Bans).await.unwrap(),
+        Response::Bans(vec!["bob".to_string()])
+    );
+
+    let unban = Request::Unban {
+        username: "bob".to_string(),
+    };
+    assert_eq!(control.request(&unban).await.unwrap(), Response::Done);
+    connect(addr, "bob").await;
+    assert!(matches!(
+        control.request(&unban).await,
+        Err(ControlError::Rejected {
+            code: ErrorCode::NotFound,
+            ..
+        })
+    ));
+
+    let kick = Request::Kick { session: 999 };
+    assert!(matches!(
+        control.request(&kick).await,
+        Err(ControlError::Rejected {
+            code: ErrorCode::NotFound,
+            ..
+        })
+    ));
+}
+
+#[tokio::test]
+async fn channels_are_managed_and_notices_reach_everyone() {
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("control.sock");
+    let addr = start(&socket).await;
+    let mut alice = connect(addr, "alice").await;
+    let mut control = ControlClient::connect(&socket).await.unwrap();
+
+    let create = Request::CreateChannel {
+        name: "ops".to_string(),
+        cover: None,
+    };
+    assert_eq!(control.request(&create).await.unwrap(), Response::Done);
+    assert!(matches!(next(&mut alice).await, Frame::Bulk(..)));
+
+    let message = Message::new(user("alice"), "ops".to_string(), "anyone?".to_string());
+    alice.send(Frame::Message(message)).await.unwrap();
+    assert!(matches!(next(&mut alice).await, Frame::Message(_)));
+
+    let inspect = Request::Channel {
+        name: "ops".to_string(),
+    };
+    let Response::Channel(state) = control.request(&inspect).await.unwrap() else {
+        panic!("expected the channel");
+    };
+    assert_eq!(state.info.messages, 1);
+    assert_eq!(state.members, ["alice"]);
+    assert_eq!(state.recent[0].body, "anyone?");
+
+    let notice = Request::Notice {
+        body: "restarting at noon".to_string(),
+        channel: None,
+    };
+    assert_eq!(control.request(&notice).await.unwrap(), Response::Done);
+    let mut channels = vec![];
+    for _ in 0..3 {
+        let Frame::Message(message) = next(&mut alice).await else {
+            panic!("expected the notice");
+        };
+        assert_eq!(message.from.username, "server");
+        assert!(message.from.bot);
+        assert_eq!(message.body, "restarting at noon");
+        channels.push(message.channel);
+    }
+    channels.sort();
+    assert_eq!(channels, ["another", "default", "ops"]);
+
+    let delete = Request::DeleteChannel {
+        name: "ops".to_string(),
+    };
+    assert_eq!(control.request(&delete).await.unwrap(), Response::Done);
+    assert!(matches!(next(&mut alice).await, Frame::Bulk(..)));
+    assert!(matches!(
+        control.request(&inspect).await,
+        Err(ControlError::Rejected {
+            code: ErrorCode::NotFound,
+            ..
+        })
+    ));
+}
+
+#[tokio::test]
+async fn snapshots_and_log_levels_are_taken_at_runtime() {
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("control.sock");
+    let addr = start(&socket).await;
+    let mut alice = connect(addr, "alice").await;
+    for body in ["one", "two"] {
+        let message = Message::new(user("alice"), "default".to_string(), body.to_string());
+        alice.send(Frame::Message(message)).await.unwrap();
+        assert!(matches!(next(&mut alice).await, Frame::Message(_)));
+    }
+    let mut control = ControlClient::connect(&socket).await.unwrap();
+
+    let path = dir.path().join("snapshot.json");
+    let response = control
+        .request(&Request::Snapshot { path: path.clone() })
+        .await
+        .unwrap();
+    assert_eq!(
+        response,
+        Response::Snapshot {
+            path: path.clone(),
+            channels: 2,
+            messages: 2
+        }
+    );
+    let snapshot: Snapshot = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    let bodies: Vec<&str> = snapshot.channels[1]
+        .messages
+        .iter()
+        .map(|message| message.body.as_str())
+        .collect();
+    assert_eq!(snapshot.channels[1].name, "default");
+    assert_eq!(bodies, ["one", "two"]);
+
+    let missing = dir.path().join("nowhere").join("snapshot.json");
+    assert!(matches!(
+        control.request(&Request::Snapshot { path: missing }).await,
+        Err(ControlError::Rejected {
+            code: ErrorCode::NotFound,
+            ..
+        })
+    ));
+
+    let level = Request::LogLevel {
+        filter: "server=info".to_string(),
+    };
+    assert_eq!(control.request(&level).await.unwrap(), Response::Done);
+    let level = Request::LogLevel {
+        filter: "server=loud".to_string(),
+    };
+    assert!(matches!(
+        control.request(&level).await,
+        Err(ControlError::Rejected {
+            code: ErrorCode::InvalidFrame,
+            ..
+        })
+    ));
+}
+
+#[tokio::test]
+async fn logins_racing_a_ban_are_refused_or_kicked() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    // Holding the channels stalls the logins halfway, wherever the ban check
+    // comes before that.
+    let channels = server.channels.lock().await;
+    let logins: Vec<_> = (0..10)
+        .map(|id| {
+            let mut chat = open(&server, id);
+            tokio::spawn(async move {
+                chat.send(Frame::Authorize(user("mallory"))).await.unwrap();
+                // Refused, or let in and kicked soon after.
+                loop {
+                    match chat.next().await {
+                        Some(Ok(Frame::Error { code, .. })) => return code,
+                        Some(Ok(_)) => {}
+                        frame => panic!("expected an error, got {frame:?}"),
+                    }
+                }
+            })
+        })
+        .collect();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    server.sessions.ban("mallory");
+    drop(channels);
+
+    for login in logins {
+        let code = tokio::time::timeout(Duration::from_secs(5), login)
+            .await
+            .expect("a login got past the ban")
+            .unwrap();
+        assert_eq!(code, ErrorCode::Forbidden);
+    }
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use common::{connect, next, user};
+use futures::{SinkExt, StreamExt};
+use protocol::Frame;
+use server::{server::Shared, Server};
+use tokio::sync::Mutex;
+
+type Channels = Arc<Mutex<HashMap<String, Shared>>>;
+
+async fn start() -> (std::net::SocketAddr, Channels) {
+    let server = Server::bind("127.0.0.1:0").await.unwrap();
+    let addr = server.addr;
+    let channels = Arc::clone(&server.channels);
+    tokio::spawn(server.run());
+    (addr, channels)
+}
+
+/// Waits until every channel holds exactly `count` peers.
+async fn wait_for_peers(channels: &Channels, count: usize) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if channels
+                .lock()
+                .await
+                .values()
+                .all(|shared| shared.peers.len() == count)
+            {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("channels did not settle on {count} peers"));
+}
+
+#[tokio::test]
+async fn disconnected_peers_are_removed_from_every_channel() {
+    let (addr, channels) = start().await;
+
+    let mut clients = vec![];
+    for i in 0..50 {
+        clients.push(connect(addr, &format!("user{i}")).await);
+    }
+    wait_for_peers(&channels, 50).await;
+
+    clients.truncate(20);
+    wait_for_peers(&channels, 20).await;
+
+    clients.clear();
+    wait_for_peers(&channels, 0).await;
+}
+
+#[tokio::test]
+async fn remaining_peers_are_told_about_disconnect() {
+    let (addr, channels) = start().await;
+
+    let mut alice = connect(addr, "alice").await;
+    let bob = connect(addr, "bob").await;
+    wait_for_peers(&channels, 2).await;
+
+    drop(bob);
+
+    assert_eq!(next(&mut alice).await, Frame::Disconnect(user("bob")));
+    wait_for_peers(&channels, 1).await;
+}
+
+#[tokio::test]
+async fn peers_that_stop_answering_heartbeats_are_evicted() {
+    let mut server = Server::bind("127.0.0.1:0").await.unwrap();
+    server.heartbeat_interval = Duration::from_millis(50);
+    server.max_missed_heartbeats = 2;
+    let addr = server.addr;
+    let channels = Arc::clone(&server.channels);
+    tokio::spawn(server.run());
+
+    let mut alice = connect(addr, "alice").await;
+    let mut silent = connect(addr, "silent").await;
+    wait_for_peers(&channels, 2).await;
+
+    // Alice answers every ping and stays, the silent peer reads its pings
+    // but never answers them.
+    let frame = loop {
+        match next(&mut alice).await {
+            Frame::Ping { id, .. } => alice.send(Frame::Pong(id)).await.unwrap(),
+            frame => break frame,
+        }
+    };
+    assert_eq!(frame, Frame::Disconnect(user("silent")));
+    wait_for_peers(&channels, 1).await;
+
+    let closed = tokio::time::timeout(Duration::from_secs(5), async {
+        while let Some(frame) = silent.next().await {
+            assert!(matches!(frame, Ok(Frame::Ping { .. })), "got {frame:?}");
+        }
+    })
+    .await;
+    assert!(closed.is_ok(), "the silent peer's connection stayed open");
+}
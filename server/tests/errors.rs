@@ -0,0 +1,118 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use common::{connect_local, next, open, unsequenced, user, LocalChat};
+use futures::{SinkExt, StreamExt};
+use protocol::{ErrorCode, Frame, Message};
+use server::Server;
+use tokio::io::AsyncWriteExt;
+
+async fn expect_error(chat: &mut LocalChat, expected: ErrorCode) {
+    match next(chat).await {
+        Frame::Error { code, .. } => assert_eq!(code, expected),
+        frame => panic!("expected {expected:?}, got {frame:?}"),
+    }
+}
+
+async fn expect_end(chat: &mut LocalChat) {
+    let end = tokio::time::timeout(Duration::from_secs(5), chat.next())
+        .await
+        .unwrap();
+    assert!(end.is_none(), "unexpected {end:?}");
+}
+
+#[tokio::test]
+async fn first_frame_has_to_authorize() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let mut chat = open(&server, 1);
+
+    chat.send(Frame::Pong(1)).await.unwrap();
+    expect_error(&mut chat, ErrorCode::Unauthorized).await;
+}
+
+#[tokio::test]
+async fn empty_usernames_are_refused() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let mut chat = open(&server, 1);
+
+    chat.send(Frame::Authorize(user(" "))).await.unwrap();
+    expect_error(&mut chat, ErrorCode::InvalidFrame).await;
+}
+
+#[tokio::test]
+async fn undecodable_frames_are_reported() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let mut alice = connect_local(&server, 1, "alice").await;
+
+    // A length prefixed payload that is no bincode frame.
+    alice
+        .get_mut()
+        .write_all(&[0, 0, 0, 4, 0xff, 0xff, 0xff, 0xff])
+        .await
+        .unwrap();
+    expect_error(&mut alice, ErrorCode::InvalidFrame).await;
+    expect_end(&mut alice).await;
+}
+
+#[tokio::test]
+async fn oversized_frames_end_the_session() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let mut alice = connect_local(&server, 1, "alice").await;
+
+    alice
+        .get_mut()
+        .write_all(&u32::MAX.to_be_bytes())
+        .await
+        .unwrap();
+    expect_error(&mut alice, ErrorCode::TooLarge).await;
+    expect_end(&mut alice).await;
+}
+
+#[tokio::test]
+async fn messages_cannot_impersonate_others() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let mut mallory = connect_local(&server, 1, "mallory").await;
+
+    let forged = Message::new(user("alice"), "default".to_string(), "hi".to_string());
+    mallory
+        .send(Frame::request(1, Frame::Message(forged.clone())))
+        .await
+        .unwrap();
+    assert!(matches!(
+        next(&mut mallory).await,
+        Frame::Rejected {
+            id: 1,
+            code: ErrorCode::Forbidden,
+            ..
+        }
+    ));
+
+    // Without a request id the refusal comes as an error frame.
+    mallory.send(Frame::Message(forged)).await.unwrap();
+    expect_error(&mut mallory, ErrorCode::Forbidden).await;
+}
+
+#[tokio::test]
+async fn unknown_channels_do_not_crash_the_session() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let mut alice = connect_local(&server, 1, "alice").await;
+
+    let lost = Message::new(user("alice"), "nowhere".to_string(), "hi".to_string());
+    alice.send(Frame::Message(lost)).await.unwrap();
+    expect_error(&mut alice, ErrorCode::NotFound).await;
+
+    let hello = Message::new(user("alice"), "default".to_string(), "hi".to_string());
+    alice.send(Frame::Message(hello.clone())).await.unwrap();
+    assert_eq!(unsequenced(next(&mut alice).await), Frame::Message(hello));
+}
+
+#[tokio::test]
+async fn huge_messages_are_refused() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let mut alice = connect_local(&server, 1, "alice").await;
+
+    let huge = Message::new(user("alice"), "default".to_string(), "a".repeat(1 << 20));
+    alice.send(Frame::Message(huge)).await.unwrap();
+    expect_error(&mut alice, ErrorCode::TooLarge).await;
+}
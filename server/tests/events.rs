@@ -0,0 +1,265 @@
+mod common;
+
+use std::{net::SocketAddr, time::Duration};
+
+use common::{connect, next, say, start_http, user, Chat, ADMIN_TOKEN};
+use futures::SinkExt;
+use protocol::{Channel, Frame};
+use reqwest::StatusCode;
+use serde_json::Value;
+use server::http::ChannelInfo;
+
+/// One parsed Server-Sent Event.
+#[derive(Debug)]
+struct Event {
+    name: String,
+    id: Option<String>,
+    data: Value,
+}
+
+/// Reads Server-Sent Events off a streaming response.
+struct Events {
+    response: reqwest::Response,
+    buffer: String,
+}
+
+impl Events {
+    async fn open(http: SocketAddr, query: &str, last_event_id: Option<u64>) -> Self {
+        let mut request = reqwest::Client::new()
+            .get(format!("http://{http}/events{query}"))
+            .bearer_auth(ADMIN_TOKEN);
+        if let Some(id) = last_event_id {
+            request = request.header("Last-Event-ID", id.to_string());
+        }
+        let response = request.send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+        Events {
+            response,
+            buffer: String::new(),
+        }
+    }
+
+    async fn next(&mut self) -> Event {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(end) = self.buffer.find("\n\n") {
+                    let block: String = self.buffer.drain(..end + 2).collect();
+                    let (mut name, mut id, mut data) = (None, None, String::new());
+                    for line in block.lines() {
+                        match line.split_once(':') {
+                            Some(("event", value)) => name = Some(value.trim().to_string()),
+                            Some(("id", value)) => id = Some(value.trim().to_string()),
+                            Some(("data", value)) => data.push_str(value.trim()),
+                            _ => {}
+                        }
+                    }
+                    // Keep-alive comments carry no event.
+                    if let Some(name) = name {
+                        let data = serde_json::from_str(&data).unwrap();
+                        return Event { name, id, data };
+                    }
+                    continue;
+                }
+                let chunk = self.response.chunk().await.unwrap().expect("stream ended");
+                self.buffer.push_str(std::str::from_utf8(&chunk).unwrap());
+            }
+        })
+        .await
+        .expect("no event arrived")
+    }
+}
+
+#[tokio::test]
+async fn messages_of_the_selected_channels_are_streamed() {
+    let (addr, http) = start_http().await;
+    let mut alice = connect(addr, "alice").await;
+    let mut events = Events::open(http, "?channels=default", None).await;
+
+    say(&mut alice, "alice", "another", "elsewhere").await;
+    let seq = say(&mut alice, "alice", "default", "hello wall").await.seq;
+
+    let event = events.next().await;
+    assert_eq!(event.name, "message");
+    assert_eq!(event.id, Some(seq.to_string()));
+    assert_eq!(event.data["body"], "hello wall");
+    assert_eq!(event.data["channel"], "default");
+    assert_eq!(event.data["from"]["username"], "alice");
+}
+
+#[tokio::test]
+async fn missed_messages_are_replayed_after_the_last_event_id() {
+    let (addr, http) = start_http().await;
+    let mut alice = connect(addr, "alice").await;
+    let first = say(&mut alice, "alice", "default", "one").await.seq;
+    say(&mut alice, "alice", "another", "two").await;
+    say(&mut alice, "alice", "default", "three").await;
+
+    let mut events = Events::open(http, "", Some(first)).await;
+    say(&mut alice, "alice", "default", "four").await;
+
+    let mut bodies = vec![];
+    let mut ids = vec![];
+    for _ in 0..3 {
+        let event = events.next().await;
+        bodies.push(event.data["body"].as_str().unwrap().to_string());
+        ids.push(event.id.unwrap().parse::<u64>().unwrap());
+    }
+    assert_eq!(bodies, ["two", "three", "four"]);
+    assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(ids[0] > first);
+}
+
+#[tokio::test]
+async fn reactions_and_departures_are_streamed() {
+    let (addr, http) = start_http().await;
+    let mut alice = connect(addr, "alice").await;
+    let bob = connect(addr, "bob").await;
+    let seq = say(&mut alice, "alice", "default", "react to me").await.seq;
+    let mut events = Events::open(http, "?channels=default", None).await;
+
+    alice
+        .send(Frame::React {
+            from: user("alice"),
+            channel: "default".to_string(),
+            seq,
+            emoji: "👍".to_string(),
+        })
+        .await
+        .unwrap();
+    let event = events.next().await;
+    assert_eq!(event.name, "reaction");
+    assert_eq!(event.data["seq"], seq);
+    assert_eq!(event.data["emoji"], "👍");
+    assert_eq!(event.id, None);
+
+    drop(bob);
+    let event = events.next().await;
+    assert_eq!(event.name, "left");
+    assert_eq!(event.data["user"]["username"], "bob");
+    assert_eq!(event.data["channel"], Value::Null);
+}
+
+#[tokio::test]
+async fn joining_and_leaving_a_channel_is_streamed() {
+    let (addr, http) = start_http().await;
+    let mut bob = connect(addr, "bob").await;
+    let mut events = Events::open(http, "?channels=default", None).await;
+
+    bob.send(Frame::request(1, Frame::Leave("default".to_string())))
+        .await
+        .unwrap();
+    assert_eq!(next(&mut bob).await, Frame::Ack(1));
+    let event = events.next().await;
+    assert_eq!(event.name, "left");
+    assert_eq!(event.data["channel"], "default");
+    assert_eq!(event.data["user"]["username"], "bob");
+
+    bob.send(Frame::request(2, Frame::Join("default".to_string())))
+        .await
+        .unwrap();
+    assert!(matches!(next(&mut bob).await, Frame::Joined(_)));
+    let event = events.next().await;
+    assert_eq!(event.name, "joined");
+    assert_eq!(event.data["channel"], "default");
+    assert_eq!(event.data["user"]["username"], "bob");
+    assert_eq!(event.id, None);
+}
+
+/// Creates the channel `name` as `chat`'s user.
+async fn create(chat: &mut Chat, name: &str) {
+    let channel = Channel {
+        name: name.to_string(),
+        cover: None,
+        messages: vec![],
+    };
+    chat.send(Frame::Channel(channel)).await.unwrap();
+    assert!(matches!(next(chat).await, Frame::Bulk(..)));
+}
+
+#[tokio::test]
+async fn channels_created_later_are_only_streamed_unfiltered() {
+    let (addr, http) = start_http().await;
+    let mut alice = connect(addr, "alice").await;
+    let mut picked = Events::open(http, "?channels=default", None).await;
+    let mut everything = Events::open(http, "", None).await;
+
+    create(&mut alice, "ops").await;
+    assert_eq!(picked.next().await.name, "channels");
+    assert_eq!(everything.next().await.name, "channels");
+    say(&mut alice, "alice", "ops", "not for default").await;
+    say(&mut alice, "alice", "default", "for default").await;
+
+    assert_eq!(picked.next().await.data["body"], "for default");
+    assert_eq!(everything.next().await.data["body"], "not for default");
+    assert_eq!(everything.next().await.data["body"], "for default");
+
+    // Streams are not members.
+    let channels: Vec<ChannelInfo> = reqwest::Client::new()
+        .get(format!("http://{http}/api/channels"))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(channels.iter().all(|channel| channel.members == 1));
+}
+
+#[tokio::test]
+async fn departures_are_only_streamed_from_the_channels_picked() {
+    let (addr, http) = start_http().await;
+    let mut alice = connect(addr, "alice").await;
+    let mut bob = connect(addr, "bob").await;
+    create(&mut alice, "ops").await;
+    next(&mut bob).await;
+    bob.send(Frame::request(1, Frame::Leave("ops".to_string())))
+        .await
+        .unwrap();
+    assert_eq!(next(&mut bob).await, Frame::Ack(1));
+    let mut events = Events::open(http, "?channels=ops", None).await;
+
+    drop(bob);
+    assert_eq!(
+        next(&mut alice).await,
+        Frame::PeerLeft {
+            channel: "ops".to_string(),
+            user: user("bob"),
+        }
+    );
+    assert_eq!(next(&mut alice).await, Frame::Disconnect(user("bob")));
+    say(&mut alice, "alice", "ops", "bob was never here").await;
+    let event = events.next().await;
+    assert_eq!(event.name, "message");
+    assert_eq!(event.data["body"], "bob was never here");
+}
+
+#[tokio::test]
+async fn the_stream_needs_a_token_and_known_channels() {
+    let (_, http) = start_http().await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("http://{http}/events"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // Browsers cannot set headers on an EventSource.
+    let response = client
+        .get(format!("http://{http}/events?token=t0ken"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = client
+        .get(format!("http://{http}/events?channels=default,nowhere"))
+        .bearer_auth("t0ken")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::{connect_local, next, say, user, LocalChat};
+use futures::SinkExt;
+use protocol::{ErrorCode, Frame, Message};
+use server::Server;
+
+/// Sends `count` messages as alice, returning them as the server stored them.
+async fn post(alice: &mut LocalChat, count: usize) -> Vec<Message> {
+    let mut sent = vec![];
+    for i in 0..count {
+        sent.push(say(alice, "alice", "default", &i.to_string()).await);
+    }
+    sent
+}
+
+async fn history(chat: &mut LocalChat, before: Option<u64>, limit: u32) -> Vec<Message> {
+    chat.send(Frame::History {
+        channel: "default".to_string(),
+        before,
+        limit,
+    })
+    .await
+    .unwrap();
+    match next(chat).await {
+        Frame::HistoryPage { channel, messages } => {
+            assert_eq!(channel, "default");
+            messages
+        }
+        frame => panic!("expected a history page, got {frame:?}"),
+    }
+}
+
+#[tokio::test]
+async fn messages_are_numbered_in_order() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let mut alice = connect_local(&server, 1, "alice").await;
+
+    let sent = post(&mut alice, 5).await;
+    assert!(sent[0].seq > 0);
+    assert!(sent.windows(2).all(|pair| pair[0].seq < pair[1].seq));
+}
+
+#[tokio::test]
+async fn history_pages_backwards() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let mut alice = connect_local(&server, 1, "alice").await;
+    let sent = post(&mut alice, 10).await;
+
+    let latest = history(&mut alice, None, 4).await;
+    assert_eq!(latest, sent[6..]);
+
+    let older = history(&mut alice, Some(latest[0].seq), 4).await;
+    assert_eq!(older, sent[2..6]);
+
+    let oldest = history(&mut alice, Some(older[0].seq), 4).await;
+    assert_eq!(oldest, sent[..2]);
+    assert!(history(&mut alice, Some(oldest[0].seq), 4).await.is_empty());
+}
+
+#[tokio::test]
+async fn left_channels_can_be_joined_again() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let mut alice = connect_local(&server, 1, "alice").await;
+    let mut bob = connect_local(&server, 2, "bob").await;
+    let sent = post(&mut alice, 1).await;
+    assert_eq!(next(&mut bob).await, Frame::Message(sent[0].clone()));
+
+    bob.send(Frame::request(1, Frame::Leave("default".to_string())))
+        .await
+        .unwrap();
+    assert_eq!(next(&mut bob).await, Frame::Ack(1));
+    assert_eq!(
+        next(&mut alice).await,
+        Frame::PeerLeft {
+            channel: "default".to_string(),
+            user: user("bob"),
+        }
+    );
+
+    // Bob no longer hears from the channel nor may post to it.
+    post(&mut alice, 1).await;
+    let message = Message::new(user("bob"), "default".to_string(), "hi".to_string());
+    bob.send(Frame::request(2, Frame::Message(message)))
+        .await
+        .unwrap();
+    assert!(matches!(
+        next(&mut bob).await,
+        Frame::Rejected {
+            id: 2,
+            code: ErrorCode::Forbidden,
+            ..
+        }
+    ));
+
+    bob.send(Frame::request(3, Frame::Join("default".to_string())))
+        .await
+        .unwrap();
+    let Frame::Joined(channel) = next(&mut bob).await else {
+        panic!("expected the joined channel");
+    };
+    assert_eq!(channel.name, "default");
+    assert_eq!(channel.messages.len(), 2);
+    assert_eq!(next(&mut bob).await, Frame::Ack(3));
+    assert_eq!(
+        next(&mut alice).await,
+        Frame::PeerJoined {
+            channel: "default".to_string(),
+            user: user("bob"),
+        }
+    );
+}
+
+#[tokio::test]
+async fn unknown_channels_cannot_be_joined() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let mut alice = connect_local(&server, 1, "alice").await;
+
+    alice
+        .send(Frame::request(1, Frame::Join("nowhere".to_string())))
+        .await
+        .unwrap();
+    assert!(matches!(
+        next(&mut alice).await,
+        Frame::Rejected {
+            id: 1,
+            code: ErrorCode::NotFound,
+            ..
+        }
+    ));
+}
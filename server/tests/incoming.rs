@@ -0,0 +1,134 @@
+mod common;
+
+use std::{net::SocketAddr, time::Duration};
+
+use common::{connect, next};
+use futures::{SinkExt, StreamExt};
+use protocol::{Frame, Message};
+use reqwest::StatusCode;
+use serde_json::json;
+use server::Server;
+
+/// Starts a server with a webhook token for `default`, returning its chat
+/// and HTTP addresses.
+async fn start() -> (SocketAddr, SocketAddr) {
+    let mut server = Server::bind("127.0.0.1:0").await.unwrap();
+    let http = server.listen_http("127.0.0.1:0").await.unwrap();
+    server
+        .hook_tokens
+        .insert("default".to_string(), "s3cret".to_string());
+    let addr = server.addr;
+    tokio::spawn(server.run());
+    (addr, http)
+}
+
+async fn post(
+    http: SocketAddr,
+    channel: &str,
+    token: Option<&str>,
+    body: serde_json::Value,
+) -> reqwest::Response {
+    let mut request = reqwest::Client::new()
+        .post(format!("http://{http}/hooks/{channel}"))
+        .json(&body);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    request.send().await.unwrap()
+}
+
+#[tokio::test]
+async fn hook_messages_are_broadcast_and_kept() {
+    let (addr, http) = start().await;
+    let mut alice = connect(addr, "alice").await;
+
+    let response = post(
+        http,
+        "default",
+        Some("s3cret"),
+        json!({"body": "build #42 passed", "username": "ci", "avatar": "https://ci.example/logo.png"}),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let posted: Message = response.json().await.unwrap();
+    assert!(posted.seq > 0);
+
+    let Frame::Message(received) = next(&mut alice).await else {
+        panic!("expected the hook message");
+    };
+    assert_eq!(received, posted);
+    assert_eq!(received.body, "build #42 passed");
+    assert_eq!(received.from.username, "ci");
+    assert_eq!(
+        received.from.avatar.as_deref(),
+        Some("https://ci.example/logo.png")
+    );
+    assert!(received.from.bot);
+
+    // It is part of the channel history like any other message.
+    alice
+        .send(Frame::History {
+            channel: "default".to_string(),
+            before: None,
+            limit: 10,
+        })
+        .await
+        .unwrap();
+    let Frame::HistoryPage { messages, .. } = next(&mut alice).await else {
+        panic!("expected a history page");
+    };
+    assert_eq!(messages, [posted]);
+}
+
+#[tokio::test]
+async fn hook_messages_default_to_a_webhook_sender() {
+    let (addr, http) = start().await;
+    let mut alice = connect(addr, "alice").await;
+
+    let response = post(http, "default", Some("s3cret"), json!({"body": "hi"})).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let Frame::Message(received) = next(&mut alice).await else {
+        panic!("expected the hook message");
+    };
+    assert_eq!(received.from.username, "webhook");
+    assert!(received.from.bot);
+}
+
+#[tokio::test]
+async fn hooks_need_the_channel_token() {
+    let (addr, http) = start().await;
+    let mut alice = connect(addr, "alice").await;
+    let hello = json!({"body": "let me in"});
+
+    let response = post(http, "default", None, hello.clone()).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let error: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(error["code"], "Unauthorized");
+
+    let response = post(http, "default", Some("guess"), hello.clone()).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // `another` exists but takes no webhooks.
+    let response = post(http, "another", Some("s3cret"), hello.clone()).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = post(http, "default", Some("s3cret"), json!({"body": "  "})).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = post(
+        http,
+        "default",
+        Some("s3cret"),
+        json!({"body": "x".repeat(64 * 1024)}),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    // None of them got through.
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), alice.next())
+            .await
+            .is_err()
+    );
+}
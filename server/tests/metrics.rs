@@ -0,0 +1,119 @@
+mod common;
+
+use std::{net::SocketAddr, sync::Arc};
+
+use common::{connect, next, say, user};
+use futures::SinkExt;
+use protocol::{Frame, Message};
+use server::{
+    connection::framed,
+    control::{ControlClient, Request, Response},
+    Server,
+};
+use tokio::{io::AsyncWriteExt, net::TcpStream, sync::Semaphore};
+
+async fn scrape(metrics: SocketAddr) -> String {
+    let response = reqwest::get(format!("http://{metrics}/metrics"))
+        .await
+        .unwrap();
+    assert!(response.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain"));
+    response.text().await.unwrap()
+}
+
+/// The value of the sample `name`, labels included, in the text format.
+fn sample(text: &str, name: &str) -> Option<f64> {
+    text.lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+        .map(|value| value.parse().unwrap())
+}
+
+#[tokio::test]
+async fn peers_messages_and_failures_are_counted() {
+    let mut server = Server::bind("127.0.0.1:0").await.unwrap();
+    let metrics = server.listen_metrics("127.0.0.1:0").await.unwrap();
+    let addr = server.addr;
+    tokio::spawn(server.run());
+
+    let text = scrape(metrics).await;
+    assert_eq!(sample(&text, "chat_connected_peers"), Some(0.0));
+
+    let mut alice = connect(addr, "alice").await;
+    let _bob = connect(addr, "bob").await;
+    for body in ["one", "two"] {
+        let message = Message::new(user("alice"), "default".to_string(), body.to_string());
+        alice.send(Frame::Message(message)).await.unwrap();
+        assert!(matches!(next(&mut alice).await, Frame::Message(_)));
+    }
+
+    // A nameless user is refused.
+    let mut nobody = framed(TcpStream::connect(addr).await.unwrap());
+    nobody.send(Frame::Authorize(user(""))).await.unwrap();
+    assert!(matches!(next(&mut nobody).await, Frame::Error { .. }));
+
+    // Garbage instead of a frame ends the connection.
+    let mut garbage = TcpStream::connect(addr).await.unwrap();
+    garbage.write_all(&[0xff; 64]).await.unwrap();
+    let mut garbage = framed(garbage);
+    assert!(matches!(next(&mut garbage).await, Frame::Error { .. }));
+
+    let text = scrape(metrics).await;
+    assert_eq!(sample(&text, "chat_connected_peers"), Some(2.0));
+    assert_eq!(
+        sample(&text, "chat_messages_total{channel=\"default\"}"),
+        Some(2.0)
+    );
+    assert_eq!(
+        sample(&text, "chat_auth_failures_total{kind=\"session\"}"),
+        Some(1.0)
+    );
+    assert_eq!(sample(&text, "chat_frame_decode_failures_total"), Some(1.0));
+    assert_eq!(
+        sample(&text, "chat_storage_latency_seconds_count"),
+        Some(2.0)
+    );
+    assert_eq!(sample(&text, "chat_connections_rejected_total"), Some(0.0));
+}
+
+#[tokio::test]
+async fn connections_over_the_limit_are_counted() {
+    let mut server = Server::bind("127.0.0.1:0").await.unwrap();
+    let metrics = server.listen_metrics("127.0.0.1:0").await.unwrap();
+    server.max_connetions = Arc::new(Semaphore::new(1));
+    let addr = server.addr;
+    tokio::spawn(server.run());
+
+    let _alice = connect(addr, "alice").await;
+    let mut bob = framed(TcpStream::connect(addr).await.unwrap());
+    bob.send(Frame::Authorize(user("bob"))).await.unwrap();
+    assert!(matches!(next(&mut bob).await, Frame::Error { .. }));
+
+    let text = scrape(metrics).await;
+    assert_eq!(sample(&text, "chat_connections_rejected_total"), Some(1.0));
+    assert_eq!(sample(&text, "chat_connected_peers"), Some(1.0));
+}
+
+#[tokio::test]
+async fn deleted_channels_are_no_longer_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("control.sock");
+    let mut server = Server::bind("127.0.0.1:0").await.unwrap();
+    let metrics = server.listen_metrics("127.0.0.1:0").await.unwrap();
+    server.listen_control(&socket).unwrap();
+    let addr = server.addr;
+    tokio::spawn(server.run());
+
+    let mut alice = connect(addr, "alice").await;
+    say(&mut alice, "alice", "another", "soon gone").await;
+    let counted = "chat_messages_total{channel=\"another\"}";
+    assert_eq!(sample(&scrape(metrics).await, counted), Some(1.0));
+
+    let mut control = ControlClient::connect(&socket).await.unwrap();
+    let delete = Request::DeleteChannel {
+        name: "another".to_string(),
+    };
+    assert_eq!(control.request(&delete).await.unwrap(), Response::Done);
+    assert_eq!(sample(&scrape(metrics).await, counted), None);
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use std::{fs, net::SocketAddr};
+
+use common::{authorized, next, user};
+use futures::SinkExt;
+use protocol::{
+    quic,
+    tls::{self, Trust},
+    ChatCodec, Frame, Message,
+};
+use server::Server;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::codec::Framed;
+
+struct Started {
+    tcp: SocketAddr,
+    quic: SocketAddr,
+    trust: Trust,
+    _dir: tempfile::TempDir,
+}
+
+/// Starts a server with a self-signed certificate for `localhost`, accepting
+/// TLS over TCP and QUIC.
+async fn start() -> Started {
+    let dir = tempfile::tempdir().unwrap();
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    fs::write(dir.path().join("server.pem"), certified.cert.pem()).unwrap();
+    fs::write(
+        dir.path().join("server.key"),
+        certified.signing_key.serialize_pem(),
+    )
+    .unwrap();
+
+    let mut server = Server::bind("127.0.0.1:0").await.unwrap();
+    server.tls = Some(
+        tls::server_config(dir.path().join("server.pem"), dir.path().join("server.key")).unwrap(),
+    );
+    let quic = server.listen_quic("127.0.0.1:0").unwrap();
+    let tcp = server.addr;
+    tokio::spawn(server.run());
+
+    Started {
+        tcp,
+        quic,
+        trust: Trust::Pin(tls::fingerprint(certified.cert.der())),
+        _dir: dir,
+    }
+}
+
+/// Waits for the next chat message, skipping presence and heartbeat frames,
+/// with the sequence number the server stamped on it cleared.
+async fn next_message<S: AsyncRead + AsyncWrite + Unpin>(
+    chat: &mut Framed<S, ChatCodec>,
+) -> Message {
+    loop {
+        if let Frame::Message(mut message) = next(chat).await {
+            assert!(message.seq > 0, "message was not sequenced");
+            message.seq = 0;
+            return message;
+        }
+    }
+}
+
+#[tokio::test]
+async fn quic_and_tcp_clients_share_channels() {
+    let server = start().await;
+
+    let config = tls::client_config(&server.trust).unwrap();
+    let mut bob = authorized(
+        tls::connect(server.tcp, "localhost", config).await.unwrap(),
+        "bob",
+    )
+    .await;
+    let mut alice = authorized(
+        quic::connect(server.quic, "localhost", &server.trust)
+            .await
+            .unwrap(),
+        "alice",
+    )
+    .await;
+
+    let hello = Message::new(user("alice"), "default".to_string(), "hello".to_string());
+    alice.send(Frame::Message(hello.clone())).await.unwrap();
+    assert_eq!(next_message(&mut bob).await, hello);
+
+    let reply = Message::new(user("bob"), "default".to_string(), "hi".to_string());
+    bob.send(Frame::Message(reply.clone())).await.unwrap();
+    // Senders get their own message echoed back first.
+    assert_eq!(next_message(&mut alice).await, hello);
+    assert_eq!(next_message(&mut alice).await, reply);
+}
+
+#[tokio::test]
+async fn quic_session_survives_address_change() {
+    let server = start().await;
+
+    let config = tls::client_config(&server.trust).unwrap();
+    let mut bob = authorized(
+        tls::connect(server.tcp, "localhost", config).await.unwrap(),
+        "bob",
+    )
+    .await;
+    let mut alice = authorized(
+        quic::connect(server.quic, "localhost", &server.trust)
+            .await
+            .unwrap(),
+        "alice",
+    )
+    .await;
+    let before = alice.get_ref().local_addr().unwrap();
+
+    // Roam to another local port, as if the device switched networks.
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    alice.get_ref().rebind(socket).unwrap();
+    assert_ne!(alice.get_ref().local_addr().unwrap(), before);
+
+    let message = Message::new(user("alice"), "default".to_string(), "moved".to_string());
+    alice.send(Frame::Message(message.clone())).await.unwrap();
+    assert_eq!(next_message(&mut bob).await, message);
+    assert_eq!(next_message(&mut alice).await, message);
+}
+
+#[tokio::test]
+async fn quic_requires_tls() {
+    let mut server = Server::bind("127.0.0.1:0").await.unwrap();
+    assert!(server.listen_quic("127.0.0.1:0").is_err());
+}
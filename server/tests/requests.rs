@@ -0,0 +1,165 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::{connect_local, next, unsequenced, user};
+use futures::SinkExt;
+use protocol::{Channel, ErrorCode, Frame, Message};
+use server::Server;
+
+fn message(channel: &str, body: &str) -> Message {
+    Message::new(user("alice"), channel.to_string(), body.to_string())
+}
+
+fn channel(name: &str) -> Channel {
+    Channel {
+        name: name.to_string(),
+        messages: vec![],
+        cover: None,
+    }
+}
+
+#[tokio::test]
+async fn messages_are_acknowledged() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let mut alice = connect_local(&server, 1, "alice").await;
+    let mut bob = connect_local(&server, 2, "bob").await;
+
+    let hello = message("default", "hello");
+    alice
+        .send(Frame::request(1, Frame::Message(hello.clone())))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        unsequenced(next(&mut alice).await),
+        Frame::Message(hello.clone())
+    );
+    assert_eq!(next(&mut alice).await, Frame::Ack(1));
+    assert_eq!(unsequenced(next(&mut bob).await), Frame::Message(hello));
+}
+
+#[tokio::test]
+async fn messages_to_unknown_channels_are_rejected() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let mut alice = connect_local(&server, 1, "alice").await;
+    let mut bob = connect_local(&server, 2, "bob").await;
+
+    alice
+        .send(Frame::request(
+            7,
+            Frame::Message(message("nowhere", "hello?")),
+        ))
+        .await
+        .unwrap();
+    assert!(matches!(
+        next(&mut alice).await,
+        Frame::Rejected {
+            id: 7,
+            code: ErrorCode::NotFound,
+            ..
+        }
+    ));
+
+    // The session is still usable and nothing reached bob.
+    let hello = message("default", "hello");
+    alice
+        .send(Frame::request(8, Frame::Message(hello.clone())))
+        .await
+        .unwrap();
+    assert_eq!(
+        unsequenced(next(&mut alice).await),
+        Frame::Message(hello.clone())
+    );
+    assert_eq!(next(&mut alice).await, Frame::Ack(8));
+    assert_eq!(unsequenced(next(&mut bob).await), Frame::Message(hello));
+}
+
+#[tokio::test]
+async fn channel_creation_is_acknowledged_once() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let mut alice = connect_local(&server, 1, "alice").await;
+
+    alice
+        .send(Frame::request(1, Frame::Channel(channel("rust"))))
+        .await
+        .unwrap();
+    let Frame::Bulk(_, channels) = next(&mut alice).await else {
+        panic!("expected the new channel list");
+    };
+    assert!(channels.iter().any(|c| c.name == "rust"));
+    assert_eq!(next(&mut alice).await, Frame::Ack(1));
+
+    alice
+        .send(Frame::request(2, Frame::Channel(channel("rust"))))
+        .await
+        .unwrap();
+    assert!(matches!(
+        next(&mut alice).await,
+        Frame::Rejected {
+            id: 2,
+            code: ErrorCode::AlreadyExists,
+            ..
+        }
+    ));
+}
+
+#[tokio::test]
+async fn only_commands_can_be_requested() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let mut alice = connect_local(&server, 1, "alice").await;
+
+    alice
+        .send(Frame::request(3, Frame::Authorize(user("mallory"))))
+        .await
+        .unwrap();
+    assert!(matches!(
+        next(&mut alice).await,
+        Frame::Rejected {
+            id: 3,
+            code: ErrorCode::InvalidFrame,
+            ..
+        }
+    ));
+}
+
+#[tokio::test]
+async fn reactions_are_relayed_to_the_channel() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let mut alice = connect_local(&server, 1, "alice").await;
+    let mut bob = connect_local(&server, 2, "bob").await;
+
+    alice
+        .send(Frame::Message(message("default", "ship it?")))
+        .await
+        .unwrap();
+    let Frame::Message(shipped) = next(&mut alice).await else {
+        panic!("expected the echo");
+    };
+    assert!(matches!(next(&mut bob).await, Frame::Message(_)));
+
+    let react = |seq| Frame::React {
+        from: user("bob"),
+        channel: "default".to_string(),
+        seq,
+        emoji: "🚀".to_string(),
+    };
+    bob.send(Frame::request(1, react(shipped.seq)))
+        .await
+        .unwrap();
+    assert_eq!(next(&mut bob).await, react(shipped.seq));
+    assert_eq!(next(&mut bob).await, Frame::Ack(1));
+    assert_eq!(next(&mut alice).await, react(shipped.seq));
+
+    bob.send(Frame::request(2, react(shipped.seq + 100)))
+        .await
+        .unwrap();
+    assert!(matches!(
+        next(&mut bob).await,
+        Frame::Rejected {
+            id: 2,
+            code: ErrorCode::NotFound,
+            ..
+        }
+    ));
+}
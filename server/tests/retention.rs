@@ -0,0 +1,181 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use common::{connect, connect_local, next, post, user};
+use futures::SinkExt;
+use protocol::{ErrorCode, Frame, Message};
+use server::{
+    control::{ControlClient, Request, Response},
+    retention::Retention,
+    Server,
+};
+
+#[test]
+fn retention_is_written_as_an_age_and_a_count() {
+    let retention: Retention = "30d,10000".parse().unwrap();
+    assert_eq!(
+        retention,
+        Retention {
+            max_age_secs: Some(30 * 86_400),
+            max_count: Some(10_000),
+        }
+    );
+    assert_eq!(retention.to_string(), "30d,10000");
+    assert_eq!("90m".parse::<Retention>().unwrap().to_string(), "90m");
+    assert_eq!("3600s".parse::<Retention>().unwrap().to_string(), "1h");
+    assert_eq!(
+        "forever".parse::<Retention>().unwrap(),
+        Retention::default()
+    );
+    assert_eq!(Retention::default().to_string(), "forever");
+
+    assert!("30x".parse::<Retention>().is_err());
+    assert!("1d,2d".parse::<Retention>().is_err());
+    assert!("".parse::<Retention>().is_err());
+}
+
+#[tokio::test]
+async fn only_the_newest_messages_are_kept() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let mut alice = connect_local(&server, 1, "alice").await;
+    let mut bob = connect_local(&server, 2, "bob").await;
+    let mut sent = vec![];
+    for body in ["one", "two", "three"] {
+        let message = Message::new(user("alice"), "default".to_string(), body.to_string());
+        sent.push(post(&mut alice, message).await.seq);
+        next(&mut bob).await;
+    }
+    let other = Message::new(user("alice"), "another".to_string(), "one".to_string());
+    post(&mut alice, other).await;
+    next(&mut bob).await;
+
+    let keep_two = Retention {
+        max_age_secs: None,
+        max_count: Some(2),
+    };
+    server.set_retention("default", keep_two).await.unwrap();
+    server.expire(Utc::now()).await;
+
+    let deleted = Frame::Deleted {
+        channel: "default".to_string(),
+        seqs: vec![sent[0]],
+    };
+    assert_eq!(next(&mut alice).await, deleted);
+    assert_eq!(next(&mut bob).await, deleted);
+    let channels = server.channels.lock().await;
+    let bodies: Vec<&str> = channels["default"]
+        .messages
+        .iter()
+        .map(|message| message.body.as_str())
+        .collect();
+    assert_eq!(bodies, ["two", "three"]);
+    assert_eq!(channels["another"].messages.len(), 1);
+    drop(channels);
+
+    // Expired messages cannot be found anymore either.
+    alice
+        .send(Frame::Search {
+            query: "one".to_string(),
+            channel: None,
+            from: None,
+            before: None,
+            after: None,
+            cursor: None,
+            limit: 10,
+        })
+        .await
+        .unwrap();
+    let Frame::SearchResults { messages, .. } = next(&mut alice).await else {
+        panic!("expected search results");
+    };
+    let channels: Vec<&str> = messages.iter().map(|m| m.channel.as_str()).collect();
+    assert_eq!(channels, ["another"]);
+}
+
+#[tokio::test]
+async fn messages_outliving_their_ttl_are_deleted() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let mut alice = connect_local(&server, 1, "alice").await;
+    let mut ephemeral = Message::new(user("alice"), "default".to_string(), "psst".to_string());
+    ephemeral.ttl = Some(Duration::from_secs(60));
+    let ephemeral = post(&mut alice, ephemeral).await;
+    let kept = Message::new(user("alice"), "default".to_string(), "kept".to_string());
+    post(&mut alice, kept).await;
+
+    let expires = ephemeral.expires.expect("the server sets the expiry");
+    server.expire(expires - chrono::Duration::seconds(1)).await;
+    assert_eq!(server.channels.lock().await["default"].messages.len(), 2);
+    server.expire(expires).await;
+    assert_eq!(
+        next(&mut alice).await,
+        Frame::Deleted {
+            channel: "default".to_string(),
+            seqs: vec![ephemeral.seq],
+        }
+    );
+    assert_eq!(server.channels.lock().await["default"].messages.len(), 1);
+
+    let mut forever = Message::new(user("alice"), "default".to_string(), "no".to_string());
+    forever.ttl = Some(Duration::ZERO);
+    alice.send(Frame::Message(forever)).await.unwrap();
+    assert!(matches!(
+        next(&mut alice).await,
+        Frame::Error {
+            code: ErrorCode::InvalidFrame,
+            ..
+        }
+    ));
+}
+
+#[tokio::test]
+async fn old_messages_are_expired_in_the_background() {
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("control.sock");
+    let mut server = Server::bind("127.0.0.1:0").await.unwrap();
+    server.listen_control(&socket).unwrap();
+    server.retention_interval = Duration::from_millis(20);
+    let addr = server.addr;
+    tokio::spawn(server.run());
+
+    let mut alice = connect(addr, "alice").await;
+    let mut old = Message::new(user("alice"), "default".to_string(), "old".to_string());
+    old.created = Utc::now() - chrono::Duration::days(2);
+    let old = post(&mut alice, old).await;
+    let new = Message::new(user("alice"), "default".to_string(), "new".to_string());
+    post(&mut alice, new).await;
+
+    let mut control = ControlClient::connect(&socket).await.unwrap();
+    let request = Request::SetRetention {
+        name: "default".to_string(),
+        retention: "1d".parse().unwrap(),
+    };
+    assert_eq!(control.request(&request).await.unwrap(), Response::Done);
+    assert_eq!(
+        next(&mut alice).await,
+        Frame::Deleted {
+            channel: "default".to_string(),
+            seqs: vec![old.seq],
+        }
+    );
+
+    let Response::Channels(channels) = control.request(&Request::Channels).await.unwrap() else {
+        panic!("expected the channels");
+    };
+    let default = channels.iter().find(|c| c.name == "default").unwrap();
+    assert_eq!(default.messages, 1);
+    assert_eq!(default.retention.to_string(), "1d");
+
+    let unknown = Request::SetRetention {
+        name: "nowhere".to_string(),
+        retention: Retention::default(),
+    };
+    assert!(matches!(
+        control.request(&unknown).await,
+        Err(server::control::ControlError::Rejected {
+            code: ErrorCode::NotFound,
+            ..
+        })
+    ));
+}
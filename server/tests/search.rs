@@ -0,0 +1,209 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use common::{connect_local, next, say, LocalChat};
+use futures::SinkExt;
+use protocol::{ErrorCode, Frame};
+use server::Server;
+
+#[derive(Default)]
+struct Search {
+    channel: Option<&'static str>,
+    from: Option<&'static str>,
+    before: Option<DateTime<Utc>>,
+    after: Option<DateTime<Utc>>,
+    cursor: Option<u64>,
+    limit: u32,
+}
+
+async fn search(chat: &mut LocalChat, query: &str, search: Search) -> Frame {
+    chat.send(Frame::Search {
+        query: query.to_string(),
+        channel: search.channel.map(str::to_string),
+        from: search.from.map(str::to_string),
+        before: search.before,
+        after: search.after,
+        cursor: search.cursor,
+        limit: if search.limit == 0 { 50 } else { search.limit },
+    })
+    .await
+    .unwrap();
+    next(chat).await
+}
+
+fn bodies(frame: &Frame) -> Vec<&str> {
+    let Frame::SearchResults { messages, .. } = frame else {
+        panic!("expected search results, got {frame:?}");
+    };
+    messages
+        .iter()
+        .map(|message| message.body.as_str())
+        .collect()
+}
+
+#[tokio::test]
+async fn every_word_has_to_match_newest_first() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let mut alice = connect_local(&server, 1, "alice").await;
+    say(
+        &mut alice,
+        "alice",
+        "default",
+        "The release is planned for Friday",
+    )
+    .await;
+    say(&mut alice, "alice", "default", "lunch anyone?").await;
+    say(
+        &mut alice,
+        "alice",
+        "another",
+        "Release notes: Déjà vu fixed",
+    )
+    .await;
+
+    let found = search(&mut alice, "release", Search::default()).await;
+    assert_eq!(
+        bodies(&found),
+        [
+            "Release notes: Déjà vu fixed",
+            "The release is planned for Friday"
+        ]
+    );
+    // Words match as prefixes, in any order, ignoring case and accents.
+    let found = search(&mut alice, "fri PLAN", Search::default()).await;
+    assert_eq!(bodies(&found), ["The release is planned for Friday"]);
+    let found = search(&mut alice, "deja", Search::default()).await;
+    assert_eq!(bodies(&found), ["Release notes: Déjà vu fixed"]);
+    // Search syntax is taken literally.
+    let found = search(&mut alice, "\"lunch\" OR (release", Search::default()).await;
+    assert!(bodies(&found).is_empty());
+    let found = search(&mut alice, "  ", Search::default()).await;
+    assert!(bodies(&found).is_empty());
+
+    let found = search(
+        &mut alice,
+        "release",
+        Search {
+            channel: Some("another"),
+            ..Search::default()
+        },
+    )
+    .await;
+    assert_eq!(bodies(&found), ["Release notes: Déjà vu fixed"]);
+}
+
+#[tokio::test]
+async fn senders_and_dates_narrow_the_results() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let mut alice = connect_local(&server, 1, "alice").await;
+    let mut bob = connect_local(&server, 2, "bob").await;
+    let first = say(&mut alice, "alice", "default", "deploy one").await;
+    next(&mut bob).await;
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    let second = say(&mut bob, "bob", "default", "deploy two").await;
+    next(&mut alice).await;
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    say(&mut alice, "alice", "default", "deploy three").await;
+
+    let found = search(
+        &mut alice,
+        "deploy",
+        Search {
+            from: Some("alice"),
+            ..Search::default()
+        },
+    )
+    .await;
+    assert_eq!(bodies(&found), ["deploy three", "deploy one"]);
+
+    let found = search(
+        &mut alice,
+        "deploy",
+        Search {
+            after: Some(first.created),
+            before: Some(second.created + chrono::Duration::milliseconds(1)),
+            ..Search::default()
+        },
+    )
+    .await;
+    assert_eq!(bodies(&found), ["deploy two"]);
+}
+
+#[tokio::test]
+async fn results_are_paged_with_the_cursor() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let mut alice = connect_local(&server, 1, "alice").await;
+    for i in 0..5 {
+        say(&mut alice, "alice", "default", &format!("standup {i}")).await;
+    }
+
+    let mut found = vec![];
+    let mut cursor = None;
+    loop {
+        let page = search(
+            &mut alice,
+            "standup",
+            Search {
+                cursor,
+                limit: 2,
+                ..Search::default()
+            },
+        )
+        .await;
+        found.extend(bodies(&page).iter().map(|body| body.to_string()));
+        let Frame::SearchResults { next, .. } = page else {
+            unreachable!();
+        };
+        match next {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
+    }
+    assert_eq!(
+        found,
+        [
+            "standup 4",
+            "standup 3",
+            "standup 2",
+            "standup 1",
+            "standup 0"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn only_channels_the_peer_is_in_are_searched() {
+    let server = Arc::new(Server::bind("127.0.0.1:0").await.unwrap());
+    let mut alice = connect_local(&server, 1, "alice").await;
+    let mut bob = connect_local(&server, 2, "bob").await;
+    bob.send(Frame::request(1, Frame::Leave("another".to_string())))
+        .await
+        .unwrap();
+    assert_eq!(next(&mut bob).await, Frame::Ack(1));
+    assert!(matches!(next(&mut alice).await, Frame::PeerLeft { .. }));
+
+    say(&mut alice, "alice", "another", "the secret plan").await;
+    say(&mut alice, "alice", "default", "the public plan").await;
+    next(&mut bob).await;
+
+    let found = search(&mut bob, "plan", Search::default()).await;
+    assert_eq!(bodies(&found), ["the public plan"]);
+    let refused = search(
+        &mut bob,
+        "plan",
+        Search {
+            channel: Some("another"),
+            ..Search::default()
+        },
+    )
+    .await;
+    assert!(matches!(
+        refused,
+        Frame::Error {
+            code: ErrorCode::Forbidden,
+            ..
+        }
+    ));
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use common::{connect, next, user};
+use protocol::{ConnectionError, Frame, Message};
+use server::{server::Shared, Server};
+use tokio::{sync::Mutex, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
+
+struct Running {
+    addr: std::net::SocketAddr,
+    channels: Arc<Mutex<HashMap<String, Shared>>>,
+    shutdown: CancellationToken,
+    task: JoinHandle<Result<(), ConnectionError>>,
+}
+
+fn start(server: Server) -> Running {
+    Running {
+        addr: server.addr,
+        channels: Arc::clone(&server.channels),
+        shutdown: server.shutdown.clone(),
+        task: tokio::spawn(server.run()),
+    }
+}
+
+impl Running {
+    /// Queues `frame` `times` times for every peer in the default channel
+    /// and requests the shutdown. Nothing else runs in between, so the frames
+    /// are still queued when the sessions notice the shutdown.
+    async fn queue_and_shut_down(&self, frame: Frame, times: usize) {
+        let channels = self.channels.lock().await;
+        for tx in channels["default"].peers.values() {
+            for _ in 0..times {
+                tx.send(frame.clone()).unwrap();
+            }
+        }
+        self.shutdown.cancel();
+    }
+
+    /// Waits for `Server::run` to return, for at most `deadline`.
+    async fn stopped(self, deadline: Duration) {
+        tokio::time::timeout(deadline, self.task)
+            .await
+            .expect("the server did not stop in time")
+            .unwrap()
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn queued_frames_are_flushed_before_the_shutdown_notice() {
+    let running = start(Server::bind("127.0.0.1:0").await.unwrap());
+    let mut alice = connect(running.addr, "alice").await;
+
+    let last = Message::new(user("bob"), "default".to_string(), "last words".to_string());
+    running
+        .queue_and_shut_down(Frame::Message(last.clone()), 1)
+        .await;
+    assert_eq!(next(&mut alice).await, Frame::Message(last));
+    assert!(matches!(
+        next(&mut alice).await,
+        Frame::ServerShutdown {
+            reconnect_after: Some(_),
+            ..
+        }
+    ));
+    running.stopped(Duration::from_secs(5)).await;
+}
+
+#[tokio::test]
+async fn peers_that_stop_reading_cannot_hold_up_the_shutdown() {
+    let mut server = Server::bind("127.0.0.1:0").await.unwrap();
+    server.shutdown_timeout = Duration::from_millis(200);
+    let running = start(server);
+    // Bob never reads again, so his socket fills up long before the server
+    // is done writing.
+    let _bob = connect(running.addr, "bob").await;
+
+    let body = "x".repeat(16 * 1024);
+    let flood = Message::new(user("alice"), "default".to_string(), body);
+    running
+        .queue_and_shut_down(Frame::Message(flood), 2_000)
+        .await;
+    running.stopped(Duration::from_secs(2)).await;
+}
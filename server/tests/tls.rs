@@ -0,0 +1,113 @@
+mod common;
+
+use std::{fs, path::Path};
+
+use common::authorize;
+use protocol::{
+    tls::{self, Trust},
+    Frame,
+};
+use rcgen::{BasicConstraints, CertificateParams, CertifiedIssuer, IsCa, KeyPair};
+use server::Server;
+
+struct Pki {
+    dir: tempfile::TempDir,
+    /// Fingerprint of the server certificate.
+    fingerprint: String,
+}
+
+/// Writes a local CA (`ca.pem`) and a server certificate for `localhost`
+/// signed by it (`server.pem`, `server.key`).
+fn generate_pki() -> Pki {
+    let dir = tempfile::tempdir().unwrap();
+
+    let mut ca_params = CertificateParams::new(vec![]).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = CertifiedIssuer::self_signed(ca_params, KeyPair::generate().unwrap()).unwrap();
+
+    let server_key = KeyPair::generate().unwrap();
+    let server_cert = CertificateParams::new(vec!["localhost".to_string()])
+        .unwrap()
+        .signed_by(&server_key, &ca)
+        .unwrap();
+
+    fs::write(dir.path().join("ca.pem"), ca.pem()).unwrap();
+    fs::write(dir.path().join("server.pem"), server_cert.pem()).unwrap();
+    fs::write(dir.path().join("server.key"), server_key.serialize_pem()).unwrap();
+
+    Pki {
+        dir,
+        fingerprint: tls::fingerprint(server_cert.der()),
+    }
+}
+
+async fn start(pki: &Pki) -> std::net::SocketAddr {
+    let path = pki.dir.path();
+    let mut server = Server::bind("127.0.0.1:0").await.unwrap();
+    server.tls =
+        Some(tls::server_config(path.join("server.pem"), path.join("server.key")).unwrap());
+    let addr = server.addr;
+    tokio::spawn(server.run());
+    addr
+}
+
+fn ca(path: &Path) -> Trust {
+    Trust::Ca(path.join("ca.pem"))
+}
+
+#[tokio::test]
+async fn client_trusting_the_ca_can_chat() {
+    let pki = generate_pki();
+    let addr = start(&pki).await;
+
+    let config = tls::client_config(&ca(pki.dir.path())).unwrap();
+    let stream = tls::connect(addr, "localhost", config).await.unwrap();
+
+    assert!(matches!(
+        authorize(stream, "alice").await.1,
+        Frame::Bulk(..)
+    ));
+}
+
+#[tokio::test]
+async fn client_pinning_the_certificate_can_chat() {
+    let pki = generate_pki();
+    let addr = start(&pki).await;
+
+    // Pinning ignores the chain, so any server name is accepted.
+    let config = tls::client_config(&Trust::Pin(pki.fingerprint.to_uppercase())).unwrap();
+    let stream = tls::connect(addr, "chat.internal", config).await.unwrap();
+
+    assert!(matches!(
+        authorize(stream, "alice").await.1,
+        Frame::Bulk(..)
+    ));
+}
+
+#[tokio::test]
+async fn wrong_pin_is_rejected() {
+    let pki = generate_pki();
+    let addr = start(&pki).await;
+
+    let config = tls::client_config(&Trust::Pin("00".repeat(32))).unwrap();
+    assert!(tls::connect(addr, "localhost", config).await.is_err());
+}
+
+#[tokio::test]
+async fn unknown_ca_is_rejected() {
+    let pki = generate_pki();
+    let other = generate_pki();
+    let addr = start(&pki).await;
+
+    let config = tls::client_config(&ca(other.dir.path())).unwrap();
+    assert!(tls::connect(addr, "localhost", config).await.is_err());
+}
+
+#[tokio::test]
+async fn wrong_server_name_is_rejected() {
+    let pki = generate_pki();
+    let addr = start(&pki).await;
+
+    let config = tls::client_config(&ca(pki.dir.path())).unwrap();
+    assert!(tls::connect(addr, "example.com", config).await.is_err());
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use std::{os::unix::fs::PermissionsExt, path::Path};
+
+use common::{authorize, next, unsequenced, user};
+use futures::SinkExt;
+use protocol::{ErrorCode, Frame, Message};
+use server::Server;
+use tokio::net::{TcpStream, UnixStream};
+
+fn current_uid() -> u32 {
+    let (stream, _) = std::os::unix::net::UnixStream::pair().unwrap();
+    stream.set_nonblocking(true).unwrap();
+    let stream = UnixStream::from_std(stream).unwrap();
+    stream.peer_cred().unwrap().uid()
+}
+
+#[tokio::test]
+async fn unix_and_tcp_clients_chat_together() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("chat.sock");
+
+    let mut server = Server::bind("127.0.0.1:0").await.unwrap();
+    server.listen_unix(&path, 0o600).unwrap();
+    let addr = server.addr;
+    tokio::spawn(server.run());
+
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    let (mut tcp, frame) = authorize(TcpStream::connect(addr).await.unwrap(), "tcp").await;
+    assert!(matches!(frame, Frame::Bulk(..)));
+    let (mut local, frame) = authorize(UnixStream::connect(&path).await.unwrap(), "bot").await;
+    assert!(matches!(frame, Frame::Bulk(..)));
+
+    let message = Message::new(user("bot"), "default".to_string(), "beep".to_string());
+    local.send(Frame::Message(message.clone())).await.unwrap();
+
+    assert_eq!(
+        unsequenced(next(&mut local).await),
+        Frame::Message(message.clone())
+    );
+    assert_eq!(unsequenced(next(&mut tcp).await), Frame::Message(message));
+}
+
+#[tokio::test]
+async fn peer_credentials_are_checked() {
+    let dir = tempfile::tempdir().unwrap();
+    let allowed = dir.path().join("allowed.sock");
+    let denied = dir.path().join("denied.sock");
+
+    let uid = current_uid();
+    for (path, uids) in [(&allowed, vec![uid]), (&denied, vec![uid + 1])] {
+        let mut server = Server::bind("127.0.0.1:0").await.unwrap();
+        server.listen_unix(path, 0o600).unwrap();
+        server.unix.as_mut().unwrap().allowed_uids = Some(uids);
+        tokio::spawn(server.run());
+    }
+
+    let (_, frame) = authorize(UnixStream::connect(&allowed).await.unwrap(), "me").await;
+    assert!(matches!(frame, Frame::Bulk(..)));
+
+    let (_, frame) = authorize(UnixStream::connect(&denied).await.unwrap(), "me").await;
+    assert!(matches!(
+        frame,
+        Frame::Error {
+            code: ErrorCode::Unauthorized,
+            ..
+        }
+    ));
+}
+
+#[tokio::test]
+async fn socket_file_is_removed_on_shutdown() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("chat.sock");
+
+    let mut server = Server::bind("127.0.0.1:0").await.unwrap();
+    server.listen_unix(&path, 0o600).unwrap();
+    let shutdown = server.shutdown.clone();
+    let running = tokio::spawn(server.run());
+    assert!(Path::new(&path).exists());
+
+    shutdown.cancel();
+    running.await.unwrap().unwrap();
+    assert!(!Path::new(&path).exists());
+}
+
+#[tokio::test]
+async fn sockets_are_linked_into_place_without_replacing_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("chat.sock");
+    std::fs::write(&path, "not a socket").unwrap();
+
+    let mut server = Server::bind("127.0.0.1:0").await.unwrap();
+    assert!(server.listen_unix(&path, 0o600).is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a socket");
+
+    std::fs::remove_file(&path).unwrap();
+    server.listen_unix(&path, 0o600).unwrap();
+    // Nothing is left of the directory the socket was bound in.
+    let entries: Vec<_> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(entries, ["chat.sock"]);
+}
+
+#[tokio::test]
+async fn the_control_socket_only_admits_the_server_user() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut server = Server::bind("127.0.0.1:0").await.unwrap();
+    server
+        .listen_control(dir.path().join("control.sock"))
+        .unwrap();
+    assert_eq!(
+        server.control.as_ref().unwrap().allowed_uids,
+        Some(vec![current_uid()])
+    );
+}
@@ -0,0 +1,297 @@
+mod common;
+
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use axum::{extract::State, http::HeaderMap, http::StatusCode, routing::post, Router};
+use common::{connect, connect_local, next, user, LocalChat, ADMIN_TOKEN};
+use futures::SinkExt;
+use protocol::{Channel, Frame, Message};
+use serde_json::Value;
+use server::{
+    webhooks::{self, Webhook},
+    Server,
+};
+use tokio::sync::mpsc;
+
+/// A request the stand-in receiver got.
+struct Received {
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+impl Received {
+    fn header(&self, name: &str) -> &str {
+        self.headers[name].to_str().unwrap()
+    }
+
+    fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).unwrap()
+    }
+}
+
+struct Receiver {
+    tx: mpsc::UnboundedSender<Received>,
+    /// Requests answered with `failure` before the receiver starts accepting.
+    failures: usize,
+    failure: StatusCode,
+    seen: AtomicUsize,
+}
+
+/// Starts an HTTP server standing in for a webhook receiver.
+async fn receiver(
+    failures: usize,
+    failure: StatusCode,
+) -> (String, mpsc::UnboundedReceiver<Received>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let state = Arc::new(Receiver {
+        tx,
+        failures,
+        failure,
+        seen: AtomicUsize::new(0),
+    });
+    let app = Router::new()
+        .route(
+            "/hook",
+            post(
+                |State(receiver): State<Arc<Receiver>>,
+                 headers: HeaderMap,
+                 body: axum::body::Bytes| async move {
+                    let _ = receiver.tx.send(Received {
+                        headers,
+                        body: body.to_vec(),
+                    });
+                    if receiver.seen.fetch_add(1, Ordering::SeqCst) < receiver.failures {
+                        receiver.failure
+                    } else {
+                        StatusCode::NO_CONTENT
+                    }
+                },
+            ),
+        )
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr: SocketAddr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    (format!("http://{addr}/hook"), rx)
+}
+
+async fn server(hooks: Vec<Webhook>) -> Arc<Server> {
+    let mut server = Server::bind("127.0.0.1:0").await.unwrap();
+    server.webhooks.hooks = hooks;
+    server.webhooks.secret = Some("hunter2".to_string());
+    server.webhooks.max_attempts = 3;
+    server.webhooks.min_backoff = Duration::from_millis(20);
+    Arc::new(server)
+}
+
+async fn received(rx: &mut mpsc::UnboundedReceiver<Received>) -> Received {
+    tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .expect("webhook was not called")
+        .unwrap()
+}
+
+async fn say(chat: &mut LocalChat, id: u64, channel: &str, body: &str) {
+    let message = Message::new(user("alice"), channel.to_string(), body.to_string());
+    chat.send(Frame::request(id, Frame::Message(message)))
+        .await
+        .unwrap();
+    assert!(matches!(next(chat).await, Frame::Message(_)));
+    assert_eq!(next(chat).await, Frame::Ack(id));
+}
+
+#[test]
+fn webhooks_parse_with_an_optional_channel() {
+    assert_eq!(
+        "https://example.com/hook?a=b".parse(),
+        Ok(Webhook {
+            url: "https://example.com/hook?a=b".to_string(),
+            channel: None,
+        })
+    );
+    assert_eq!(
+        "ops=http://127.0.0.1/hook".parse(),
+        Ok(Webhook {
+            url: "http://127.0.0.1/hook".to_string(),
+            channel: Some("ops".to_string()),
+        })
+    );
+    assert!("ops=ftp://example.com".parse::<Webhook>().is_err());
+}
+
+#[tokio::test]
+async fn messages_are_posted_signed() {
+    let (url, mut rx) = receiver(0, StatusCode::OK).await;
+    let server = server(vec![Webhook { url, channel: None }]).await;
+    let mut alice = connect_local(&server, 1, "alice").await;
+
+    say(&mut alice, 1, "default", "hello hooks").await;
+
+    let request = received(&mut rx).await;
+    assert_eq!(request.header(webhooks::EVENT_HEADER), "message");
+    assert_eq!(request.header("content-type"), "application/json");
+    assert_eq!(
+        request.header(webhooks::SIGNATURE_HEADER),
+        webhooks::sign("hunter2", &request.body)
+    );
+    let json = request.json();
+    assert_eq!(json["event"], "message");
+    assert_eq!(json["channel"], "default");
+    assert_eq!(json["message"]["body"], "hello hooks");
+    assert_eq!(json["message"]["from"]["username"], "alice");
+    assert!(json["message"]["seq"].as_u64().unwrap() > 0);
+}
+
+#[tokio::test]
+async fn joins_and_new_channels_are_posted_for_their_channel_only() {
+    let (url, mut rx) = receiver(0, StatusCode::OK).await;
+    let server = server(vec![Webhook {
+        url,
+        channel: Some("ops".to_string()),
+    }])
+    .await;
+    let mut alice = connect_local(&server, 1, "alice").await;
+    let mut bob = connect_local(&server, 2, "bob").await;
+
+    say(&mut alice, 1, "default", "not for ops").await;
+    let ops = Channel {
+        name: "ops".to_string(),
+        messages: vec![],
+        cover: None,
+    };
+    alice
+        .send(Frame::request(2, Frame::Channel(ops)))
+        .await
+        .unwrap();
+    assert!(matches!(next(&mut alice).await, Frame::Bulk(..)));
+    assert_eq!(next(&mut alice).await, Frame::Ack(2));
+    // Read first, bob's session may answer the join before passing these on.
+    assert!(matches!(next(&mut bob).await, Frame::Message(_)));
+    assert!(matches!(next(&mut bob).await, Frame::Bulk(..)));
+    bob.send(Frame::request(1, Frame::Join("ops".to_string())))
+        .await
+        .unwrap();
+    assert!(matches!(next(&mut bob).await, Frame::Joined(_)));
+
+    let created = received(&mut rx).await.json();
+    assert_eq!(created["event"], "channel_created");
+    assert_eq!(created["channel"], "ops");
+    assert_eq!(created["by"]["username"], "alice");
+    let joined = received(&mut rx).await.json();
+    assert_eq!(joined["event"], "join");
+    assert_eq!(joined["user"]["username"], "bob");
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_retried_and_logged() {
+    let (url, mut rx) = receiver(2, StatusCode::SERVICE_UNAVAILABLE).await;
+    let server = server(vec![Webhook {
+        url: url.clone(),
+        channel: None,
+    }])
+    .await;
+    let mut alice = connect_local(&server, 1, "alice").await;
+
+    say(&mut alice, 1, "default", "eventually").await;
+
+    let attempts: Vec<Received> = vec![
+        received(&mut rx).await,
+        received(&mut rx).await,
+        received(&mut rx).await,
+    ];
+    // Every attempt is the same delivery, byte for byte.
+    for attempt in &attempts[1..] {
+        assert_eq!(attempt.body, attempts[0].body);
+        assert_eq!(
+            attempt.header(webhooks::DELIVERY_HEADER),
+            attempts[0].header(webhooks::DELIVERY_HEADER)
+        );
+    }
+
+    // The log is written right after the receiver answered.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let log = server.webhooks.deliveries();
+    let statuses: Vec<(u32, Option<u16>)> = log
+        .iter()
+        .map(|delivery| (delivery.attempt, delivery.status))
+        .collect();
+    assert_eq!(statuses, [(1, Some(503)), (2, Some(503)), (3, Some(204))]);
+    assert!(log.iter().all(|delivery| delivery.url == url));
+    assert!(log[2].succeeded());
+}
+
+#[tokio::test]
+async fn client_errors_are_not_retried() {
+    let (url, mut rx) = receiver(usize::MAX, StatusCode::GONE).await;
+    let server = server(vec![Webhook { url, channel: None }]).await;
+    let mut alice = connect_local(&server, 1, "alice").await;
+
+    say(&mut alice, 1, "default", "nobody home").await;
+
+    received(&mut rx).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(rx.try_recv().is_err());
+    let log = server.webhooks.deliveries();
+    assert_eq!(log.len(), 1);
+    assert_eq!(log[0].status, Some(410));
+    assert!(!log[0].succeeded());
+}
+
+#[tokio::test]
+async fn the_delivery_log_is_served_to_admins() {
+    let (url, mut rx) = receiver(usize::MAX, StatusCode::GONE).await;
+    let mut server = Server::bind("127.0.0.1:0").await.unwrap();
+    let http = server.listen_http("127.0.0.1:0").await.unwrap();
+    server.admin_tokens = vec![ADMIN_TOKEN.to_string()];
+    server.webhooks.hooks = vec![Webhook {
+        url: url.clone(),
+        channel: None,
+    }];
+    let addr = server.addr;
+    tokio::spawn(server.run());
+
+    let mut alice = connect(addr, "alice").await;
+    common::say(&mut alice, "alice", "default", "nobody home").await;
+    received(&mut rx).await;
+
+    // The attempt is logged once the receiver's answer is in.
+    let message = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let deliveries: Vec<Value> = reqwest::Client::new()
+                .get(format!("http://{http}/api/webhooks/deliveries"))
+                .bearer_auth(ADMIN_TOKEN)
+                .send()
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+            if let Some(message) = deliveries
+                .into_iter()
+                .find(|delivery| delivery["event"] == "message")
+            {
+                return message;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("the message delivery was not logged");
+    assert_eq!(message["url"], url.as_str());
+    assert_eq!(message["attempt"], 1);
+    assert_eq!(message["status"], 410);
+
+    let anonymous = reqwest::get(format!("http://{http}/api/webhooks/deliveries"))
+        .await
+        .unwrap();
+    assert_eq!(anonymous.status(), reqwest::StatusCode::UNAUTHORIZED);
+}
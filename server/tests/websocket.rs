@@ -0,0 +1,106 @@
+mod common;
+
+use std::{net::SocketAddr, time::Duration};
+
+use common::{connect, next, unsequenced, user};
+use futures::{SinkExt, StreamExt};
+use protocol::{Frame, Message};
+use server::Server;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{tungstenite, MaybeTlsStream, WebSocketStream};
+
+type Ws = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn start() -> (SocketAddr, SocketAddr) {
+    let mut server = Server::bind("127.0.0.1:0").await.unwrap();
+    let ws_addr = server.listen_ws("127.0.0.1:0").await.unwrap();
+    let addr = server.addr;
+    tokio::spawn(server.run());
+    (addr, ws_addr)
+}
+
+async fn next_ws(ws: &mut Ws) -> tungstenite::Message {
+    tokio::time::timeout(Duration::from_secs(5), ws.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap()
+}
+
+async fn connect_binary(addr: SocketAddr, name: &str) -> Ws {
+    let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{addr}"))
+        .await
+        .unwrap();
+    let bytes = bincode::serialize(&Frame::Authorize(user(name))).unwrap();
+    ws.send(tungstenite::Message::Binary(bytes.into()))
+        .await
+        .unwrap();
+
+    let tungstenite::Message::Binary(bytes) = next_ws(&mut ws).await else {
+        panic!("expected a binary message");
+    };
+    let frame: Frame = bincode::deserialize(&bytes).unwrap();
+    assert!(matches!(frame, Frame::Bulk(..)));
+    ws
+}
+
+async fn connect_json(addr: SocketAddr, name: &str) -> Ws {
+    let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{addr}"))
+        .await
+        .unwrap();
+    let json = serde_json::to_string(&Frame::Authorize(user(name))).unwrap();
+    ws.send(tungstenite::Message::Text(json.into()))
+        .await
+        .unwrap();
+
+    assert!(matches!(next_json(&mut ws).await, Frame::Bulk(..)));
+    ws
+}
+
+async fn next_json(ws: &mut Ws) -> Frame {
+    let tungstenite::Message::Text(text) = next_ws(ws).await else {
+        panic!("expected a text message");
+    };
+    serde_json::from_str(text.as_str()).unwrap()
+}
+
+#[tokio::test]
+async fn tcp_and_websocket_clients_chat_together() {
+    let (addr, ws_addr) = start().await;
+
+    let mut tcp = connect(addr, "tcp").await;
+    let mut binary = connect_binary(ws_addr, "binary").await;
+    let mut json = connect_json(ws_addr, "json").await;
+
+    // TCP -> both WebSocket flavours.
+    let message = Message::new(user("tcp"), "default".to_string(), "hi".to_string());
+    tcp.send(Frame::Message(message.clone())).await.unwrap();
+    assert_eq!(
+        unsequenced(next(&mut tcp).await),
+        Frame::Message(message.clone())
+    );
+
+    let tungstenite::Message::Binary(bytes) = next_ws(&mut binary).await else {
+        panic!("expected a binary message");
+    };
+    assert_eq!(
+        unsequenced(Frame::try_from(bytes).unwrap()),
+        Frame::Message(message.clone())
+    );
+    assert_eq!(
+        unsequenced(next_json(&mut json).await),
+        Frame::Message(message)
+    );
+
+    // JSON WebSocket -> TCP.
+    let message = Message::new(user("json"), "default".to_string(), "hello".to_string());
+    let text = serde_json::to_string(&Frame::Message(message.clone())).unwrap();
+    json.send(tungstenite::Message::Text(text.into()))
+        .await
+        .unwrap();
+    assert_eq!(
+        unsequenced(next_json(&mut json).await),
+        Frame::Message(message.clone())
+    );
+    assert_eq!(unsequenced(next(&mut tcp).await), Frame::Message(message));
+}
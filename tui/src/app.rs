@@ -0,0 +1,448 @@
+use chat_client::Event;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use protocol::{Channel, ErrorCode, Message, User};
+
+use crate::command::{Command, HELP, HISTORY_PAGE};
+
+/// Lines scrolled by PageUp and PageDown.
+const SCROLL_PAGE: usize = 10;
+
+/// What the user asked for that needs the server, see `App::key`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Send {
+        channel: String,
+        body: String,
+    },
+    Join(String),
+    Leave(String),
+    Create {
+        name: String,
+        cover: Option<String>,
+    },
+    History {
+        channel: String,
+        before: Option<u64>,
+        limit: u32,
+    },
+    Quit,
+}
+
+/// How the server answered an `Action`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// Sent messages show up through `Event::Message` like everyone else's.
+    Sent,
+    Joined(Channel),
+    Left(String),
+    Created(String),
+    History {
+        channel: String,
+        messages: Vec<Message>,
+    },
+    Failed(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelView {
+    pub name: String,
+    pub messages: Vec<Message>,
+    /// Messages that arrived while another channel was shown.
+    pub unread: usize,
+    /// Index of the first message that was unread when switching here, the
+    /// "new" marker is drawn above it.
+    pub marker: Option<usize>,
+    pub joined: bool,
+}
+
+impl ChannelView {
+    fn new(channel: Channel) -> Self {
+        ChannelView {
+            name: channel.name,
+            messages: channel.messages,
+            unread: 0,
+            marker: None,
+            joined: true,
+        }
+    }
+}
+
+/// Everything on screen, updated from key presses and server events.
+#[derive(Debug)]
+pub struct App {
+    pub user: User,
+    pub channels: Vec<ChannelView>,
+    pub current: usize,
+    pub input: String,
+    /// Cursor position in `input`, in characters.
+    pub cursor: usize,
+    /// Messages of the current channel scrolled past at the bottom.
+    pub scroll: usize,
+    pub status: String,
+    /// Lines entered before, oldest first, recalled with Up and Down.
+    sent: Vec<String>,
+    /// Position in `sent` while recalling, and the line being typed before.
+    recalling: Option<(usize, String)>,
+    /// Whether the last event was an error turning the user away, which
+    /// makes a following `Event::Disconnected` final.
+    turned_away: bool,
+}
+
+impl App {
+    pub fn new(user: User, channels: Vec<Channel>) -> Self {
+        let mut app = App {
+            user,
+            channels: vec![],
+            current: 0,
+            input: String::new(),
+            cursor: 0,
+            scroll: 0,
+            status: "type /help for commands".to_string(),
+            sent: vec![],
+            recalling: None,
+            turned_away: false,
+        };
+        app.merge(channels);
+        app.current = app.position("default").unwrap_or(0);
+        app
+    }
+
+    pub fn channel(&self) -> Option<&ChannelView> {
+        self.channels.get(self.current)
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.channels
+            .iter()
+            .position(|channel| channel.name == name)
+    }
+
+    /// Shows the channel at `index`, marking its messages read.
+    pub fn switch(&mut self, index: usize) {
+        if let Some(channel) = self.channels.get_mut(self.current) {
+            channel.marker = None;
+        }
+        let Some(channel) = self.channels.get_mut(index) else {
+            return;
+        };
+        channel.marker = (channel.unread > 0).then(|| channel.messages.len() - channel.unread);
+        channel.unread = 0;
+        self.current = index;
+        self.scroll = 0;
+    }
+
+    /// Adds channels not known yet, keeping the list sorted.
+    fn merge(&mut self, channels: Vec<Channel>) {
+        let current = self.channel().map(|channel| channel.name.clone());
+        for channel in channels {
+            if self.position(&channel.name).is_none() {
+                self.channels.push(ChannelView::new(channel));
+            }
+        }
+        self.channels.sort_by(|a, b| a.name.cmp(&b.name));
+        if let Some(index) = current.and_then(|name| self.position(&name)) {
+            self.current = index;
+        }
+    }
+
+    pub fn key(&mut self, key: KeyEvent) -> Option<Action> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('c') if ctrl => return Some(Action::Quit),
+            KeyCode::Char('n') if ctrl => self.cycle(1),
+            KeyCode::Char('p') if ctrl => self.cycle(self.channels.len().saturating_sub(1)),
+            KeyCode::Tab => self.cycle(1),
+            KeyCode::BackTab => self.cycle(self.channels.len().saturating_sub(1)),
+            KeyCode::Char('u') if ctrl => {
+                self.input.clear();
+                self.cursor = 0;
+            }
+            KeyCode::Char(c) => {
+                let at = self.byte_offset(self.cursor);
+                self.input.insert(at, c);
+                self.cursor += 1;
+            }
+            KeyCode::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                let at = self.byte_offset(self.cursor);
+                self.input.remove(at);
+            }
+            KeyCode::Delete if self.cursor < self.input.chars().count() => {
+                let at = self.byte_offset(self.cursor);
+                self.input.remove(at);
+            }
+            KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Right => self.cursor = (self.cursor + 1).min(self.input.chars().count()),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.input.chars().count(),
+            KeyCode::Up => self.recall_older(),
+            KeyCode::Down => self.recall_newer(),
+            KeyCode::PageUp => return self.scroll_up(),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_sub(SCROLL_PAGE),
+            KeyCode::Enter => return self.submit(),
+            _ => {}
+        }
+        None
+    }
+
+    fn byte_offset(&self, chars: usize) -> usize {
+        self.input
+            .char_indices()
+            .nth(chars)
+            .map_or(self.input.len(), |(at, _)| at)
+    }
+
+    fn cycle(&mut self, by: usize) {
+        if !self.channels.is_empty() {
+            self.switch((self.current + by) % self.channels.len());
+        }
+    }
+
+    fn recall_older(&mut self) {
+        let index = match &self.recalling {
+            Some((0, _)) => return,
+            Some((index, _)) => index - 1,
+            None if self.sent.is_empty() => return,
+            None => {
+                self.recalling = Some((self.sent.len(), self.input.clone()));
+                self.sent.len() - 1
+            }
+        };
+        if let Some((recalled, _)) = &mut self.recalling {
+            *recalled = index;
+        }
+        self.set_input(self.sent[index].clone());
+    }
+
+    fn recall_newer(&mut self) {
+        let Some((index, draft)) = self.recalling.take() else {
+            return;
+        };
+        if index + 1 < self.sent.len() {
+            self.recalling = Some((index + 1, draft));
+            self.set_input(self.sent[index + 1].clone());
+        } else {
+            self.set_input(draft);
+        }
+    }
+
+    fn set_input(&mut self, input: String) {
+        self.cursor = input.chars().count();
+        self.input = input;
+    }
+
+    /// Scrolls towards older messages, asking for history once the oldest
+    /// loaded one is reached.
+    fn scroll_up(&mut self) -> Option<Action> {
+        let channel = self.channel()?;
+        let oldest = channel.messages.len().saturating_sub(1);
+        if self.scroll < oldest {
+            self.scroll = (self.scroll + SCROLL_PAGE).min(oldest);
+            return None;
+        }
+        self.history(HISTORY_PAGE)
+    }
+
+    fn history(&self, limit: u32) -> Option<Action> {
+        let channel = self.channel()?;
+        Some(Action::History {
+            channel: channel.name.clone(),
+            before: channel.messages.first().map(|message| message.seq),
+            limit,
+        })
+    }
+
+    fn submit(&mut self) -> Option<Action> {
+        let line = self.input.trim().to_string();
+        self.input.clear();
+        self.cursor = 0;
+        self.recalling = None;
+        if line.is_empty() {
+            return None;
+        }
+        self.sent.push(line.clone());
+
+        if line.starts_with('/') {
+            return match line.parse() {
+                Ok(command) => self.command(command),
+                Err(error) => {
+                    self.status = error;
+                    None
+                }
+            };
+        }
+
+        let Some(channel) = self.channel().map(|channel| channel.name.clone()) else {
+            self.status = "no channel to send to".to_string();
+            return None;
+        };
+        self.scroll = 0;
+        Some(Action::Send {
+            channel,
+            body: line,
+        })
+    }
+
+    fn command(&mut self, command: Command) -> Option<Action> {
+        match command {
+            Command::Join(name) => Some(Action::Join(name)),
+            Command::Leave(name) => name
+                .or_else(|| self.channel().map(|channel| channel.name.clone()))
+                .map(Action::Leave),
+            Command::Create { name, cover } => Some(Action::Create { name, cover }),
+            Command::Switch(name) => {
+                match self.position(&name) {
+                    Some(index) => self.switch(index),
+                    None => self.status = format!("no channel named {name}"),
+                }
+                None
+            }
+            Command::History(limit) => self.history(limit),
+            Command::Help => {
+                self.status = HELP.to_string();
+                None
+            }
+            Command::Quit => Some(Action::Quit),
+        }
+    }
+
+    pub fn event(&mut self, event: Event) {
+        let turned_away = std::mem::take(&mut self.turned_away);
+        match event {
+            Event::Message(message) => self.message(message),
+            Event::Channels(channels) => self.merge(channels),
+            Event::Left(user) => self.status = format!("{} left", user.username),
+            Event::Reaction {
+                from,
+                channel,
+                emoji,
+                ..
+            } => self.status = format!("{} reacted {emoji} in #{channel}", from.username),
+            Event::Deleted { channel, seqs } => self.delete(&channel, &seqs),
+            Event::ServerShutdown { reason, .. } => {
+                self.status = format!("server shutting down: {reason}");
+            }
+            // Keep the reason on screen, the client will not come back.
+            Event::Disconnected if turned_away => self.status.push_str(", disconnected"),
+            Event::Disconnected => self.status = "connection lost, reconnecting…".to_string(),
+            Event::Reconnected(channels) => {
+                // The server may have restarted, its channels are the truth.
+                for channel in channels.iter() {
+                    if let Some(index) = self.position(&channel.name) {
+                        self.channels[index].messages = channel.messages.clone();
+                        self.channels[index].marker = None;
+                    }
+                }
+                self.merge(channels);
+                self.status = "reconnected".to_string();
+            }
+            Event::Error { code, reason } => {
+                self.turned_away = matches!(code, ErrorCode::Forbidden | ErrorCode::Unauthorized);
+                self.status = format!("{code}: {reason}");
+            }
+        }
+    }
+
+    fn message(&mut self, message: Message) {
+        let Some(index) = self.position(&message.channel) else {
+            return;
+        };
+        let channel = &mut self.channels[index];
+        channel.messages.push(message);
+        if index != self.current {
+            channel.unread += 1;
+        } else if self.scroll > 0 {
+            // Keep the scrolled back view where it is.
+            self.scroll += 1;
+        }
+    }
+
+    /// Drops deleted messages, keeping the unread count, the marker and the
+    /// scrolled back view in line.
+    fn delete(&mut self, channel: &str, seqs: &[u64]) {
+        let Some(index) = self.position(channel) else {
+            return;
+        };
+        let current = index == self.current;
+        let view = &mut self.channels[index];
+        let total = view.messages.len();
+        let mut kept = Vec::with_capacity(total);
+        for (at, message) in std::mem::take(&mut view.messages).into_iter().enumerate() {
+            if !seqs.contains(&message.seq) {
+                kept.push(message);
+                continue;
+            }
+            if at >= total.saturating_sub(view.unread) {
+                view.unread -= 1;
+            }
+            if let Some(marker) = &mut view.marker {
+                if at < *marker {
+                    *marker -= 1;
+                }
+            }
+            if current && at >= total.saturating_sub(self.scroll) {
+                self.scroll -= 1;
+            }
+        }
+        view.messages = kept;
+        if view
+            .marker
+            .is_some_and(|marker| marker >= view.messages.len())
+        {
+            view.marker = None;
+        }
+    }
+
+    pub fn outcome(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Sent => {}
+            Outcome::Joined(channel) => {
+                let name = channel.name.clone();
+                match self.position(&name) {
+                    Some(index) => {
+                        self.channels[index].messages = channel.messages;
+                        self.channels[index].joined = true;
+                    }
+                    None => self.merge(vec![channel]),
+                }
+                if let Some(index) = self.position(&name) {
+                    self.switch(index);
+                }
+                self.status = format!("joined {name}");
+            }
+            Outcome::Left(name) => {
+                if let Some(index) = self.position(&name) {
+                    self.channels[index].joined = false;
+                }
+                self.status = format!("left {name}");
+            }
+            Outcome::Created(name) => {
+                if let Some(index) = self.position(&name) {
+                    self.switch(index);
+                }
+                self.status = format!("created {name}");
+            }
+            Outcome::History { channel, messages } => {
+                let Some(index) = self.position(&channel) else {
+                    return;
+                };
+                let view = &mut self.channels[index];
+                let oldest = view.messages.first().map(|message| message.seq);
+                let older: Vec<Message> = messages
+                    .into_iter()
+                    .filter(|message| oldest.is_none_or(|oldest| message.seq < oldest))
+                    .collect();
+                if older.is_empty() {
+                    self.status = format!("no older messages in {channel}");
+                    return;
+                }
+                if let Some(marker) = &mut view.marker {
+                    *marker += older.len();
+                }
+                self.status = format!("loaded {} older messages", older.len());
+                view.messages.splice(0..0, older);
+            }
+            Outcome::Failed(reason) => self.status = reason,
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use protocol::{Compression, Encoding};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+pub struct Cli {
+    #[arg(short, long)]
+    pub user: String,
+    /// Color of the user name, as `#rrggbb`.
+    #[arg(short, long)]
+    pub color: Option<String>,
+    #[arg(short, long, default_value = "127.0.0.1:9999")]
+    pub addr: String,
+    /// Connect over TLS and verify the server against this CA certificate.
+    #[arg(long)]
+    pub tls_ca: Option<PathBuf>,
+    /// Connect over TLS and accept only the server certificate with this
+    /// SHA-256 fingerprint.
+    #[arg(long, conflicts_with = "tls_ca")]
+    pub tls_pin: Option<String>,
+    #[arg(long, default_value = "localhost")]
+    pub server_name: String,
+    /// Connect over QUIC instead of TCP, requires `--tls-ca` or `--tls-pin`.
+    #[arg(long)]
+    pub quic: bool,
+    /// Ask the server to exchange frames as `bincode`, `json` or `msgpack`.
+    #[arg(long)]
+    pub encoding: Option<Encoding>,
+    /// Ask the server to compress large frames with `zstd` or `deflate`.
+    #[arg(long)]
+    pub compression: Option<Compression>,
+}
@@ -0,0 +1,71 @@
+use std::str::FromStr;
+
+/// Shown by `/help`.
+pub const HELP: &str = "/join <channel>  /leave [channel]  /create <channel> [cover]  \
+/switch <channel>  /history [count]  /quit";
+
+/// Messages loaded by `/history` unless a count is given.
+pub const HISTORY_PAGE: u32 = 50;
+
+/// A slash command typed into the input line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Join(String),
+    /// Leaves the named channel, or the current one.
+    Leave(Option<String>),
+    Create {
+        name: String,
+        cover: Option<String>,
+    },
+    Switch(String),
+    /// Loads this many messages older than the oldest one shown.
+    History(u32),
+    Help,
+    Quit,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    /// Parses `input` starting with `/`, the error is meant for the status
+    /// line.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut words = input.split_whitespace();
+        let name = words
+            .next()
+            .and_then(|word| word.strip_prefix('/'))
+            .ok_or_else(|| "commands start with /".to_string())?;
+        let mut argument = |usage: &str| {
+            words
+                .next()
+                .map(str::to_string)
+                .ok_or_else(|| format!("usage: {usage}"))
+        };
+
+        let command = match name {
+            "join" | "j" => Command::Join(argument("/join <channel>")?),
+            "leave" | "part" => Command::Leave(argument("").ok()),
+            "create" => Command::Create {
+                name: argument("/create <channel> [cover]")?,
+                cover: argument("").ok(),
+            },
+            "switch" | "s" => Command::Switch(argument("/switch <channel>")?),
+            "history" => match argument("").ok() {
+                Some(count) => Command::History(
+                    count
+                        .parse()
+                        .map_err(|_| "usage: /history [count]".to_string())?,
+                ),
+                None => Command::History(HISTORY_PAGE),
+            },
+            "help" => Command::Help,
+            "quit" | "q" => Command::Quit,
+            _ => return Err(format!("unknown command /{name}, try /help")),
+        };
+
+        match words.next() {
+            Some(extra) => Err(format!("unexpected {extra:?}, try /help")),
+            None => Ok(command),
+        }
+    }
+}
@@ -0,0 +1,10 @@
+//! Terminal chat client. `App` holds what is on screen and turns key presses
+//! into `Action`s for the server, `ui::draw` renders it.
+
+pub mod app;
+pub mod cli;
+pub mod command;
+pub mod ui;
+
+pub use app::{Action, App, Outcome};
+pub use command::Command;
@@ -0,0 +1,102 @@
+use std::error::Error;
+
+use chat_client::{Client, Config};
+use chat_tui::{cli::Cli, ui, Action, App, Outcome};
+use clap::Parser;
+use crossterm::event::{Event as TermEvent, EventStream, KeyEventKind};
+use futures::StreamExt;
+use protocol::{tls::Trust, User};
+use ratatui::DefaultTerminal;
+use tokio::sync::mpsc;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let args = Cli::parse();
+
+    let mut config = Config::new(args.addr);
+    config.trust = match (&args.tls_ca, &args.tls_pin) {
+        (Some(ca), _) => Some(Trust::Ca(ca.to_owned())),
+        (_, Some(pin)) => Some(Trust::Pin(pin.to_owned())),
+        _ => None,
+    };
+    config.server_name = args.server_name;
+    config.quic = args.quic;
+    config.encoding = args.encoding;
+    config.compression = args.compression;
+    let client = Client::connect(config).await?;
+
+    let user = User {
+        username: args.user,
+        color: args.color,
+        avatar: None,
+        bot: false,
+    };
+    let channels = client.login(user.clone()).await?;
+    let app = App::new(user, channels);
+
+    let terminal = ratatui::init();
+    let result = run(terminal, app, client).await;
+    ratatui::restore();
+    result
+}
+
+async fn run(
+    mut terminal: DefaultTerminal,
+    mut app: App,
+    client: Client,
+) -> Result<(), Box<dyn Error>> {
+    let mut keys = EventStream::new();
+    let mut events = Box::pin(client.events());
+    // Server answers arrive out of band so typing never waits on them.
+    let (outcomes_tx, mut outcomes) = mpsc::unbounded_channel();
+
+    loop {
+        terminal.draw(|frame| ui::draw(frame, &app))?;
+
+        tokio::select! {
+            key = keys.next() => match key {
+                Some(Ok(TermEvent::Key(key))) if key.kind == KeyEventKind::Press => {
+                    match app.key(key) {
+                        Some(Action::Quit) => return Ok(()),
+                        Some(action) => {
+                            let client = client.clone();
+                            let outcomes = outcomes_tx.clone();
+                            tokio::spawn(async move {
+                                let _ = outcomes.send(perform(&client, action).await);
+                            });
+                        }
+                        None => {}
+                    }
+                }
+                // Anything else, e.g. a resize, just redraws.
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+                None => return Ok(()),
+            },
+            Some(event) = events.next() => app.event(event),
+            Some(outcome) = outcomes.recv() => app.outcome(outcome),
+        }
+    }
+}
+
+async fn perform(client: &Client, action: Action) -> Outcome {
+    let result = match action {
+        Action::Send { channel, body } => client.send(&channel, &body).await.map(|_| Outcome::Sent),
+        Action::Join(name) => client.join(&name).await.map(Outcome::Joined),
+        Action::Leave(name) => client.leave(&name).await.map(|_| Outcome::Left(name)),
+        Action::Create { name, cover } => client
+            .create_channel(&name, cover)
+            .await
+            .map(|_| Outcome::Created(name)),
+        Action::History {
+            channel,
+            before,
+            limit,
+        } => client
+            .history(&channel, before, limit)
+            .await
+            .map(|messages| Outcome::History { channel, messages }),
+        Action::Quit => Ok(Outcome::Sent),
+    };
+    result.unwrap_or_else(|e| Outcome::Failed(e.to_string()))
+}
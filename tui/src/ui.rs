@@ -0,0 +1,126 @@
+use chrono::Local;
+use protocol::Message;
+use ratatui::{
+    layout::{Constraint, Layout, Position, Rect},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, List, ListItem, Paragraph},
+    Frame,
+};
+
+use crate::app::App;
+
+const CHANNEL_LIST_WIDTH: u16 = 24;
+
+pub fn draw(frame: &mut Frame, app: &App) {
+    let [sidebar, main] =
+        Layout::horizontal([Constraint::Length(CHANNEL_LIST_WIDTH), Constraint::Min(0)])
+            .areas(frame.area());
+    let [messages, input, status] = Layout::vertical([
+        Constraint::Min(0),
+        Constraint::Length(3),
+        Constraint::Length(1),
+    ])
+    .areas(main);
+
+    draw_channels(frame, app, sidebar);
+    draw_messages(frame, app, messages);
+    draw_input(frame, app, input);
+    frame.render_widget(Paragraph::new(app.status.as_str()).dark_gray(), status);
+}
+
+fn draw_channels(frame: &mut Frame, app: &App, area: Rect) {
+    let items: Vec<ListItem> = app
+        .channels
+        .iter()
+        .enumerate()
+        .map(|(index, channel)| {
+            let mut style = Style::default();
+            if !channel.joined {
+                style = style.dark_gray();
+            }
+            if channel.unread > 0 {
+                style = style.add_modifier(Modifier::BOLD);
+            }
+            if index == app.current {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+
+            let mut line = vec![Span::raw(format!("#{}", channel.name))];
+            if channel.unread > 0 {
+                line.push(Span::raw(format!(" ({})", channel.unread)).yellow());
+            }
+            ListItem::new(Line::from(line)).style(style)
+        })
+        .collect();
+    frame.render_widget(
+        List::new(items).block(Block::bordered().title("channels")),
+        area,
+    );
+}
+
+fn draw_messages(frame: &mut Frame, app: &App, area: Rect) {
+    let Some(channel) = app.channel() else {
+        frame.render_widget(Block::bordered(), area);
+        return;
+    };
+
+    let mut lines = vec![];
+    for (index, message) in channel.messages.iter().enumerate() {
+        if channel.marker == Some(index) {
+            lines.push(Line::from("── new ──").yellow().centered());
+        }
+        lines.push(message_line(message));
+    }
+
+    // Show the newest lines that fit, `scroll` messages up from the bottom.
+    let height = area.height.saturating_sub(2) as usize;
+    let end = lines.len().saturating_sub(app.scroll);
+    let start = end.saturating_sub(height);
+    let lines = lines[start..end].to_vec();
+
+    let mut title = format!("#{}", channel.name);
+    if app.scroll > 0 {
+        title.push_str(&format!(" (+{} below)", app.scroll));
+    }
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(title)),
+        area,
+    );
+}
+
+fn message_line(message: &Message) -> Line<'_> {
+    let time = message.created.with_timezone(&Local).format("%H:%M");
+    let name_style = match message.from.color.as_deref().and_then(hex_color) {
+        Some(color) => Style::default().fg(color),
+        None => Style::default().cyan(),
+    };
+    let mut spans = vec![
+        Span::raw(format!("{time} ")).dark_gray(),
+        Span::styled(message.from.username.as_str(), name_style.bold()),
+    ];
+    if message.from.bot {
+        spans.push(Span::raw(" [bot]").magenta());
+    }
+    spans.push(Span::raw(": "));
+    spans.push(Span::raw(message.body.as_str()));
+    Line::from(spans)
+}
+
+/// Parses colors given as `#rrggbb`.
+fn hex_color(color: &str) -> Option<Color> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |at: usize| u8::from_str_radix(hex.get(at..at + 2)?, 16).ok();
+    Some(Color::Rgb(channel(0)?, channel(2)?, channel(4)?))
+}
+
+fn draw_input(frame: &mut Frame, app: &App, area: Rect) {
+    frame.render_widget(
+        Paragraph::new(app.input.as_str()).block(Block::bordered()),
+        area,
+    );
+    frame.set_cursor_position(Position::new(area.x + 1 + app.cursor as u16, area.y + 1));
+}
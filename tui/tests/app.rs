@@ -0,0 +1,293 @@
+use chat_client::Event;
+use chat_tui::{app::ChannelView, ui, Action, App, Command, Outcome};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use protocol::{Channel, ErrorCode, Message, User};
+use ratatui::{backend::TestBackend, Terminal};
+
+fn user(name: &str) -> User {
+    User {
+        username: name.to_string(),
+        color: None,
+        avatar: None,
+        bot: false,
+    }
+}
+
+fn channel(name: &str) -> Channel {
+    Channel {
+        name: name.to_string(),
+        messages: vec![],
+        cover: None,
+    }
+}
+
+fn message(channel: &str, body: &str, seq: u64) -> Message {
+    let mut message = Message::new(user("bob"), channel.to_string(), body.to_string());
+    message.seq = seq;
+    message
+}
+
+fn app() -> App {
+    App::new(
+        user("alice"),
+        vec![channel("default"), channel("another"), channel("rust")],
+    )
+}
+
+fn press(app: &mut App, code: KeyCode) -> Option<Action> {
+    app.key(KeyEvent::new(code, KeyModifiers::NONE))
+}
+
+fn type_line(app: &mut App, line: &str) -> Option<Action> {
+    for c in line.chars() {
+        assert_eq!(press(app, KeyCode::Char(c)), None);
+    }
+    press(app, KeyCode::Enter)
+}
+
+fn current(app: &App) -> &ChannelView {
+    app.channel().unwrap()
+}
+
+#[test]
+fn starts_in_the_default_channel() {
+    let app = app();
+    assert_eq!(current(&app).name, "default");
+    let names: Vec<_> = app.channels.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, ["another", "default", "rust"]);
+}
+
+#[test]
+fn lines_are_sent_to_the_current_channel() {
+    let mut app = app();
+    assert_eq!(
+        type_line(&mut app, "hello"),
+        Some(Action::Send {
+            channel: "default".to_string(),
+            body: "hello".to_string()
+        })
+    );
+    assert!(app.input.is_empty());
+    assert_eq!(press(&mut app, KeyCode::Enter), None);
+}
+
+#[test]
+fn input_is_edited_at_the_cursor() {
+    let mut app = app();
+    type_line(&mut app, "/switch rust");
+    for c in "hélo".chars() {
+        press(&mut app, KeyCode::Char(c));
+    }
+    press(&mut app, KeyCode::Left);
+    press(&mut app, KeyCode::Char('l'));
+    press(&mut app, KeyCode::Home);
+    press(&mut app, KeyCode::Delete);
+    press(&mut app, KeyCode::Char('H'));
+    press(&mut app, KeyCode::End);
+    press(&mut app, KeyCode::Backspace);
+    assert_eq!(app.input, "Héll");
+    assert_eq!(app.cursor, 4);
+}
+
+#[test]
+fn input_history_is_recalled() {
+    let mut app = app();
+    type_line(&mut app, "first");
+    type_line(&mut app, "second");
+    for c in "draft".chars() {
+        press(&mut app, KeyCode::Char(c));
+    }
+
+    press(&mut app, KeyCode::Up);
+    assert_eq!(app.input, "second");
+    press(&mut app, KeyCode::Up);
+    assert_eq!(app.input, "first");
+    press(&mut app, KeyCode::Up);
+    assert_eq!(app.input, "first");
+    press(&mut app, KeyCode::Down);
+    assert_eq!(app.input, "second");
+    press(&mut app, KeyCode::Down);
+    assert_eq!(app.input, "draft");
+}
+
+#[test]
+fn other_channels_count_unread_messages() {
+    let mut app = app();
+    app.event(Event::Message(message("rust", "one", 1)));
+    app.event(Event::Message(message("rust", "two", 2)));
+    app.event(Event::Message(message("default", "here", 3)));
+
+    let rust = app.channels.iter().find(|c| c.name == "rust").unwrap();
+    assert_eq!(rust.unread, 2);
+    assert_eq!(current(&app).unread, 0);
+
+    type_line(&mut app, "/switch rust");
+    assert_eq!(current(&app).name, "rust");
+    assert_eq!(current(&app).unread, 0);
+    // The marker sits above the first unread message until switching away.
+    assert_eq!(current(&app).marker, Some(0));
+    press(&mut app, KeyCode::Tab);
+    let rust = app.channels.iter().find(|c| c.name == "rust").unwrap();
+    assert_eq!(rust.marker, None);
+}
+
+#[test]
+fn deleted_messages_disappear_with_their_unread_count() {
+    let mut app = app();
+    for seq in 1..=4 {
+        app.event(Event::Message(message("rust", &format!("m{seq}"), seq)));
+    }
+    app.event(Event::Deleted {
+        channel: "rust".to_string(),
+        seqs: vec![1, 4],
+    });
+
+    let rust = app.channels.iter().find(|c| c.name == "rust").unwrap();
+    let bodies: Vec<_> = rust.messages.iter().map(|m| m.body.as_str()).collect();
+    assert_eq!(bodies, ["m2", "m3"]);
+    assert_eq!(rust.unread, 2);
+}
+
+#[test]
+fn tab_cycles_through_channels() {
+    let mut app = app();
+    press(&mut app, KeyCode::Tab);
+    assert_eq!(current(&app).name, "rust");
+    press(&mut app, KeyCode::Tab);
+    assert_eq!(current(&app).name, "another");
+    press(&mut app, KeyCode::BackTab);
+    assert_eq!(current(&app).name, "rust");
+}
+
+#[test]
+fn slash_commands_become_actions() {
+    let mut app = app();
+    assert_eq!(
+        type_line(&mut app, "/join rust"),
+        Some(Action::Join("rust".to_string()))
+    );
+    assert_eq!(
+        type_line(&mut app, "/leave"),
+        Some(Action::Leave("default".to_string()))
+    );
+    assert_eq!(
+        type_line(&mut app, "/create news http://example.com/cover.png"),
+        Some(Action::Create {
+            name: "news".to_string(),
+            cover: Some("http://example.com/cover.png".to_string())
+        })
+    );
+    assert_eq!(type_line(&mut app, "/quit"), Some(Action::Quit));
+
+    assert_eq!(type_line(&mut app, "/frobnicate"), None);
+    assert!(app.status.contains("unknown command"));
+    assert_eq!(type_line(&mut app, "/switch nowhere"), None);
+    assert!(app.status.contains("no channel named nowhere"));
+}
+
+#[test]
+fn commands_parse() {
+    assert_eq!("/j rust".parse(), Ok(Command::Join("rust".to_string())));
+    assert_eq!("/leave".parse(), Ok(Command::Leave(None)));
+    assert_eq!("/history 5".parse(), Ok(Command::History(5)));
+    assert_eq!("/history".parse(), Ok(Command::History(50)));
+    assert_eq!("/help".parse(), Ok(Command::Help));
+    assert!("/join".parse::<Command>().is_err());
+    assert!("/history lots".parse::<Command>().is_err());
+    assert!("/switch a b".parse::<Command>().is_err());
+    assert!("hello".parse::<Command>().is_err());
+}
+
+#[test]
+fn scrolling_past_the_oldest_message_loads_history() {
+    let mut app = app();
+    for seq in 10..15 {
+        app.event(Event::Message(message("default", "hi", seq)));
+    }
+
+    assert_eq!(press(&mut app, KeyCode::PageUp), None);
+    assert_eq!(app.scroll, 4);
+    let action = press(&mut app, KeyCode::PageUp);
+    assert_eq!(
+        action,
+        Some(Action::History {
+            channel: "default".to_string(),
+            before: Some(10),
+            limit: 50
+        })
+    );
+
+    app.outcome(Outcome::History {
+        channel: "default".to_string(),
+        messages: vec![message("default", "old", 8), message("default", "older", 9)],
+    });
+    let seqs: Vec<_> = current(&app).messages.iter().map(|m| m.seq).collect();
+    assert_eq!(seqs, [8, 9, 10, 11, 12, 13, 14]);
+
+    press(&mut app, KeyCode::PageDown);
+    assert_eq!(app.scroll, 0);
+}
+
+#[test]
+fn new_channels_are_added_and_joined() {
+    let mut app = app();
+    app.event(Event::Channels(vec![channel("default"), channel("news")]));
+    assert_eq!(current(&app).name, "default");
+
+    app.outcome(Outcome::Created("news".to_string()));
+    assert_eq!(current(&app).name, "news");
+
+    app.outcome(Outcome::Left("rust".to_string()));
+    let rust = app.channels.iter().find(|c| c.name == "rust").unwrap();
+    assert!(!rust.joined);
+
+    let mut rejoined = channel("rust");
+    rejoined.messages.push(message("rust", "while away", 1));
+    app.outcome(Outcome::Joined(rejoined));
+    assert_eq!(current(&app).name, "rust");
+    assert!(current(&app).joined);
+    assert_eq!(current(&app).messages.len(), 1);
+}
+
+#[test]
+fn kicks_are_not_mistaken_for_lost_connections() {
+    let mut app = app();
+    app.event(Event::Disconnected);
+    assert_eq!(app.status, "connection lost, reconnecting…");
+
+    app.event(Event::Error {
+        code: ErrorCode::Forbidden,
+        reason: "kicked by an administrator".to_string(),
+    });
+    app.event(Event::Disconnected);
+    assert_eq!(
+        app.status,
+        "forbidden: kicked by an administrator, disconnected"
+    );
+}
+
+#[test]
+fn screen_shows_channels_messages_and_input() {
+    let mut app = app();
+    app.event(Event::Message(message("default", "hello there", 1)));
+    app.event(Event::Message(message("rust", "unseen", 2)));
+    for c in "typing".chars() {
+        press(&mut app, KeyCode::Char(c));
+    }
+
+    let mut terminal = Terminal::new(TestBackend::new(80, 12)).unwrap();
+    terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+    let screen: String = terminal
+        .backend()
+        .buffer()
+        .content()
+        .iter()
+        .map(|cell| cell.symbol())
+        .collect();
+
+    assert!(screen.contains("#default"));
+    assert!(screen.contains("#rust (1)"));
+    assert!(screen.contains("bob: hello there"));
+    assert!(!screen.contains("unseen"));
+    assert!(screen.contains("typing"));
+}